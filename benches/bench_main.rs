@@ -3,7 +3,7 @@
 //! Run benchmarks with: `cargo bench`
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use jsonlt::{Record, Table};
+use jsonlt::{Key, Record, Table};
 
 fn bench_table_insert(c: &mut Criterion) {
     c.bench_function("table_insert_100", |b| {
@@ -18,15 +18,16 @@ fn bench_table_insert(c: &mut Criterion) {
 }
 
 fn bench_table_lookup(c: &mut Criterion) {
+    let keys: Vec<_> = (0..1000).map(|i| Key::from(format!("key-{i}"))).collect();
     let mut table = Table::new();
-    for i in 0..1000 {
-        table.insert(format!("key-{i}"), Record::new());
+    for key in &keys {
+        table.insert(key.clone(), Record::new());
     }
 
     c.bench_function("table_lookup_1000", |b| {
         b.iter(|| {
-            for i in 0..1000 {
-                black_box(table.get(&format!("key-{i}")));
+            for key in &keys {
+                black_box(table.get(key));
             }
         });
     });
@@ -61,8 +62,8 @@ fn bench_record_serialization(c: &mut Criterion) {
         }
     });
 
-    c.bench_function("record_from_value", |b| {
-        b.iter(|| black_box(Record::from_value(value.clone())));
+    c.bench_function("record_try_from_value", |b| {
+        b.iter(|| black_box(Record::try_from_value(value.clone())));
     });
 }
 
@@ -0,0 +1,226 @@
+//! Conversion of tables to Apache Arrow record batches and Parquet files.
+
+use crate::io::StreamReader;
+use crate::{Entry, Key, Record, Result, Table};
+use arrow_array::RecordBatch;
+use arrow_json::reader::{infer_json_schema_from_iterator, Decoder, ReaderBuilder};
+use arrow_schema::{Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+
+/// The default number of rows in each record batch.
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// Options for converting records to Arrow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArrowOptions {
+    /// The schema of the record batches.
+    ///
+    /// When `None`, the schema is inferred from the records with
+    /// [`infer_schema`].
+    pub schema: Option<SchemaRef>,
+
+    /// The maximum number of rows in each record batch.
+    pub batch_size: usize,
+}
+
+impl Default for ArrowOptions {
+    fn default() -> Self {
+        Self {
+            schema: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+}
+
+/// Infers an Arrow schema covering every field of the records.
+///
+/// Nested objects become struct columns and arrays become list columns.
+/// Fields are nullable, since records need not share the same fields.
+///
+/// # Errors
+///
+/// Returns an error if a record is not a JSON object or a field holds
+/// values of incompatible types.
+pub fn infer_schema<'a, I>(records: I) -> Result<Schema>
+where
+    I: IntoIterator<Item = &'a Record>,
+{
+    let values = records.into_iter().map(|record| Ok(record.value()));
+    Ok(infer_json_schema_from_iterator(values)?)
+}
+
+fn decoder(schema: SchemaRef, batch_size: usize) -> Result<Decoder> {
+    Ok(ReaderBuilder::new(schema)
+        .with_batch_size(batch_size)
+        .build_decoder()?)
+}
+
+impl Table {
+    /// Converts the table into Arrow record batches, in key order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the schema cannot be inferred or a record does
+    /// not match it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::{ArrowOptions, Record, Table};
+    /// use serde_json::json;
+    ///
+    /// let mut table = Table::new();
+    /// table.insert(1, Record::try_from_value(json!({"id": 1, "name": "alice"})).unwrap());
+    /// table.insert(2, Record::try_from_value(json!({"id": 2})).unwrap());
+    ///
+    /// let batches = table.to_record_batches(&ArrowOptions::default()).unwrap();
+    /// assert_eq!(batches[0].num_rows(), 2);
+    /// assert_eq!(batches[0].schema().field(1).name(), "name");
+    /// ```
+    pub fn to_record_batches(&self, options: &ArrowOptions) -> Result<Vec<RecordBatch>> {
+        let records: Vec<_> = self.iter().collect();
+        let schema = match &options.schema {
+            Some(schema) => Arc::clone(schema),
+            None => Arc::new(infer_schema(records.iter().map(|(_, record)| *record))?),
+        };
+
+        let batch_size = options.batch_size.max(1);
+        let mut decoder = decoder(schema, batch_size)?;
+        let mut batches = Vec::new();
+        for chunk in records.chunks(batch_size) {
+            let values: Vec<_> = chunk.iter().map(|(_, record)| record.value()).collect();
+            decoder.serialize(&values)?;
+            batches.extend(decoder.flush()?);
+        }
+        Ok(batches)
+    }
+
+    /// Writes the table as a Parquet file, in key order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the records cannot be converted or the output
+    /// cannot be written.
+    pub fn write_parquet<W: Write + Send>(&self, writer: W, options: &ArrowOptions) -> Result<()> {
+        let batches = self.to_record_batches(options)?;
+        let schema = match (&options.schema, batches.first()) {
+            (Some(schema), _) => Arc::clone(schema),
+            (None, Some(batch)) => batch.schema(),
+            (None, None) => Arc::new(Schema::empty()),
+        };
+        write_batches(writer, schema, batches.into_iter().map(Ok))
+    }
+}
+
+fn write_batches<W, I>(writer: W, schema: SchemaRef, batches: I) -> Result<()>
+where
+    W: Write + Send,
+    I: IntoIterator<Item = Result<RecordBatch>>,
+{
+    let mut writer = ArrowWriter::try_new(writer, schema, None)?;
+    for batch in batches {
+        writer.write(&batch?)?;
+    }
+    writer.close()?;
+    Ok(())
+}
+
+/// Converts the records of a JSONLT file into Arrow record batches as the
+/// file is read.
+///
+/// Created by [`StreamReader::record_batches`]. Only the current records
+/// are converted, in key order: versions superseded by later lines and
+/// records deleted by tombstones are left out. Since any later line may
+/// supersede a record, the rest of the file is read when the first batch is
+/// produced, holding the current records in memory.
+pub struct RecordBatches<R> {
+    reader: Option<StreamReader<R>>,
+    records: std::collections::btree_map::IntoValues<Key, Record>,
+    decoder: Decoder,
+    schema: SchemaRef,
+    batch_size: usize,
+}
+
+impl<R> std::fmt::Debug for RecordBatches<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecordBatches")
+            .field("schema", &self.schema)
+            .field("batch_size", &self.batch_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: BufRead> RecordBatches<R> {
+    /// Returns the schema of the record batches.
+    #[must_use]
+    pub fn schema(&self) -> SchemaRef {
+        Arc::clone(&self.schema)
+    }
+
+    fn next_batch(&mut self) -> Result<Option<RecordBatch>> {
+        if let Some(mut reader) = self.reader.take() {
+            let mut current = BTreeMap::new();
+            while let Some(entry) = reader.read_entry()? {
+                match entry.entry {
+                    Entry::Record { key, record } => {
+                        current.insert(key, record);
+                    }
+                    Entry::Tombstone { key } => {
+                        current.remove(&key);
+                    }
+                }
+            }
+            self.records = current.into_values();
+        }
+        let records: Vec<_> = self.records.by_ref().take(self.batch_size).collect();
+        let values: Vec<_> = records.iter().map(Record::value).collect();
+        self.decoder.serialize(&values)?;
+        Ok(self.decoder.flush()?)
+    }
+
+    /// Writes the remaining records as a Parquet file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line is invalid, a record does not match the
+    /// schema, or the output cannot be written.
+    pub fn write_parquet<W: Write + Send>(self, writer: W) -> Result<()> {
+        let schema = self.schema();
+        write_batches(writer, schema, self)
+    }
+}
+
+impl<R: BufRead> Iterator for RecordBatches<R> {
+    type Item = Result<RecordBatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_batch().transpose()
+    }
+}
+
+impl<R: BufRead> StreamReader<R> {
+    /// Converts the records current after the remaining lines into Arrow
+    /// record batches of up to `batch_size` rows with the given schema.
+    ///
+    /// See [`RecordBatches`] for which records are converted.
+    ///
+    /// The schema must be known up front because the file is only read
+    /// once; it can be inferred from a first pass with [`infer_schema`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the schema cannot be used to decode records.
+    pub fn record_batches(self, schema: SchemaRef, batch_size: usize) -> Result<RecordBatches<R>> {
+        let batch_size = batch_size.max(1);
+        Ok(RecordBatches {
+            reader: Some(self),
+            records: BTreeMap::new().into_values(),
+            decoder: decoder(Arc::clone(&schema), batch_size)?,
+            schema,
+            batch_size,
+        })
+    }
+}
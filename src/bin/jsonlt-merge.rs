@@ -0,0 +1,117 @@
+//! A git merge driver for JSONLT files.
+//!
+//! Merges two versions of a JSONLT file against their common ancestor key by
+//! key and writes a compacted result. To use it, register the driver in your
+//! git configuration:
+//!
+//! ```text
+//! [merge "jsonlt"]
+//!     name = JSONLT three-way merge
+//!     driver = jsonlt-merge %O %A %B
+//! ```
+//!
+//! and enable it for JSONLT files in `.gitattributes`:
+//!
+//! ```text
+//! *.jsonlt merge=jsonlt
+//! ```
+//!
+//! The result is written over the `ours` file (`%A`), as git expects. When
+//! the merge has conflicts, the conflicting keys are written as blocks of
+//! conflict markers and the driver exits with status 1. Errors, including
+//! files with no header declaring a key when `--key` is not given, exit with
+//! status 2 and leave the output untouched.
+
+use jsonlt::io::FileTable;
+use jsonlt::{merge_files, KeySpecifier, MergeOptions, Result};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str =
+    "usage: jsonlt-merge [--field-level] [--key FIELD]... BASE OURS THEIRS [OUTPUT]";
+
+struct Args {
+    options: MergeOptions,
+    key: Vec<String>,
+    paths: Vec<PathBuf>,
+}
+
+fn parse_args() -> std::result::Result<Args, String> {
+    let mut args = Args {
+        options: MergeOptions::default(),
+        key: Vec::new(),
+        paths: Vec::new(),
+    };
+    let mut raw = std::env::args_os().skip(1);
+    while let Some(arg) = raw.next() {
+        match arg.to_str() {
+            Some("--field-level") => args.options.field_level = true,
+            Some("--key") => {
+                let field = raw.next().and_then(|field| field.into_string().ok());
+                args.key.push(field.ok_or("--key requires a field name")?);
+            }
+            Some("-h" | "--help") => return Err(String::new()),
+            _ => args.paths.push(arg.into()),
+        }
+    }
+    if !(3..=4).contains(&args.paths.len()) {
+        return Err("expected BASE, OURS and THEIRS paths".into());
+    }
+    Ok(args)
+}
+
+fn open(path: &PathBuf, key: Option<&KeySpecifier>) -> Result<FileTable> {
+    key.map_or_else(
+        || FileTable::open(path),
+        |key| FileTable::open_with_key(path, key.clone()),
+    )
+}
+
+fn run(args: &Args) -> Result<bool> {
+    let key = match args.key.as_slice() {
+        [] => None,
+        [field] => Some(KeySpecifier::Field(field.clone())),
+        fields => Some(KeySpecifier::Fields(fields.to_vec())),
+    };
+    let base = open(&args.paths[0], key.as_ref())?;
+    let ours = open(&args.paths[1], key.as_ref())?;
+    let theirs = open(&args.paths[2], key.as_ref())?;
+
+    let merged = merge_files(&base, &ours, &theirs, args.options)?;
+    merged.write_to_path(args.paths.get(3).unwrap_or(&args.paths[1]))?;
+    let result = merged.result;
+
+    for conflict in &result.conflicts {
+        if conflict.fields.is_empty() {
+            eprintln!("conflict: {}", conflict.key);
+        } else {
+            eprintln!(
+                "conflict: {} ({})",
+                conflict.key,
+                conflict.fields.join(", ")
+            );
+        }
+    }
+    Ok(result.is_clean())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("jsonlt-merge: {message}");
+            }
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(err) => {
+            eprintln!("jsonlt-merge: {}", err.full_message());
+            ExitCode::from(2)
+        }
+    }
+}
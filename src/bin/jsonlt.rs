@@ -0,0 +1,557 @@
+//! The `jsonlt` command-line tool.
+//!
+//! Reads and edits JSONLT files while respecting headers and tombstones.
+//! Run `jsonlt --help` for the list of commands.
+
+use clap::{Parser, Subcommand, ValueEnum};
+use jsonlt::io::FileTable;
+use jsonlt::{
+    check_format, check_references, format, merge_files, to_canonical_string, validate_with, Entry,
+    Error, Key, KeySpecifier, MergeOptions, Record, Reference, Result, ValidateOptions,
+};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Query and edit JSON Lines Table (JSONLT) files.
+#[derive(Debug, Parser)]
+#[command(name = "jsonlt", version, about)]
+struct Cli {
+    /// Output format.
+    #[arg(long, short, global = true, value_enum, default_value_t = Format::Table)]
+    format: Format,
+
+    /// Key field for files without a header; repeat for compound keys.
+    #[arg(long = "key", short, global = true, value_name = "FIELD")]
+    key_fields: Vec<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Human-readable text.
+    Table,
+    /// JSON.
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print the record stored under a key.
+    Get {
+        /// The JSONLT file.
+        file: PathBuf,
+        /// The key; integers and arrays are parsed as JSON.
+        key: String,
+    },
+    /// Insert or replace records.
+    ///
+    /// Records are read from the arguments, or as JSON lines from standard
+    /// input when none are given. The file is created with a header if it
+    /// does not exist and `--key` is given.
+    Put {
+        /// The JSONLT file.
+        file: PathBuf,
+        /// Records as JSON objects.
+        records: Vec<String>,
+    },
+    /// Delete records by key.
+    Delete {
+        /// The JSONLT file.
+        file: PathBuf,
+        /// The keys to delete.
+        #[arg(required = true)]
+        keys: Vec<String>,
+    },
+    /// List all records in key order.
+    List {
+        /// The JSONLT file.
+        file: PathBuf,
+    },
+    /// Print the number of records.
+    Count {
+        /// The JSONLT file.
+        file: PathBuf,
+    },
+    /// Check that files are valid JSONLT.
+    ///
+    /// Lists every issue found. Exits with status 1 if any file has errors,
+    /// or has warnings and `--strict` is given.
+    ///
+    /// References between the files, which are tables named after their
+    /// file names up to the first dot, are checked once every file is
+    /// valid; each dangling reference is an error.
+    Validate {
+        /// The JSONLT files.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Check records against the schema in the header.
+        #[arg(long)]
+        schema: bool,
+        /// A reference to check, such as `orders.user_id -> users`.
+        #[arg(long = "reference", value_name = "REFERENCE")]
+        references: Vec<Reference>,
+        /// Treat warnings as errors.
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Rewrite files into canonical form, keeping every line.
+    ///
+    /// With `--check`, files are left untouched and the command exits with
+    /// status 1 if any file is not in canonical form.
+    Format {
+        /// The JSONLT files.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Only report files that are not in canonical form.
+        #[arg(long)]
+        check: bool,
+    },
+    /// Rewrite a file with only its live records.
+    Compact {
+        /// The JSONLT file.
+        file: PathBuf,
+    },
+    /// Print every version of a key, including deletions.
+    History {
+        /// The JSONLT file.
+        file: PathBuf,
+        /// The key; integers and arrays are parsed as JSON.
+        key: String,
+    },
+    /// Compare two files.
+    Diff {
+        /// The old file.
+        old: PathBuf,
+        /// The new file.
+        new: PathBuf,
+    },
+    /// Merge two files derived from a common ancestor.
+    ///
+    /// Exits with status 1 if there are conflicts, which are written as
+    /// blocks of conflict markers.
+    Merge {
+        /// The common ancestor.
+        base: PathBuf,
+        /// Our version.
+        ours: PathBuf,
+        /// Their version.
+        theirs: PathBuf,
+        /// Where to write the result; defaults to standard output.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Merge records changed on both sides field by field.
+        #[arg(long)]
+        field_level: bool,
+    },
+}
+
+/// The outcome of a command that ran without errors.
+enum Outcome {
+    Success,
+    Failure,
+}
+
+impl Cli {
+    fn key_specifier(&self) -> Option<KeySpecifier> {
+        match self.key_fields.as_slice() {
+            [] => None,
+            [field] => Some(KeySpecifier::Field(field.clone())),
+            fields => Some(KeySpecifier::Fields(fields.to_vec())),
+        }
+    }
+
+    fn open(&self, path: &Path) -> Result<FileTable> {
+        self.key_specifier().map_or_else(
+            || FileTable::open(path),
+            |key| FileTable::open_with_key(path, key),
+        )
+    }
+}
+
+/// Parses a key argument.
+///
+/// Arguments that are JSON integers, strings or arrays are parsed as JSON;
+/// anything else is taken as a string key.
+fn parse_key(arg: &str) -> Result<Key> {
+    match serde_json::from_str::<Value>(arg) {
+        Ok(Value::Array(elements)) => elements
+            .iter()
+            .map(Key::from_value)
+            .collect::<Result<Vec<_>>>()
+            .map(Key::Tuple),
+        Ok(value @ (Value::Number(_) | Value::String(_))) => Key::from_value(&value),
+        _ => Ok(Key::String(arg.to_string())),
+    }
+}
+
+fn parse_record(text: &str) -> Result<Record> {
+    Record::try_from_value(serde_json::from_str(text)?)
+}
+
+fn print_json(out: &mut impl Write, value: &Value) -> Result<()> {
+    writeln!(out, "{}", serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
+
+fn run(cli: &Cli, out: &mut impl Write) -> Result<Outcome> {
+    match &cli.command {
+        Command::Get { file, key } => get(cli, out, file, key),
+        Command::Put { file, records } => put(cli, file, records),
+        Command::Delete { file, keys } => delete(cli, file, keys),
+        Command::List { file } => list(cli, out, file),
+        Command::Count { file } => {
+            writeln!(out, "{}", cli.open(file)?.table().len())?;
+            Ok(Outcome::Success)
+        }
+        Command::Validate {
+            files,
+            schema,
+            references,
+            strict,
+        } => validate(cli, out, files, *schema, references, *strict),
+        Command::Format { files, check } => format_files(cli, out, files, *check),
+        Command::Compact { file } => {
+            cli.open(file)?.compact()?;
+            Ok(Outcome::Success)
+        }
+        Command::History { file, key } => history(cli, out, file, key),
+        Command::Diff { old, new } => diff(cli, out, old, new),
+        Command::Merge {
+            base,
+            ours,
+            theirs,
+            output,
+            field_level,
+        } => merge(
+            cli,
+            out,
+            [base, ours, theirs],
+            output.as_deref(),
+            *field_level,
+        ),
+    }
+}
+
+fn get(cli: &Cli, out: &mut impl Write, file: &Path, key: &str) -> Result<Outcome> {
+    let table = cli.open(file)?;
+    let key = parse_key(key)?;
+    let record = table
+        .table()
+        .get(&key)
+        .ok_or_else(|| Error::NotFound { key: key.clone() })?;
+    match cli.format {
+        Format::Json => writeln!(out, "{}", to_canonical_string(record.value()))?,
+        Format::Table => print_json(out, record.value())?,
+    }
+    Ok(Outcome::Success)
+}
+
+fn put(cli: &Cli, file: &Path, records: &[String]) -> Result<Outcome> {
+    let mut table = match cli.key_specifier() {
+        Some(key) if !file.exists() => FileTable::create(file, key)?,
+        _ => cli.open(file)?,
+    };
+    if records.is_empty() {
+        for line in io::stdin().lock().lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                table.put(&parse_record(&line)?)?;
+            }
+        }
+    } else {
+        for record in records {
+            table.put(&parse_record(record)?)?;
+        }
+    }
+    Ok(Outcome::Success)
+}
+
+fn delete(cli: &Cli, file: &Path, keys: &[String]) -> Result<Outcome> {
+    let mut table = cli.open(file)?;
+    let mut outcome = Outcome::Success;
+    for key in keys {
+        let key = parse_key(key)?;
+        if !table.delete(key.clone())? {
+            eprintln!("jsonlt: record not found: {key}");
+            outcome = Outcome::Failure;
+        }
+    }
+    Ok(outcome)
+}
+
+fn list(cli: &Cli, out: &mut impl Write, file: &Path) -> Result<Outcome> {
+    let table = cli.open(file)?;
+    let records: Vec<_> = table.table().iter().collect();
+    match cli.format {
+        Format::Json => {
+            let records: Vec<_> = records.iter().map(|(_, r)| r.value()).collect();
+            print_json(out, &json!(records))?;
+        }
+        Format::Table => {
+            let width = records
+                .iter()
+                .map(|(key, _)| key.to_string().chars().count())
+                .max()
+                .unwrap_or(0);
+            for (key, record) in records {
+                let key = key.to_string();
+                let record = to_canonical_string(record.value());
+                writeln!(out, "{key:<width$}  {record}")?;
+            }
+        }
+    }
+    Ok(Outcome::Success)
+}
+
+fn validate(
+    cli: &Cli,
+    out: &mut impl Write,
+    files: &[PathBuf],
+    check_schema: bool,
+    references: &[Reference],
+    strict: bool,
+) -> Result<Outcome> {
+    let options = ValidateOptions {
+        key: cli.key_specifier(),
+        check_schema,
+        schema: None,
+    };
+    let mut outcome = Outcome::Success;
+    for file in files {
+        let report = validate_with(file, &options)?;
+        if !report.is_valid() || (strict && report.warnings().next().is_some()) {
+            outcome = Outcome::Failure;
+        }
+        match cli.format {
+            Format::Json => {
+                let issues: Vec<_> = report
+                    .issues
+                    .iter()
+                    .map(|issue| {
+                        json!({
+                            "line": issue.line,
+                            "column": issue.column,
+                            "severity": issue.severity.to_string(),
+                            "message": issue.message,
+                        })
+                    })
+                    .collect();
+                let valid = report.is_valid();
+                writeln!(
+                    out,
+                    "{}",
+                    json!({"file": file, "valid": valid, "issues": issues})
+                )?;
+            }
+            Format::Table => {
+                for issue in &report.issues {
+                    writeln!(out, "{}:{issue}", file.display())?;
+                }
+                if report.issues.is_empty() {
+                    writeln!(out, "{}: ok", file.display())?;
+                }
+            }
+        }
+    }
+    if references.is_empty() || !matches!(outcome, Outcome::Success) {
+        return Ok(outcome);
+    }
+
+    let mut tables = BTreeMap::new();
+    for file in files {
+        let name = file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.split('.').next())
+            .unwrap_or_default();
+        tables.insert(name, (file, cli.open(file)?));
+    }
+    let dangling = check_references(references, |name| {
+        tables.get(name).map(|(_, table)| table.table())
+    })?;
+    for dangling in &dangling {
+        let file = tables[dangling.reference.table.as_str()].0;
+        match cli.format {
+            Format::Json => writeln!(
+                out,
+                "{}",
+                json!({
+                    "file": file,
+                    "reference": dangling.reference.to_string(),
+                    "key": dangling.key.to_value(),
+                    "value": dangling.value,
+                })
+            )?,
+            Format::Table => writeln!(out, "{}: error: {dangling}", file.display())?,
+        }
+    }
+    Ok(if dangling.is_empty() {
+        outcome
+    } else {
+        Outcome::Failure
+    })
+}
+
+fn format_files(
+    cli: &Cli,
+    out: &mut impl Write,
+    files: &[PathBuf],
+    check: bool,
+) -> Result<Outcome> {
+    let mut outcome = Outcome::Success;
+    for file in files {
+        let report = if check {
+            check_format(file)?
+        } else {
+            format(file)?
+        };
+        if check && !report.is_canonical() {
+            outcome = Outcome::Failure;
+        }
+        match cli.format {
+            Format::Json => writeln!(
+                out,
+                "{}",
+                json!({"file": file, "canonical": report.is_canonical(), "lines": report.changed})
+            )?,
+            Format::Table => {
+                if !report.is_canonical() {
+                    let verb = if check { "not canonical" } else { "formatted" };
+                    let count = report.changed.len();
+                    writeln!(out, "{}: {verb}: {count} lines", file.display())?;
+                }
+            }
+        }
+    }
+    Ok(outcome)
+}
+
+fn history(cli: &Cli, out: &mut impl Write, file: &Path, key: &str) -> Result<Outcome> {
+    let history = cli.open(file)?.history(parse_key(key)?)?;
+    match cli.format {
+        Format::Json => {
+            let versions: Vec<_> = history
+                .iter()
+                .map(|version| {
+                    json!({
+                        "line": version.line,
+                        "deleted": version.entry.is_tombstone(),
+                        "record": version.entry.record().map(Record::value),
+                    })
+                })
+                .collect();
+            print_json(out, &json!(versions))?;
+        }
+        Format::Table => {
+            for version in history {
+                match &version.entry {
+                    Entry::Record { record, .. } => {
+                        let record = to_canonical_string(record.value());
+                        writeln!(out, "{:>6}  {record}", version.line)?;
+                    }
+                    Entry::Tombstone { .. } => {
+                        writeln!(out, "{:>6}  (deleted)", version.line)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(Outcome::Success)
+}
+
+/// Compares two files, failing if they differ like `diff(1)`.
+fn diff(cli: &Cli, out: &mut impl Write, old: &Path, new: &Path) -> Result<Outcome> {
+    let diff = cli.open(old)?.table().diff(cli.open(new)?.table());
+    match cli.format {
+        Format::Json => {
+            let changed: Vec<_> = diff
+                .changed
+                .iter()
+                .map(|(key, change)| {
+                    let changes: Vec<_> = change
+                        .changes
+                        .iter()
+                        .map(|c| json!({"pointer": c.pointer, "old": c.old, "new": c.new}))
+                        .collect();
+                    json!({"key": key, "changes": changes})
+                })
+                .collect();
+            let added: Vec<_> = diff.added.values().map(Record::value).collect();
+            let removed: Vec<_> = diff.removed.values().map(Record::value).collect();
+            print_json(
+                out,
+                &json!({"added": added, "removed": removed, "changed": changed}),
+            )?;
+        }
+        Format::Table => {
+            let show =
+                |value: Option<&Value>| value.map_or_else(|| "(none)".into(), to_canonical_string);
+            for key in diff.removed.keys() {
+                writeln!(out, "- {key}")?;
+            }
+            for key in diff.added.keys() {
+                writeln!(out, "+ {key}")?;
+            }
+            for (key, change) in &diff.changed {
+                writeln!(out, "~ {key}")?;
+                for c in &change.changes {
+                    let (old, new) = (show(c.old.as_ref()), show(c.new.as_ref()));
+                    writeln!(out, "    {}: {old} -> {new}", c.pointer)?;
+                }
+            }
+        }
+    }
+    Ok(if diff.is_empty() {
+        Outcome::Success
+    } else {
+        Outcome::Failure
+    })
+}
+
+fn merge(
+    cli: &Cli,
+    out: &mut impl Write,
+    [base, ours, theirs]: [&PathBuf; 3],
+    output: Option<&Path>,
+    field_level: bool,
+) -> Result<Outcome> {
+    let (base, ours, theirs) = (cli.open(base)?, cli.open(ours)?, cli.open(theirs)?);
+    let merged = merge_files(&base, &ours, &theirs, MergeOptions { field_level })?;
+    match output {
+        Some(path) => merged.write_to_path(path)?,
+        None => merged.write(out)?,
+    }
+    let result = merged.result;
+    for conflict in &result.conflicts {
+        eprintln!("conflict: {}", conflict.key);
+    }
+    Ok(if result.is_clean() {
+        Outcome::Success
+    } else {
+        Outcome::Failure
+    })
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let mut out = BufWriter::new(io::stdout().lock());
+    let result = run(&cli, &mut out);
+    let flushed = out.flush();
+    match (result, flushed) {
+        (Ok(Outcome::Success), Ok(())) => ExitCode::SUCCESS,
+        (Ok(Outcome::Failure), Ok(())) => ExitCode::from(1),
+        (Err(err), _) => {
+            eprintln!("jsonlt: {}", err.full_message());
+            ExitCode::from(2)
+        }
+        (_, Err(err)) => {
+            eprintln!("jsonlt: {err}");
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! CSV import and export of tables.
+
+use crate::{record_key, to_canonical_string, Error, KeySpecifier, Record, Result, Table};
+use serde_json::{Map, Number, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Write};
+
+/// How the cells of a CSV column are converted to JSON values on import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColumnType {
+    /// The cell is taken as a string, exactly as written.
+    String,
+
+    /// The cell must be an integer.
+    Integer,
+
+    /// The cell must be a number.
+    Number,
+
+    /// The cell must be `true` or `false`.
+    Boolean,
+
+    /// The cell must hold a JSON value.
+    Json,
+}
+
+/// Options for CSV import and export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// The key specifier of the records.
+    ///
+    /// On import, every row must hold a valid key in these columns; when
+    /// `None`, the first column is the key. On export, the key columns are
+    /// written first.
+    pub key: Option<KeySpecifier>,
+
+    /// Column types to use on import instead of inferring them.
+    pub types: BTreeMap<String, ColumnType>,
+
+    /// The field delimiter.
+    pub delimiter: u8,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            key: None,
+            types: BTreeMap::new(),
+            delimiter: b',',
+        }
+    }
+}
+
+impl Table {
+    /// Writes the table as CSV, one row per record in key order.
+    ///
+    /// Nested objects are flattened into columns named by dotted paths, so
+    /// `{"user": {"name": "alice"}}` is written to a `user.name` column.
+    /// Arrays, empty objects and objects with a field name containing a dot
+    /// are written as JSON-encoded cells. Missing fields are written as
+    /// empty cells, and strings that would otherwise be read back as another
+    /// type, such as `"42"`, are written JSON-encoded so that
+    /// [`Table::from_csv`] restores them exactly.
+    ///
+    /// Top-level field names containing a dot cannot be told apart from
+    /// nested fields and are read back as nested objects.
+    ///
+    /// Records are written one at a time without being copied.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a record is not a JSON object or the output
+    /// cannot be written.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::{CsvOptions, Key, Record, Table};
+    /// use serde_json::json;
+    ///
+    /// let mut table = Table::new();
+    /// table.insert("a", Record::try_from_value(json!({"id": "a", "user": {"age": 30}, "tags": ["x"]})).unwrap());
+    ///
+    /// let options = CsvOptions { key: Some("id".into()), ..CsvOptions::default() };
+    /// let mut csv = Vec::new();
+    /// table.to_csv(&mut csv, &options).unwrap();
+    /// assert_eq!(csv, b"id,tags,user.age\na,\"[\"\"x\"\"]\",30\n");
+    ///
+    /// let read = Table::from_csv(csv.as_slice(), &options).unwrap();
+    /// let key = Key::from("a");
+    /// assert_eq!(read.get(&key), table.get(&key));
+    /// ```
+    pub fn to_csv<W: Write>(&self, writer: W, options: &CsvOptions) -> Result<()> {
+        let records: Vec<_> = self.iter().collect();
+
+        let mut columns = BTreeSet::new();
+        for (_, record) in &records {
+            flatten(object_of(record)?, &mut String::new(), &mut |column, _| {
+                if !columns.contains(column) {
+                    columns.insert(column.to_string());
+                }
+            });
+        }
+        let key_fields = options.key.as_ref().map_or(&[][..], KeySpecifier::fields);
+        let columns: Vec<String> = key_fields
+            .iter()
+            .cloned()
+            .chain(columns.into_iter().filter(|c| !key_fields.contains(c)))
+            .collect();
+        let positions: BTreeMap<&str, usize> = columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| (column.as_str(), i))
+            .collect();
+
+        let mut writer = ::csv::WriterBuilder::new()
+            .delimiter(options.delimiter)
+            .from_writer(writer);
+        writer.write_record(&columns)?;
+        let mut row = vec![String::new(); columns.len()];
+        for (_, record) in records {
+            row.iter_mut().for_each(String::clear);
+            flatten(
+                object_of(record)?,
+                &mut String::new(),
+                &mut |column, value| {
+                    if let Some(&i) = positions.get(column) {
+                        row[i] = encode_cell(value);
+                    }
+                },
+            );
+            writer.write_record(&row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a table from CSV with a header row.
+    ///
+    /// Columns named by dotted paths are nested into objects, and empty
+    /// cells are left out of the record. Cells of columns without a type in
+    /// [`CsvOptions::types`] are inferred: `null`, `true`, `false`, numbers
+    /// and JSON-encoded strings, arrays and objects are decoded, and
+    /// anything else is a string.
+    ///
+    /// Rows are inserted as they are read, so the CSV is never held in
+    /// memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the row if a cell does not match its column
+    /// type, a row has no valid key, or the input is not valid CSV.
+    pub fn from_csv<R: Read>(reader: R, options: &CsvOptions) -> Result<Self> {
+        let mut reader = ::csv::ReaderBuilder::new()
+            .delimiter(options.delimiter)
+            .from_reader(reader);
+        let columns: Vec<String> = reader.headers()?.iter().map(String::from).collect();
+        for (i, column) in columns.iter().enumerate() {
+            if column.is_empty() || columns[..i].contains(column) {
+                return Err(Error::invalid_format(format!(
+                    "CSV column {column:?} is empty or repeated"
+                )));
+            }
+        }
+        let key =
+            match &options.key {
+                Some(key) => key.clone(),
+                None => KeySpecifier::Field(columns.first().cloned().ok_or_else(|| {
+                    Error::invalid_format("CSV has no columns to take the key from")
+                })?),
+            };
+        let types: Vec<Option<ColumnType>> = columns
+            .iter()
+            .map(|column| options.types.get(column).copied())
+            .collect();
+
+        let mut table = Self::new();
+        let mut row = ::csv::StringRecord::new();
+        while reader.read_record(&mut row)? {
+            let line = row.position().map_or(0, ::csv::Position::line);
+            let at_row = |message: String| Error::invalid_format(format!("row {line}: {message}"));
+            let mut object = Map::new();
+            for ((column, cell), column_type) in columns.iter().zip(&row).zip(&types) {
+                if cell.is_empty() {
+                    continue;
+                }
+                let value = decode_cell(cell, *column_type)
+                    .map_err(|message| at_row(format!("column {column:?}: {message}")))?;
+                insert_path(&mut object, column, value)
+                    .map_err(|()| at_row(format!("column {column:?} conflicts with another")))?;
+            }
+            let record_key = record_key(&object, &key).map_err(|err| at_row(err.to_string()))?;
+            let record = Record::try_from_value(Value::Object(object))
+                .map_err(|err| at_row(err.to_string()))?;
+            table.insert(record_key, record);
+        }
+        Ok(table)
+    }
+}
+
+fn object_of(record: &Record) -> Result<&Map<String, Value>> {
+    record
+        .value()
+        .as_object()
+        .ok_or_else(|| Error::invalid_format("record must be a JSON object"))
+}
+
+/// Calls `visit` with the dotted path and value of every cell of a record.
+fn flatten<'a>(
+    object: &'a Map<String, Value>,
+    path: &mut String,
+    visit: &mut impl FnMut(&str, &'a Value),
+) {
+    for (field, value) in object {
+        let len = path.len();
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(field);
+        match value {
+            Value::Object(inner) if is_flattenable(inner) => flatten(inner, path, visit),
+            _ => visit(path, value),
+        }
+        path.truncate(len);
+    }
+}
+
+fn is_flattenable(object: &Map<String, Value>) -> bool {
+    !object.is_empty()
+        && object
+            .keys()
+            .all(|field| !field.is_empty() && !field.contains('.'))
+}
+
+fn encode_cell(value: &Value) -> String {
+    match value {
+        Value::String(s) if infer_cell(s) == Value::String(s.clone()) => s.clone(),
+        _ => to_canonical_string(value),
+    }
+}
+
+fn infer_cell(cell: &str) -> Value {
+    if cell.is_empty() {
+        return Value::Null;
+    }
+    let first = cell.as_bytes()[0];
+    let looks_like_json = matches!(cell, "null" | "true" | "false")
+        || matches!(first, b'"' | b'[' | b'{' | b'-' | b'0'..=b'9');
+    if looks_like_json {
+        if let Ok(value) = serde_json::from_str(cell) {
+            return value;
+        }
+    }
+    Value::String(cell.to_string())
+}
+
+fn decode_cell(cell: &str, column_type: Option<ColumnType>) -> std::result::Result<Value, String> {
+    let Some(column_type) = column_type else {
+        return Ok(infer_cell(cell));
+    };
+    match column_type {
+        ColumnType::String => Ok(Value::String(cell.to_string())),
+        ColumnType::Integer => cell
+            .parse::<i64>()
+            .map(Value::from)
+            .map_err(|_| format!("expected an integer, found {cell:?}")),
+        ColumnType::Number => cell
+            .parse::<f64>()
+            .ok()
+            .and_then(|n| {
+                cell.parse::<i64>()
+                    .map_or_else(|_| Number::from_f64(n), |i| Some(i.into()))
+            })
+            .map(Value::Number)
+            .ok_or_else(|| format!("expected a number, found {cell:?}")),
+        ColumnType::Boolean => match cell {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Err(format!("expected true or false, found {cell:?}")),
+        },
+        ColumnType::Json => {
+            serde_json::from_str(cell).map_err(|err| format!("invalid JSON cell: {err}"))
+        }
+    }
+}
+
+/// Inserts a value at a dotted path, creating intermediate objects.
+fn insert_path(
+    object: &mut Map<String, Value>,
+    path: &str,
+    value: Value,
+) -> std::result::Result<(), ()> {
+    let mut object = object;
+    let mut segments = path.split('.').peekable();
+    while let Some(segment) = segments.next() {
+        if segments.peek().is_none() {
+            if object.contains_key(segment) {
+                return Err(());
+            }
+            object.insert(segment.to_string(), value);
+            return Ok(());
+        }
+        let Value::Object(inner) = object
+            .entry(segment)
+            .or_insert_with(|| Value::Object(Map::new()))
+        else {
+            return Err(());
+        };
+        object = inner;
+    }
+    Ok(())
+}
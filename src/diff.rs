@@ -0,0 +1,180 @@
+//! Structural differences between JSONLT tables.
+
+use crate::{Key, Operations, Record, Table};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// A change to a single value inside a record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    /// A JSON pointer to the changed value.
+    pub pointer: String,
+
+    /// The previous value, or `None` if the field was added.
+    pub old: Option<Value>,
+
+    /// The new value, or `None` if the field was removed.
+    pub new: Option<Value>,
+}
+
+/// The differences between two versions of a record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordDiff {
+    /// The record before the change.
+    pub old: Record,
+
+    /// The record after the change.
+    pub new: Record,
+
+    /// The changed values, in field order.
+    ///
+    /// Objects are compared field by field; arrays and scalars are compared
+    /// as a whole.
+    pub changes: Vec<FieldChange>,
+}
+
+/// The differences between two tables.
+///
+/// Created by [`Table::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableDiff {
+    /// Records only present in the new table.
+    pub added: BTreeMap<Key, Record>,
+
+    /// Records only present in the old table.
+    pub removed: BTreeMap<Key, Record>,
+
+    /// Records present in both tables with different contents.
+    pub changed: BTreeMap<Key, RecordDiff>,
+}
+
+impl TableDiff {
+    /// Returns `true` if the tables are identical.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Converts the diff into the operations transforming the old table into
+    /// the new one.
+    ///
+    /// Operations are ordered by key within removals, insertions and
+    /// updates, in that order.
+    #[must_use]
+    pub fn into_operations(self) -> Vec<Operations> {
+        let removed = self
+            .removed
+            .into_keys()
+            .map(|key| Operations::Delete { key });
+        let added = self
+            .added
+            .into_iter()
+            .map(|(key, record)| Operations::Insert { key, record });
+        let changed = self
+            .changed
+            .into_iter()
+            .map(|(key, diff)| Operations::Update {
+                key,
+                record: diff.new,
+            });
+        removed.chain(added).chain(changed).collect()
+    }
+}
+
+impl Table {
+    /// Compares this table with `other`.
+    ///
+    /// The returned diff describes how to go from this table to `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::{Key, Record, Table};
+    /// use serde_json::json;
+    ///
+    /// let mut old = Table::new();
+    /// old.insert("a", Record::try_from_value(json!({"id": "a", "n": 1})).unwrap());
+    ///
+    /// let mut new = Table::new();
+    /// new.insert("a", Record::try_from_value(json!({"id": "a", "n": 2})).unwrap());
+    ///
+    /// let diff = old.diff(&new);
+    /// assert_eq!(diff.changed[&"a".into()].changes[0].pointer, "/n");
+    ///
+    /// for op in diff.into_operations() {
+    ///     op.apply(&mut old).unwrap();
+    /// }
+    /// let key = Key::from("a");
+    /// assert_eq!(old.get(&key), new.get(&key));
+    /// ```
+    #[must_use]
+    pub fn diff(&self, other: &Self) -> TableDiff {
+        let mut diff = TableDiff::default();
+        for (key, old) in self.iter() {
+            match other.get(key) {
+                None => {
+                    diff.removed.insert(key.clone(), old.clone());
+                }
+                Some(new) if new != old => {
+                    let mut changes = Vec::new();
+                    diff_values(
+                        Some(old.value()),
+                        Some(new.value()),
+                        &mut String::new(),
+                        &mut changes,
+                    );
+                    diff.changed.insert(
+                        key.clone(),
+                        RecordDiff {
+                            old: old.clone(),
+                            new: new.clone(),
+                            changes,
+                        },
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+        for (key, new) in other.iter() {
+            if !self.contains_key(key) {
+                diff.added.insert(key.clone(), new.clone());
+            }
+        }
+        diff
+    }
+}
+
+/// Appends a field name to a JSON pointer, escaping it as required by
+/// RFC 6901.
+pub fn push_segment(pointer: &mut String, field: &str) {
+    pointer.push('/');
+    pointer.push_str(&field.replace('~', "~0").replace('/', "~1"));
+}
+
+fn diff_values(
+    old: Option<&Value>,
+    new: Option<&Value>,
+    pointer: &mut String,
+    changes: &mut Vec<FieldChange>,
+) {
+    if old == new {
+        return;
+    }
+    let (Some(Value::Object(old)), Some(Value::Object(new))) = (old, new) else {
+        changes.push(FieldChange {
+            pointer: pointer.clone(),
+            old: old.cloned(),
+            new: new.cloned(),
+        });
+        return;
+    };
+    let mut fields: Vec<&String> = old.keys().chain(new.keys()).collect();
+    fields.sort_unstable();
+    fields.dedup();
+    for field in fields {
+        let len = pointer.len();
+        push_segment(pointer, field);
+        diff_values(old.get(field), new.get(field), pointer, changes);
+        pointer.truncate(len);
+    }
+}
@@ -1,70 +1,357 @@
 //! Error types for JSONLT operations.
 
-use std::fmt;
+use crate::{DanglingReference, Key, KeySpecifier};
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use core::fmt;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
 
 /// A specialized Result type for JSONLT operations.
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
 
 /// Error type for JSONLT operations.
-#[derive(Debug)]
+///
+/// Use [`Error::kind`] to tell errors apart without matching on their
+/// fields; it looks through any [`Error::Context`] added along the way.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
     /// An I/O error occurred.
-    Io(std::io::Error),
+    #[cfg(feature = "std")]
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
 
     /// A JSON parsing error occurred.
-    #[cfg(feature = "serde")]
-    Json(serde_json::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] crate::json::Error),
+
+    /// A CSV reading or writing error occurred.
+    #[cfg(feature = "csv")]
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+
+    /// An Arrow conversion error occurred.
+    #[cfg(feature = "arrow")]
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] arrow_schema::ArrowError),
+
+    /// A Parquet writing error occurred.
+    #[cfg(feature = "arrow")]
+    #[error("Parquet error: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+
+    /// A SQLite error occurred.
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
 
     /// A record with the specified key was not found.
-    NotFound(String),
+    #[error("record not found: {key}")]
+    NotFound {
+        /// The key that was looked up.
+        key: Key,
+    },
 
     /// A record with the specified key already exists.
-    AlreadyExists(String),
+    #[error("record already exists: {key}")]
+    AlreadyExists {
+        /// The key that was inserted.
+        key: Key,
+    },
+
+    /// A database has no table with the specified name.
+    #[error("table not found: {name}")]
+    TableNotFound {
+        /// The name that was looked up.
+        name: String,
+    },
+
+    /// A record references a key its target table does not have, or
+    /// would after a delete.
+    #[error("dangling reference: {0}")]
+    DanglingReference(Box<DanglingReference>),
 
     /// The record format is invalid.
-    InvalidFormat(String),
+    #[error("invalid format: {}", Located(message, *line, *offset))]
+    InvalidFormat {
+        /// What is wrong.
+        message: String,
+
+        /// The one-based line the problem was found on, if known.
+        line: Option<u64>,
+
+        /// The byte offset of the start of that line, if known.
+        offset: Option<u64>,
+    },
 
     /// The key format is invalid.
-    InvalidKey(String),
+    #[error("invalid key: {}", Located(message, *line, *offset))]
+    InvalidKey {
+        /// What is wrong.
+        message: String,
+
+        /// The one-based line the problem was found on, if known.
+        line: Option<u64>,
+
+        /// The byte offset of the start of that line, if known.
+        offset: Option<u64>,
+    },
+
+    /// A key specifier differs from the one required.
+    #[error("key specifier mismatch: expected {expected}, found {found}")]
+    KeyMismatch {
+        /// The key specifier required, such as that of a table or writer.
+        expected: KeySpecifier,
+
+        /// The key specifier found, such as that of a header.
+        found: KeySpecifier,
+    },
+
+    /// An operation on a file failed.
+    #[cfg(feature = "std")]
+    #[error("failed to {operation} {}", path.display())]
+    Context {
+        /// The operation that failed, such as `"open"`.
+        operation: &'static str,
+
+        /// The file the operation was on.
+        path: PathBuf,
+
+        /// The error that made the operation fail.
+        source: Box<Self>,
+    },
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+/// The kind of an [`Error`], for matching without its fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// See [`Error::Io`].
+    Io,
+    /// See [`Error::Json`].
+    Json,
+    /// See `Error::Csv`.
+    Csv,
+    /// See `Error::Arrow`.
+    Arrow,
+    /// See `Error::Parquet`.
+    Parquet,
+    /// See `Error::Sqlite`.
+    Sqlite,
+    /// See [`Error::NotFound`].
+    NotFound,
+    /// See [`Error::AlreadyExists`].
+    AlreadyExists,
+    /// See [`Error::TableNotFound`].
+    TableNotFound,
+    /// See [`Error::DanglingReference`].
+    DanglingReference,
+    /// See [`Error::InvalidFormat`].
+    InvalidFormat,
+    /// See [`Error::InvalidKey`].
+    InvalidKey,
+    /// See [`Error::KeyMismatch`].
+    KeyMismatch,
+}
+
+impl Error {
+    /// Returns the kind of the error, looking through any context.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::{ErrorKind, Operations, Table};
+    ///
+    /// let mut table = Table::new();
+    /// let err = Operations::Delete { key: "a".into() }.apply(&mut table).unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::NotFound);
+    /// ```
+    #[must_use]
+    #[allow(clippy::missing_const_for_fn)] // Not const with std, which looks into boxed context
+    pub fn kind(&self) -> ErrorKind {
         match self {
-            Self::Io(err) => write!(f, "I/O error: {err}"),
-            #[cfg(feature = "serde")]
-            Self::Json(err) => write!(f, "JSON error: {err}"),
-            Self::NotFound(key) => write!(f, "record not found: {key}"),
-            Self::AlreadyExists(key) => write!(f, "record already exists: {key}"),
-            Self::InvalidFormat(msg) => write!(f, "invalid format: {msg}"),
-            Self::InvalidKey(msg) => write!(f, "invalid key: {msg}"),
+            #[cfg(feature = "std")]
+            Self::Io(_) => ErrorKind::Io,
+            Self::Json(_) => ErrorKind::Json,
+            #[cfg(feature = "csv")]
+            Self::Csv(_) => ErrorKind::Csv,
+            #[cfg(feature = "arrow")]
+            Self::Arrow(_) => ErrorKind::Arrow,
+            #[cfg(feature = "arrow")]
+            Self::Parquet(_) => ErrorKind::Parquet,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(_) => ErrorKind::Sqlite,
+            Self::NotFound { .. } => ErrorKind::NotFound,
+            Self::AlreadyExists { .. } => ErrorKind::AlreadyExists,
+            Self::TableNotFound { .. } => ErrorKind::TableNotFound,
+            Self::DanglingReference(_) => ErrorKind::DanglingReference,
+            Self::InvalidFormat { .. } => ErrorKind::InvalidFormat,
+            Self::InvalidKey { .. } => ErrorKind::InvalidKey,
+            Self::KeyMismatch { .. } => ErrorKind::KeyMismatch,
+            #[cfg(feature = "std")]
+            Self::Context { source, .. } => source.kind(),
+        }
+    }
+
+    /// Returns the line the error was found on, looking through any
+    /// context.
+    #[must_use]
+    #[allow(clippy::missing_const_for_fn)] // Not const with std, which looks into boxed context
+    pub fn line(&self) -> Option<u64> {
+        match self {
+            Self::InvalidFormat { line, .. } | Self::InvalidKey { line, .. } => *line,
+            #[cfg(feature = "std")]
+            Self::Context { source, .. } => source.line(),
+            _ => None,
         }
     }
-}
 
-impl std::error::Error for Error {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    /// Returns the file the error concerns, if it was given one with
+    /// [`Error::context`].
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    #[must_use]
+    pub fn path(&self) -> Option<&Path> {
         match self {
-            Self::Io(err) => Some(err),
-            #[cfg(feature = "serde")]
-            Self::Json(err) => Some(err),
-            Self::NotFound(_)
-            | Self::AlreadyExists(_)
-            | Self::InvalidFormat(_)
-            | Self::InvalidKey(_) => None,
+            Self::Context { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Returns the message of the error followed by that of the error it
+    /// wraps, if it was given context with [`Error::context`].
+    ///
+    /// The message of an error with context only names the operation and
+    /// file, leaving the cause to [`source`](core::error::Error::source).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::Error;
+    ///
+    /// let err = Error::invalid_format("line must be a JSON object");
+    /// # #[cfg(feature = "std")]
+    /// let err = err.context("read", "users.jsonlt");
+    /// # #[cfg(feature = "std")]
+    /// assert_eq!(err.to_string(), "failed to read users.jsonlt");
+    /// # #[cfg(feature = "std")]
+    /// assert_eq!(
+    ///     err.full_message(),
+    ///     "failed to read users.jsonlt: invalid format: line must be a JSON object"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn full_message(&self) -> String {
+        match self {
+            #[cfg(feature = "std")]
+            Self::Context { source, .. } => alloc::format!("{self}: {}", source.full_message()),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Wraps the error with the operation and file it occurred in.
+    ///
+    /// An error that already has context for the same file is returned
+    /// unchanged, so only the innermost operation on a file is named.
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    #[must_use]
+    pub fn context(self, operation: &'static str, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        if self.path() == Some(&path) {
+            return self;
+        }
+        Self::Context {
+            operation,
+            path,
+            source: Box::new(self),
+        }
+    }
+
+    /// Creates an [`Error::InvalidFormat`] with no location.
+    #[must_use]
+    pub fn invalid_format(message: impl Into<String>) -> Self {
+        Self::InvalidFormat {
+            message: message.into(),
+            line: None,
+            offset: None,
+        }
+    }
+
+    /// Creates an [`Error::InvalidKey`] with no location.
+    #[must_use]
+    pub fn invalid_key(message: impl Into<String>) -> Self {
+        Self::InvalidKey {
+            message: message.into(),
+            line: None,
+            offset: None,
+        }
+    }
+
+    /// Records the line, and optionally the byte offset, an error was found
+    /// at, unless the error already has them.
+    ///
+    /// JSON errors become [`Error::InvalidFormat`] errors, and other errors
+    /// are returned unchanged.
+    pub(crate) fn at(self, at_line: Option<u64>, at_offset: Option<u64>) -> Self {
+        match self {
+            Self::InvalidFormat {
+                message,
+                line,
+                offset,
+            } => Self::InvalidFormat {
+                message,
+                line: line.or(at_line),
+                offset: offset.or(at_offset),
+            },
+            Self::InvalidKey {
+                message,
+                line,
+                offset,
+            } => Self::InvalidKey {
+                message,
+                line: line.or(at_line),
+                offset: offset.or(at_offset),
+            },
+            Self::Json(err) => Self::InvalidFormat {
+                message: err.to_string(),
+                line: at_line,
+                offset: at_offset,
+            },
+            other => other,
         }
     }
 }
 
-impl From<std::io::Error> for Error {
-    fn from(err: std::io::Error) -> Self {
-        Self::Io(err)
+/// Adds file context to the error of a result.
+#[cfg(feature = "std")]
+pub trait ResultExt<T> {
+    /// Wraps an error with [`Error::context`].
+    fn context(self, operation: &'static str, path: &Path) -> Result<T>;
+}
+
+#[cfg(feature = "std")]
+impl<T, E: Into<Error>> ResultExt<T> for core::result::Result<T, E> {
+    fn context(self, operation: &'static str, path: &Path) -> Result<T> {
+        self.map_err(|err| err.into().context(operation, path))
     }
 }
 
-#[cfg(feature = "serde")]
-impl From<serde_json::Error> for Error {
-    fn from(err: serde_json::Error) -> Self {
-        Self::Json(err)
+/// Formats an error message after the line it was found on and before the
+/// byte offset of that line.
+struct Located<'a>(&'a str, Option<u64>, Option<u64>);
+
+impl fmt::Display for Located<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self(message, line, offset) = self;
+        if let Some(line) = line {
+            write!(f, "line {line}: ")?;
+        }
+        f.write_str(message)?;
+        if let Some(offset) = offset {
+            write!(f, " (at byte {offset})")?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,127 @@
+//! Change notifications for JSONLT tables.
+
+use crate::{Key, Record};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::sync::mpsc;
+
+/// The kind of change applied to a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    /// A record was added under a key that was not present before.
+    Insert,
+
+    /// An existing record was replaced.
+    Update,
+
+    /// A record was removed.
+    Delete,
+}
+
+/// A change applied to a table.
+///
+/// Events are delivered to every subscriber after the table has been
+/// modified, so subscribers observe the table in its new state.
+#[derive(Debug, Clone)]
+pub struct ChangeEvent {
+    /// The kind of change.
+    pub kind: ChangeKind,
+
+    /// The key of the affected record.
+    pub key: Key,
+
+    /// The record before the change, if the key was present.
+    pub old: Option<Record>,
+
+    /// The record after the change, if the key is still present.
+    pub new: Option<Record>,
+}
+
+/// Identifies a subscription registered on a table.
+///
+/// Pass it to [`Table::unsubscribe`](crate::Table::unsubscribe) to stop
+/// receiving events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+/// A subscriber receiving change events.
+enum Subscriber {
+    Callback(Arc<dyn Fn(&ChangeEvent) + Send + Sync>),
+    #[cfg(feature = "std")]
+    Channel(mpsc::Sender<ChangeEvent>),
+}
+
+/// The set of subscribers registered on a table.
+///
+/// Subscribers are tied to a single table instance: cloning a table yields a
+/// table without subscribers.
+#[derive(Default)]
+pub struct Observers {
+    next_id: u64,
+    subscribers: Vec<(SubscriptionId, Subscriber)>,
+}
+
+impl Observers {
+    /// Returns `true` if no subscriber is registered.
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    /// Registers a callback subscriber.
+    pub fn add_callback(
+        &mut self,
+        callback: Arc<dyn Fn(&ChangeEvent) + Send + Sync>,
+    ) -> SubscriptionId {
+        self.add(Subscriber::Callback(callback))
+    }
+
+    /// Registers a channel subscriber.
+    #[cfg(feature = "std")]
+    pub fn add_channel(&mut self, sender: mpsc::Sender<ChangeEvent>) -> SubscriptionId {
+        self.add(Subscriber::Channel(sender))
+    }
+
+    fn add(&mut self, subscriber: Subscriber) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscribers.push((id, subscriber));
+        id
+    }
+
+    /// Removes a subscriber, returning `true` if it was registered.
+    pub fn remove(&mut self, id: SubscriptionId) -> bool {
+        let before = self.subscribers.len();
+        self.subscribers.retain(|(existing, _)| *existing != id);
+        self.subscribers.len() != before
+    }
+
+    /// Delivers an event to every subscriber.
+    ///
+    /// Channel subscribers whose receiver has been dropped are removed.
+    pub fn notify(&mut self, event: &ChangeEvent) {
+        self.subscribers.retain(|(_, subscriber)| match subscriber {
+            Subscriber::Callback(callback) => {
+                callback(event);
+                true
+            }
+            #[cfg(feature = "std")]
+            Subscriber::Channel(sender) => sender.send(event.clone()).is_ok(),
+        });
+    }
+}
+
+impl Clone for Observers {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observers")
+            .field("subscribers", &self.subscribers.len())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,150 @@
+//! Rewriting JSONLT files into canonical form.
+
+use crate::error::ResultExt;
+use crate::io::{replace_file, Compression, Decoder, Durability, Encoder};
+use crate::{to_canonical_string, Error, Result};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// The outcome of formatting a JSONLT file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatReport {
+    /// The number of lines read.
+    pub lines: u64,
+
+    /// The one-based numbers of the lines that were not in canonical form.
+    pub changed: Vec<u64>,
+}
+
+impl FormatReport {
+    /// Returns `true` if every line was already in canonical form.
+    #[must_use]
+    pub fn is_canonical(&self) -> bool {
+        self.changed.is_empty()
+    }
+}
+
+/// Rewrites a JSONLT file into canonical form.
+///
+/// Every line keeps its position, so the history of the file is preserved;
+/// only member order, whitespace and escapes change. A byte order mark and
+/// carriage returns are removed and a final newline is added. The file is
+/// only replaced if a line changed, by writing a new file next to it and
+/// renaming it over the original. The new file keeps the permissions of the
+/// original, and is synced before the rename and the directory after it.
+///
+/// Files ending in `.gz` or `.zst` are decompressed to be read and the new
+/// file is compressed the same way; see [`Compression::from_path`].
+///
+/// # Errors
+///
+/// Returns an error if a line is not a JSON object or the file cannot be
+/// read or written. The file is left untouched on error.
+pub fn format<P: AsRef<Path>>(path: P) -> Result<FormatReport> {
+    format_with(path, Durability::default())
+}
+
+/// Rewrites a JSONLT file into canonical form, syncing the new file unless
+/// `durability` is [`Durability::None`].
+///
+/// See [`format()`].
+///
+/// # Errors
+///
+/// Returns an error if a line is not a JSON object or the file cannot be
+/// read or written. The file is left untouched on error.
+pub fn format_with<P: AsRef<Path>>(path: P, durability: Durability) -> Result<FormatReport> {
+    let path = path.as_ref();
+    let report = check_format(path)?;
+    if report.is_canonical() {
+        return Ok(report);
+    }
+    let compression = Compression::from_path(path);
+    let sync = durability != Durability::None;
+    replace_file(path, ".format", sync, |file| {
+        let mut writer = BufWriter::new(Encoder::new(file, compression)?);
+        format_reader(open(path)?, &mut writer)?;
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .finish()
+    })
+    .context("format", path)?;
+    Ok(report)
+}
+
+/// Checks whether a JSONLT file is in canonical form without changing it.
+///
+/// Files ending in `.gz` or `.zst` are decompressed as they are read.
+///
+/// # Errors
+///
+/// Returns an error if a line is not a JSON object or the file cannot be
+/// read.
+pub fn check_format<P: AsRef<Path>>(path: P) -> Result<FormatReport> {
+    let path = path.as_ref();
+    open(path)
+        .and_then(|reader| format_reader(reader, io::sink()))
+        .context("read", path)
+}
+
+/// Opens a file for reading, decompressing it as its extension says.
+fn open(path: &Path) -> Result<BufReader<Decoder<File>>> {
+    let decoder = Decoder::new(File::open(path)?, Compression::from_path(path))?;
+    Ok(BufReader::new(decoder))
+}
+
+/// Writes the lines read from `reader` to `writer` in canonical form.
+///
+/// # Errors
+///
+/// Returns an error naming the line number if a line is not a JSON object,
+/// or if reading or writing fails.
+///
+/// # Examples
+///
+/// ```rust
+/// use jsonlt::format_reader;
+///
+/// let mut out = Vec::new();
+/// let report = format_reader("{ \"b\": 1, \"a\": 2 }\n{\"a\":3}".as_bytes(), &mut out).unwrap();
+///
+/// assert_eq!(out, b"{\"a\":2,\"b\":1}\n{\"a\":3}\n");
+/// assert_eq!(report.changed, vec![1, 2]);
+/// ```
+pub fn format_reader<R: BufRead, W: Write>(mut reader: R, mut writer: W) -> Result<FormatReport> {
+    let mut report = FormatReport::default();
+    let mut buffer = Vec::new();
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            break;
+        }
+        report.lines += 1;
+        let line = report.lines;
+        let text = std::str::from_utf8(&buffer)
+            .map_err(|_| Error::invalid_format("invalid UTF-8").at(Some(line), None))?;
+        let original = text.strip_suffix('\n').unwrap_or(text);
+        let trimmed = original.strip_suffix('\r').unwrap_or(original);
+        let trimmed = if line == 1 {
+            trimmed.strip_prefix('\u{feff}').unwrap_or(trimmed)
+        } else {
+            trimmed
+        };
+
+        let value: Value =
+            serde_json::from_str(trimmed).map_err(|err| Error::from(err).at(Some(line), None))?;
+        if !value.is_object() {
+            return Err(Error::invalid_format("line must be a JSON object").at(Some(line), None));
+        }
+        let canonical = to_canonical_string(&value);
+        if canonical != original || original.len() == text.len() {
+            report.changed.push(line);
+        }
+        writer.write_all(canonical.as_bytes())?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(report)
+}
@@ -0,0 +1,140 @@
+//! The optional header line of a JSONLT file.
+
+use crate::json::{Map, Value};
+use crate::{Error, KeySpecifier, Result};
+use alloc::format;
+use alloc::string::String;
+
+/// The field that marks a line as a header.
+pub const HEADER_FIELD: &str = "$jsonlt";
+
+/// The version of the JSONLT format written by this crate.
+pub const VERSION: u64 = 1;
+
+/// The header of a JSONLT file.
+///
+/// A header is a first line of the form `{"$jsonlt": {...}}` describing the
+/// format version, the key specifier and optional schema and metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// The format version.
+    pub version: u64,
+
+    /// The key specifier for the records in the file.
+    pub key: Option<KeySpecifier>,
+
+    /// A URL referencing a JSON Schema for the records.
+    pub schema_url: Option<String>,
+
+    /// An inline JSON Schema for the records.
+    pub schema: Option<Value>,
+
+    /// Arbitrary user metadata.
+    pub meta: Option<Value>,
+}
+
+impl Header {
+    /// Creates a header for the current format version with a key specifier.
+    #[must_use]
+    pub const fn new(key: KeySpecifier) -> Self {
+        Self {
+            version: VERSION,
+            key: Some(key),
+            schema_url: None,
+            schema: None,
+            meta: None,
+        }
+    }
+
+    /// Returns `true` if the JSON object is a header line.
+    #[must_use]
+    pub fn is_header(object: &Map<String, Value>) -> bool {
+        object.contains_key(HEADER_FIELD)
+    }
+
+    /// Parses a header from a header line object.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object is not a well-formed header or uses an
+    /// unsupported version.
+    pub fn from_object(object: &Map<String, Value>) -> Result<Self> {
+        if object.len() != 1 {
+            return Err(Error::invalid_format(format!(
+                "header must contain only the {HEADER_FIELD:?} field"
+            )));
+        }
+        let Some(Value::Object(fields)) = object.get(HEADER_FIELD) else {
+            return Err(Error::invalid_format(format!(
+                "header field {HEADER_FIELD:?} must be an object"
+            )));
+        };
+
+        let version = fields
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| Error::invalid_format("header version must be an integer"))?;
+        if version != VERSION {
+            return Err(Error::invalid_format(format!(
+                "unsupported JSONLT version {version}"
+            )));
+        }
+
+        let key = match fields.get("key") {
+            None => None,
+            Some(value) => {
+                let key = KeySpecifier::from_value(value).map_err(|_| {
+                    Error::invalid_format("header key must be a string or an array of strings")
+                })?;
+                key.validate()?;
+                Some(key)
+            }
+        };
+
+        let schema_url = match fields.get("$schema") {
+            None => None,
+            Some(Value::String(url)) => Some(url.clone()),
+            Some(_) => return Err(Error::invalid_format("header $schema must be a string")),
+        };
+
+        let schema = fields.get("schema").cloned();
+        if schema.as_ref().is_some_and(|schema| !schema.is_object()) {
+            return Err(Error::invalid_format("header schema must be an object"));
+        }
+        if schema.is_some() && schema_url.is_some() {
+            return Err(Error::invalid_format(
+                "header must not contain both $schema and schema",
+            ));
+        }
+
+        Ok(Self {
+            version,
+            key,
+            schema_url,
+            schema,
+            meta: fields.get("meta").cloned(),
+        })
+    }
+
+    /// Returns the header as a header line object.
+    #[must_use]
+    pub fn to_value(&self) -> Value {
+        let mut fields = Map::new();
+        fields.insert("version".into(), Value::from(self.version));
+        if let Some(key) = &self.key {
+            fields.insert("key".into(), key.to_value());
+        }
+        if let Some(url) = &self.schema_url {
+            fields.insert("$schema".into(), Value::from(url.as_str()));
+        }
+        if let Some(schema) = &self.schema {
+            fields.insert("schema".into(), schema.clone());
+        }
+        if let Some(meta) = &self.meta {
+            fields.insert("meta".into(), meta.clone());
+        }
+        let mut object = Map::new();
+        object.insert(HEADER_FIELD.into(), Value::Object(fields));
+        Value::Object(object)
+    }
+}
@@ -0,0 +1,450 @@
+//! Async I/O operations for JSONLT tables using tokio.
+
+use super::compress::{AsyncDecoder, AsyncEncoder};
+use super::stream::{header_line, record_line, table_line};
+use super::Compression;
+use crate::error::ResultExt;
+use crate::line::LineParser;
+use crate::{
+    tombstone_line, Entry, Error, Header, Key, KeySpecifier, LogEntry, Record, Result, Table,
+};
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+
+/// Reads a JSONLT table from a file asynchronously.
+///
+/// The key specifier is taken from the file header. Files ending in `.gz`
+/// or `.zst` are decompressed as they are read; see
+/// [`Compression::from_path`].
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read, is not valid JSONLT, or has
+/// records but no header declaring a key.
+pub async fn read_table<P: AsRef<Path>>(path: P) -> Result<Table> {
+    let path = path.as_ref();
+    let compression = Compression::from_path(path);
+    let file = File::open(path).await.context("open", path)?;
+    let mut reader =
+        AsyncStreamReader::with_compression(file, compression).context("open", path)?;
+    let mut table = Table::new();
+    async {
+        while let Some(LogEntry { entry, .. }) = reader.read_entry().await? {
+            match entry {
+                Entry::Record { key, record } => table.insert(key, record),
+                Entry::Tombstone { key } => {
+                    table.remove(&key);
+                }
+            }
+        }
+        Ok::<_, Error>(table)
+    }
+    .await
+    .context("read", path)
+}
+
+/// Writes a table to a file asynchronously, as a compacted JSONLT file with
+/// a header declaring `key`, truncating any existing file.
+///
+/// Records are written in key order. Files ending in `.gz` or `.zst` are
+/// compressed as they are written; see [`Compression::from_path`].
+///
+/// Since a [`Table`] does not carry a key specifier, `key` is required.
+/// This changed the signature of the earlier placeholder, which took only
+/// the path and table and wrote nothing.
+///
+/// # Errors
+///
+/// Returns an error if a record does not match `key`, is stored under a key
+/// other than its own, or the file cannot be written.
+pub async fn write_table<P: AsRef<Path>>(
+    path: P,
+    table: &Table,
+    key: impl Into<KeySpecifier>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let key = key.into();
+    let compression = Compression::from_path(path);
+    let file = File::create(path).await.context("create", path)?;
+    let mut writer = AsyncStreamWriter::with_compression(file, key.clone(), compression)
+        .context("create", path)?;
+    async {
+        writer.write_header(&Header::new(key)).await?;
+        writer.write_table(table).await?;
+        writer.shutdown().await
+    }
+    .await
+    .context("write", path)
+}
+
+/// A reader for JSONLT files.
+pub struct TableReader<R> {
+    reader: BufReader<AsyncDecoder<R>>,
+}
+
+impl<R: tokio::io::AsyncRead + Unpin> TableReader<R> {
+    /// Creates a new table reader.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(AsyncDecoder::Plain(reader)),
+        }
+    }
+
+    /// Creates a table reader decompressing `reader` with the given
+    /// compression.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the compression is not supported without the
+    /// `compression` feature.
+    pub fn with_compression(reader: R, compression: Compression) -> Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(AsyncDecoder::new(reader, compression)?),
+        })
+    }
+
+    /// Reads the next line from the file.
+    ///
+    /// The line terminator (`\n` or `\r\n`) is not included.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line cannot be read.
+    pub async fn read_line(&mut self) -> Result<Option<String>> {
+        let mut line = String::new();
+        let bytes_read = self.reader.read_line(&mut line).await?;
+        if bytes_read == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+}
+
+/// Reads the entries of a JSONLT file one line at a time, asynchronously.
+///
+/// This is the async counterpart of
+/// [`StreamReader`](crate::io::StreamReader), built on [`TableReader`].
+pub struct AsyncStreamReader<R> {
+    reader: TableReader<R>,
+    parser: LineParser,
+}
+
+impl<R: tokio::io::AsyncRead + Unpin> AsyncStreamReader<R> {
+    /// Creates a reader taking the key specifier from the file header.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: TableReader::new(reader),
+            parser: LineParser::new(None),
+        }
+    }
+
+    /// Creates a reader using the given key specifier.
+    ///
+    /// If the file has a header declaring a key, it must match `key`.
+    pub fn with_key(reader: R, key: impl Into<KeySpecifier>) -> Self {
+        Self {
+            reader: TableReader::new(reader),
+            parser: LineParser::new(Some(key.into())),
+        }
+    }
+
+    /// Creates a reader decompressing `reader` with the given compression,
+    /// taking the key specifier from the file header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the compression is not supported without the
+    /// `compression` feature.
+    pub fn with_compression(reader: R, compression: Compression) -> Result<Self> {
+        Ok(Self {
+            reader: TableReader::with_compression(reader, compression)?,
+            parser: LineParser::new(None),
+        })
+    }
+
+    /// Returns the header, once the first line has been read.
+    #[must_use]
+    pub const fn header(&self) -> Option<&Header> {
+        self.parser.header()
+    }
+
+    /// Returns the key specifier in effect.
+    #[must_use]
+    pub const fn key_specifier(&self) -> Option<&KeySpecifier> {
+        self.parser.key_specifier()
+    }
+
+    /// Reads the next record or tombstone.
+    ///
+    /// Returns `None` at the end of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line cannot be read or is not valid JSONLT.
+    pub async fn read_entry(&mut self) -> Result<Option<LogEntry>> {
+        while let Some(line) = self.reader.read_line().await? {
+            if let Some(entry) = self.parser.parse(&line)? {
+                return Ok(Some(LogEntry {
+                    line: self.parser.line_number(),
+                    entry,
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// A writer for JSONLT files.
+///
+/// A compressed stream is only complete once [`TableWriter::shutdown`] has
+/// been called.
+pub struct TableWriter<W> {
+    writer: BufWriter<AsyncEncoder<W>>,
+}
+
+impl<W: tokio::io::AsyncWrite + Unpin> TableWriter<W> {
+    /// Creates a new table writer.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: BufWriter::new(AsyncEncoder::Plain(writer)),
+        }
+    }
+
+    /// Creates a table writer compressing into `writer` with the given
+    /// compression.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the compression is not supported without the
+    /// `compression` feature.
+    pub fn with_compression(writer: W, compression: Compression) -> Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(AsyncEncoder::new(writer, compression)?),
+        })
+    }
+
+    /// Writes a line to the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line cannot be written.
+    pub async fn write_line(&mut self, line: &str) -> Result<()> {
+        self.writer.write_all(line.as_bytes()).await?;
+        self.writer.write_all(b"\n").await?;
+        Ok(())
+    }
+
+    /// Flushes buffered lines to the underlying writer.
+    ///
+    /// This does not sync a file to disk; see [`TableWriter::sync`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writer cannot be flushed.
+    pub async fn flush(&mut self) -> Result<()> {
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// Flushes the writer, completes a compressed stream and shuts down the
+    /// underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writer cannot be flushed or shut down.
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.writer.shutdown().await?;
+        Ok(())
+    }
+}
+
+impl TableWriter<File> {
+    /// Flushes the writer and syncs the file to disk.
+    ///
+    /// Unlike [`TableWriter::flush`], which only hands buffered lines to the
+    /// operating system, the lines written so far survive an operating
+    /// system crash once this returns. A compressed stream is flushed up to
+    /// the last line but stays open.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writer cannot be flushed or the file cannot
+    /// be synced.
+    pub async fn sync(&mut self) -> Result<()> {
+        self.writer.flush().await?;
+        self.writer.get_mut().get_mut().sync_data().await?;
+        Ok(())
+    }
+}
+
+/// Writes records and tombstones to a JSONLT file in canonical form,
+/// asynchronously.
+///
+/// This is the async counterpart of
+/// [`StreamWriter`](crate::io::StreamWriter), built on [`TableWriter`].
+pub struct AsyncStreamWriter<W> {
+    writer: TableWriter<W>,
+    key: KeySpecifier,
+}
+
+impl<W: tokio::io::AsyncWrite + Unpin> AsyncStreamWriter<W> {
+    /// Creates a writer for records keyed by `key`.
+    pub fn new(writer: W, key: impl Into<KeySpecifier>) -> Self {
+        Self {
+            writer: TableWriter::new(writer),
+            key: key.into(),
+        }
+    }
+
+    /// Creates a writer for records keyed by `key`, compressing into
+    /// `writer` with the given compression.
+    ///
+    /// Call [`AsyncStreamWriter::shutdown`] to complete the compressed
+    /// stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the compression is not supported without the
+    /// `compression` feature.
+    pub fn with_compression(
+        writer: W,
+        key: impl Into<KeySpecifier>,
+        compression: Compression,
+    ) -> Result<Self> {
+        Ok(Self {
+            writer: TableWriter::with_compression(writer, compression)?,
+            key: key.into(),
+        })
+    }
+
+    /// Writes a header line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header declares a different key specifier or
+    /// the line cannot be written.
+    pub async fn write_header(&mut self, header: &Header) -> Result<()> {
+        let line = header_line(&self.key, header)?;
+        self.writer.write_line(&line).await
+    }
+
+    /// Writes a record and returns its key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record is not an object with a valid key or
+    /// the line cannot be written.
+    pub async fn write_record(&mut self, record: &Record) -> Result<Key> {
+        let (key, line) = record_line(&self.key, record)?;
+        self.writer.write_line(&line).await?;
+        Ok(key)
+    }
+
+    /// Writes a tombstone deleting `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key does not match the key specifier or the
+    /// line cannot be written.
+    pub async fn write_tombstone(&mut self, key: &Key) -> Result<()> {
+        let line = tombstone_line(&self.key, key)?;
+        self.writer.write_line(&line).await
+    }
+
+    /// Writes a record or tombstone.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry is invalid or the line cannot be
+    /// written.
+    pub async fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        match entry {
+            Entry::Record { record, .. } => self.write_record(record).await.map(drop),
+            Entry::Tombstone { key } => self.write_tombstone(key).await,
+        }
+    }
+
+    /// Writes every record of a table in key order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a record is invalid, is stored under a key other
+    /// than its own, or a line cannot be written.
+    pub async fn write_table(&mut self, table: &Table) -> Result<()> {
+        for (key, record) in table.iter() {
+            let line = table_line(&self.key, key, record)?;
+            self.writer.write_line(&line).await?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writer cannot be flushed.
+    pub async fn flush(&mut self) -> Result<()> {
+        self.writer.flush().await
+    }
+
+    /// Flushes the writer, completes a compressed stream and shuts down the
+    /// underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writer cannot be flushed or shut down.
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.writer.shutdown().await
+    }
+}
+
+impl AsyncStreamWriter<File> {
+    /// Flushes the writer and syncs the file to disk.
+    ///
+    /// See [`TableWriter::sync`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writer cannot be flushed or the file cannot
+    /// be synced.
+    pub async fn sync(&mut self) -> Result<()> {
+        self.writer.sync().await
+    }
+}
+
+/// Opens a JSONLT file for reading.
+///
+/// Files ending in `.gz` or `.zst` are decompressed as they are read; see
+/// [`Compression::from_path`].
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened.
+pub async fn open<P: AsRef<Path>>(path: P) -> Result<TableReader<File>> {
+    let path = path.as_ref();
+    let compression = Compression::from_path(path);
+    let file = File::open(path).await.context("open", path)?;
+    TableReader::with_compression(file, compression).context("open", path)
+}
+
+/// Creates a JSONLT file for writing.
+///
+/// Files ending in `.gz` or `.zst` are compressed as they are written; call
+/// [`TableWriter::shutdown`] to complete the compressed stream.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be created.
+pub async fn create<P: AsRef<Path>>(path: P) -> Result<TableWriter<File>> {
+    let path = path.as_ref();
+    let compression = Compression::from_path(path);
+    let file = File::create(path).await.context("create", path)?;
+    TableWriter::with_compression(file, compression).context("create", path)
+}
@@ -0,0 +1,387 @@
+//! Transparent compression of JSONLT files.
+
+use crate::Result;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// The compression format of a JSONLT file.
+///
+/// Compressed files require the `compression` feature. Without it, opening
+/// a compressed file returns an error instead of reading garbage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Compression {
+    /// The file is not compressed.
+    #[default]
+    None,
+
+    /// The file is gzip compressed, as detected from a `.gz` extension.
+    ///
+    /// Files made of several gzip members, such as those written by
+    /// appending to a compressed file, are read as one stream.
+    Gzip,
+
+    /// The file is Zstandard compressed, as detected from a `.zst`
+    /// extension.
+    ///
+    /// Files made of several frames are read as one stream.
+    Zstd,
+}
+
+impl Compression {
+    /// Detects the compression of a file from its extension.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::io::Compression;
+    ///
+    /// assert_eq!(Compression::from_path("users.jsonlt.gz"), Compression::Gzip);
+    /// assert_eq!(Compression::from_path("users.jsonlt.zst"), Compression::Zstd);
+    /// assert_eq!(Compression::from_path("users.jsonlt"), Compression::None);
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Self::Gzip,
+            Some("zst") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+
+    /// Returns `true` unless the compression is [`Compression::None`].
+    #[must_use]
+    pub const fn is_compressed(self) -> bool {
+        !matches!(self, Self::None)
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn unsupported(self) -> crate::Error {
+        crate::Error::invalid_format(format!("{self} files require the compression feature"))
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "uncompressed",
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        })
+    }
+}
+
+/// A reader decompressing a JSONLT file.
+///
+/// Created with [`Decoder::new`], and by [`StreamReader::open`] for files
+/// with a compressed extension.
+///
+/// [`StreamReader::open`]: crate::io::StreamReader::open
+pub struct Decoder<R: Read> {
+    inner: DecoderInner<R>,
+}
+
+enum DecoderInner<R: Read> {
+    Plain(R),
+    #[cfg(feature = "compression")]
+    Gzip(flate2::read::MultiGzDecoder<R>),
+    #[cfg(feature = "compression")]
+    Zstd(zstd::stream::read::Decoder<'static, io::BufReader<R>>),
+}
+
+impl<R: Read> Decoder<R> {
+    /// Creates a reader decompressing `reader` with the given compression.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the compression is not supported without the
+    /// `compression` feature.
+    pub fn new(reader: R, compression: Compression) -> Result<Self> {
+        let inner = match compression {
+            Compression::None => DecoderInner::Plain(reader),
+            #[cfg(feature = "compression")]
+            Compression::Gzip => DecoderInner::Gzip(flate2::read::MultiGzDecoder::new(reader)),
+            #[cfg(feature = "compression")]
+            Compression::Zstd => DecoderInner::Zstd(zstd::stream::read::Decoder::new(reader)?),
+            #[cfg(not(feature = "compression"))]
+            _ => return Err(compression.unsupported()),
+        };
+        Ok(Self { inner })
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            DecoderInner::Plain(reader) => reader.read(buf),
+            #[cfg(feature = "compression")]
+            DecoderInner::Gzip(reader) => reader.read(buf),
+            #[cfg(feature = "compression")]
+            DecoderInner::Zstd(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl<R: Read> fmt::Debug for Decoder<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Decoder").finish_non_exhaustive()
+    }
+}
+
+/// A writer compressing a JSONLT file.
+///
+/// Created with [`Encoder::new`], and by [`StreamWriter::create`] for files
+/// with a compressed extension. The compressed stream is only complete once
+/// [`Encoder::finish`] has been called; dropping the encoder finishes it as
+/// well but ignores any error.
+///
+/// [`StreamWriter::create`]: crate::io::StreamWriter::create
+pub struct Encoder<W: Write> {
+    inner: Option<EncoderInner<W>>,
+}
+
+enum EncoderInner<W: Write> {
+    Plain(W),
+    #[cfg(feature = "compression")]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(feature = "compression")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// Creates a writer compressing into `writer` with the given compression
+    /// at the default level.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the compression is not supported without the
+    /// `compression` feature.
+    pub fn new(writer: W, compression: Compression) -> Result<Self> {
+        let inner = match compression {
+            Compression::None => EncoderInner::Plain(writer),
+            #[cfg(feature = "compression")]
+            Compression::Gzip => EncoderInner::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "compression")]
+            Compression::Zstd => EncoderInner::Zstd(zstd::stream::write::Encoder::new(writer, 0)?),
+            #[cfg(not(feature = "compression"))]
+            _ => return Err(compression.unsupported()),
+        };
+        Ok(Self { inner: Some(inner) })
+    }
+
+    fn inner_mut(&mut self) -> io::Result<&mut EncoderInner<W>> {
+        self.inner
+            .as_mut()
+            .ok_or_else(|| io::Error::other("encoder already finished"))
+    }
+
+    /// Completes the compressed stream and returns the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the end of the stream cannot be written.
+    pub fn finish(mut self) -> Result<W> {
+        let inner = self
+            .inner
+            .take()
+            .ok_or_else(|| io::Error::other("encoder already finished"))?;
+        let writer = match inner {
+            EncoderInner::Plain(mut writer) => {
+                writer.flush()?;
+                writer
+            }
+            #[cfg(feature = "compression")]
+            EncoderInner::Gzip(encoder) => encoder.finish()?,
+            #[cfg(feature = "compression")]
+            EncoderInner::Zstd(encoder) => encoder.finish()?,
+        };
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.inner_mut()? {
+            EncoderInner::Plain(writer) => writer.write(buf),
+            #[cfg(feature = "compression")]
+            EncoderInner::Gzip(writer) => writer.write(buf),
+            #[cfg(feature = "compression")]
+            EncoderInner::Zstd(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner_mut()? {
+            EncoderInner::Plain(writer) => writer.flush(),
+            #[cfg(feature = "compression")]
+            EncoderInner::Gzip(writer) => writer.flush(),
+            #[cfg(feature = "compression")]
+            EncoderInner::Zstd(writer) => writer.flush(),
+        }
+    }
+}
+
+impl<W: Write> Drop for Encoder<W> {
+    fn drop(&mut self) {
+        #[cfg(feature = "compression")]
+        if let Some(EncoderInner::Zstd(encoder)) = &mut self.inner {
+            // Gzip encoders finish themselves when dropped; Zstandard ones
+            // do not.
+            let _ = encoder.do_finish();
+        }
+    }
+}
+
+impl<W: Write> fmt::Debug for Encoder<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Encoder").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "async")]
+pub use tokio_io::{AsyncDecoder, AsyncEncoder};
+
+#[cfg(feature = "async")]
+mod tokio_io {
+    use super::Compression;
+    use crate::Result;
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    /// An async reader decompressing a JSONLT file.
+    #[derive(Debug)]
+    pub enum AsyncDecoder<R> {
+        Plain(R),
+        #[cfg(feature = "compression")]
+        Gzip(async_compression::tokio::bufread::GzipDecoder<tokio::io::BufReader<R>>),
+        #[cfg(feature = "compression")]
+        Zstd(async_compression::tokio::bufread::ZstdDecoder<tokio::io::BufReader<R>>),
+    }
+
+    impl<R: AsyncRead + Unpin> AsyncDecoder<R> {
+        #[allow(clippy::unnecessary_wraps)] // Fails without the compression feature
+        pub fn new(reader: R, compression: Compression) -> Result<Self> {
+            #[cfg(feature = "compression")]
+            use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+            #[cfg(feature = "compression")]
+            use tokio::io::BufReader;
+
+            Ok(match compression {
+                Compression::None => Self::Plain(reader),
+                #[cfg(feature = "compression")]
+                Compression::Gzip => {
+                    let mut decoder = GzipDecoder::new(BufReader::new(reader));
+                    decoder.multiple_members(true);
+                    Self::Gzip(decoder)
+                }
+                #[cfg(feature = "compression")]
+                Compression::Zstd => {
+                    let mut decoder = ZstdDecoder::new(BufReader::new(reader));
+                    decoder.multiple_members(true);
+                    Self::Zstd(decoder)
+                }
+                #[cfg(not(feature = "compression"))]
+                _ => return Err(compression.unsupported()),
+            })
+        }
+    }
+
+    impl<R: AsyncRead + Unpin> AsyncRead for AsyncDecoder<R> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            match self.get_mut() {
+                Self::Plain(reader) => Pin::new(reader).poll_read(cx, buf),
+                #[cfg(feature = "compression")]
+                Self::Gzip(reader) => Pin::new(reader).poll_read(cx, buf),
+                #[cfg(feature = "compression")]
+                Self::Zstd(reader) => Pin::new(reader).poll_read(cx, buf),
+            }
+        }
+    }
+
+    /// An async writer compressing a JSONLT file.
+    ///
+    /// The compressed stream is completed when the writer is shut down.
+    #[derive(Debug)]
+    pub enum AsyncEncoder<W> {
+        Plain(W),
+        #[cfg(feature = "compression")]
+        Gzip(async_compression::tokio::write::GzipEncoder<W>),
+        #[cfg(feature = "compression")]
+        Zstd(async_compression::tokio::write::ZstdEncoder<W>),
+    }
+
+    impl<W: AsyncWrite + Unpin> AsyncEncoder<W> {
+        #[allow(clippy::unnecessary_wraps)] // Fails without the compression feature
+        pub fn new(writer: W, compression: Compression) -> Result<Self> {
+            #[cfg(feature = "compression")]
+            use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+
+            Ok(match compression {
+                Compression::None => Self::Plain(writer),
+                #[cfg(feature = "compression")]
+                Compression::Gzip => Self::Gzip(GzipEncoder::new(writer)),
+                #[cfg(feature = "compression")]
+                Compression::Zstd => Self::Zstd(ZstdEncoder::new(writer)),
+                #[cfg(not(feature = "compression"))]
+                _ => return Err(compression.unsupported()),
+            })
+        }
+    }
+
+    impl<W> AsyncEncoder<W> {
+        /// Returns the underlying writer.
+        pub fn get_mut(&mut self) -> &mut W {
+            match self {
+                Self::Plain(writer) => writer,
+                #[cfg(feature = "compression")]
+                Self::Gzip(writer) => writer.get_mut(),
+                #[cfg(feature = "compression")]
+                Self::Zstd(writer) => writer.get_mut(),
+            }
+        }
+    }
+
+    impl<W: AsyncWrite + Unpin> AsyncWrite for AsyncEncoder<W> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            match self.get_mut() {
+                Self::Plain(writer) => Pin::new(writer).poll_write(cx, buf),
+                #[cfg(feature = "compression")]
+                Self::Gzip(writer) => Pin::new(writer).poll_write(cx, buf),
+                #[cfg(feature = "compression")]
+                Self::Zstd(writer) => Pin::new(writer).poll_write(cx, buf),
+            }
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            match self.get_mut() {
+                Self::Plain(writer) => Pin::new(writer).poll_flush(cx),
+                #[cfg(feature = "compression")]
+                Self::Gzip(writer) => Pin::new(writer).poll_flush(cx),
+                #[cfg(feature = "compression")]
+                Self::Zstd(writer) => Pin::new(writer).poll_flush(cx),
+            }
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            match self.get_mut() {
+                Self::Plain(writer) => Pin::new(writer).poll_shutdown(cx),
+                #[cfg(feature = "compression")]
+                Self::Gzip(writer) => Pin::new(writer).poll_shutdown(cx),
+                #[cfg(feature = "compression")]
+                Self::Zstd(writer) => Pin::new(writer).poll_shutdown(cx),
+            }
+        }
+    }
+}
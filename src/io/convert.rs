@@ -0,0 +1,222 @@
+//! Conversion between JSONLT and plain JSON formats.
+
+use crate::io::StreamWriter;
+use crate::{
+    record_key, to_canonical_string, Error, Header, Key, KeySpecifier, Record, Result, Table,
+};
+use serde::de::{DeserializeSeed, Deserializer, SeqAccess, Visitor};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, Read, Write};
+
+/// Collects records into a table, remembering the ones without a valid key.
+struct Importer<'a> {
+    key: &'a KeySpecifier,
+    table: Table,
+    failures: Vec<String>,
+}
+
+impl<'a> Importer<'a> {
+    fn new(key: &'a KeySpecifier) -> Self {
+        Self {
+            key,
+            table: Table::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, position: &str, value: Value) {
+        let entry = match &value {
+            Value::Object(object) => record_key(object, self.key),
+            _ => Err(Error::invalid_format("record must be a JSON object")),
+        }
+        .and_then(|key| Ok((key, Record::try_from_value(value)?)));
+        match entry {
+            Ok((key, record)) => self.table.insert(key, record),
+            Err(Error::InvalidFormat { message, .. } | Error::InvalidKey { message, .. }) => {
+                self.failures.push(format!("{position}: {message}"));
+            }
+            Err(err) => self.failures.push(format!("{position}: {err}")),
+        }
+    }
+
+    fn finish(self) -> Result<Table> {
+        if self.failures.is_empty() {
+            return Ok(self.table);
+        }
+        Err(Error::invalid_key(format!(
+            "{} records have no valid key: {}",
+            self.failures.len(),
+            self.failures.join("; ")
+        )))
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for &mut Importer<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for &mut Importer<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a JSON array of records")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<(), A::Error> {
+        let mut index = 0;
+        while let Some(value) = seq.next_element()? {
+            index += 1;
+            self.add(&format!("record {index}"), value);
+        }
+        Ok(())
+    }
+}
+
+/// Reads a table from a JSON array of records.
+///
+/// Records are decoded one at a time, so the array is never held in memory
+/// as a whole. Later records replace earlier ones with the same key.
+///
+/// # Errors
+///
+/// Returns an error if the input is not a JSON array, or an error listing
+/// every record that is not an object with a valid key.
+///
+/// # Examples
+///
+/// ```rust
+/// use jsonlt::io::read_json_array;
+///
+/// let table = read_json_array(r#"[{"id": 1}, {"id": 2}]"#.as_bytes(), &"id".into()).unwrap();
+/// assert_eq!(table.len(), 2);
+///
+/// let err = read_json_array(r#"[{"id": 1}, {"name": "x"}, 3]"#.as_bytes(), &"id".into());
+/// assert!(err.unwrap_err().to_string().contains("2 records have no valid key"));
+/// ```
+pub fn read_json_array<R: Read>(reader: R, key: &KeySpecifier) -> Result<Table> {
+    let mut importer = Importer::new(key);
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    (&mut importer).deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    importer.finish()
+}
+
+/// Reads a table from plain JSON Lines without a header.
+///
+/// Blank lines are skipped. Later records replace earlier ones with the same
+/// key.
+///
+/// # Errors
+///
+/// Returns an error naming the line if a line is not valid JSON, or an
+/// error listing every line that is not an object with a valid key.
+pub fn read_jsonl<R: BufRead>(reader: R, key: &KeySpecifier) -> Result<Table> {
+    let mut importer = Importer::new(key);
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let number = index + 1;
+        let value = serde_json::from_str(&line)
+            .map_err(|err| Error::from(err).at(Some(number as u64), None))?;
+        importer.add(&format!("line {number}"), value);
+    }
+    importer.finish()
+}
+
+/// Writes a table as a compacted JSONLT file with a header declaring `key`.
+///
+/// Records are written in key order.
+///
+/// # Errors
+///
+/// Returns an error if a record does not match `key` or the output cannot
+/// be written.
+pub fn write_jsonlt<W: Write>(writer: W, table: &Table, key: &KeySpecifier) -> Result<()> {
+    let mut writer = StreamWriter::new(writer, key.clone());
+    writer.write_header(&Header::new(key.clone()))?;
+    writer.write_table(table)?;
+    writer.flush()
+}
+
+/// Writes a table as a JSON array of records in key order, one record per
+/// line.
+///
+/// # Errors
+///
+/// Returns an error if the output cannot be written.
+pub fn write_json_array<W: Write>(mut writer: W, table: &Table) -> Result<()> {
+    writer.write_all(b"[")?;
+    for (i, (_, record)) in table.iter().enumerate() {
+        writer.write_all(if i == 0 { b"\n" } else { b",\n" })?;
+        writer.write_all(to_canonical_string(record.value()).as_bytes())?;
+    }
+    writer.write_all(b"\n]\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes a table as a JSON object mapping each key to its record, in key
+/// order with one member per line.
+///
+/// String keys are used as member names directly; integer and tuple keys
+/// are written as their JSON text, such as `"7"` or `"[\"a\",1]"`.
+///
+/// # Errors
+///
+/// Returns an error if two keys become the same member name, such as the
+/// integer key `7` and the string key `"7"`, in which case nothing is
+/// written, or if the output cannot be written.
+///
+/// # Examples
+///
+/// ```rust
+/// use jsonlt::io::write_json_object;
+/// use jsonlt::{Record, Table};
+/// use serde_json::json;
+///
+/// let mut table = Table::new();
+/// table.insert(7, Record::try_from_value(json!({"id": 7})).unwrap());
+/// table.insert("a", Record::try_from_value(json!({"id": "a"})).unwrap());
+///
+/// let mut out = Vec::new();
+/// write_json_object(&mut out, &table).unwrap();
+/// assert_eq!(out, b"{\n\"7\":{\"id\":7},\n\"a\":{\"id\":\"a\"}\n}\n");
+/// ```
+pub fn write_json_object<W: Write>(mut writer: W, table: &Table) -> Result<()> {
+    let mut names: HashMap<String, &Key> = HashMap::new();
+    let mut members = Vec::new();
+    for (key, record) in table.iter() {
+        let name = key
+            .as_str()
+            .map_or_else(|| to_canonical_string(&key.to_value()), ToString::to_string);
+        if let Some(other) = names.insert(name.clone(), key) {
+            return Err(Error::invalid_key(format!(
+                "keys {} and {} are written as the same member name {name:?}",
+                to_canonical_string(&other.to_value()),
+                to_canonical_string(&key.to_value()),
+            )));
+        }
+        members.push((name, record));
+    }
+    writer.write_all(b"{")?;
+    for (i, (name, record)) in members.into_iter().enumerate() {
+        writer.write_all(if i == 0 { b"\n" } else { b",\n" })?;
+        writer.write_all(to_canonical_string(&Value::String(name)).as_bytes())?;
+        writer.write_all(b":")?;
+        writer.write_all(to_canonical_string(record.value()).as_bytes())?;
+    }
+    writer.write_all(b"\n}\n")?;
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,619 @@
+//! A directory of JSONLT files used as one database.
+//!
+//! Every `*.jsonlt` file in the directory is a table named after the file.
+//! Compressed table files, such as `*.jsonlt.gz`, are not supported since a
+//! commit rolled back by truncating them could cut a compressed member in
+//! half; opening a directory holding one fails instead of ignoring it.
+//! Transactions spanning several tables are committed with a journal file,
+//! `.jsonlt-journal`, holding the length of every table file about to be
+//! appended to:
+//!
+//! ```text
+//! {"$jsonlt-journal":{"version":1}}
+//! {"length":1024,"table":"orders"}
+//! {"length":512,"table":"users"}
+//! ```
+//!
+//! The journal is written and synced before any table, and removed once all
+//! the appended lines are synced, which is the commit point. If the process
+//! stops in between, opening the database finds the journal and truncates
+//! the tables back to their recorded lengths, so either every write of a
+//! transaction is kept or none is. With [`Durability::None`] nothing is
+//! synced, which keeps this guarantee only for crashes of the process.
+
+use super::durability::{crash_point, sync_dir, CrashPoint};
+use super::{Compression, Durability, FileTable};
+use crate::error::ResultExt;
+use crate::json::{self, Map, Value};
+use crate::reference::{check_deleted, check_record, deletions, Tables};
+use crate::{check_references, record_key, to_canonical_string, tombstone_line, Error, Key};
+use crate::{DanglingReference, KeySpecifier, Record, Reference, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// The extension of table files.
+const TABLE_EXTENSION: &str = "jsonlt";
+
+/// The name of the journal file.
+const JOURNAL_NAME: &str = ".jsonlt-journal";
+
+/// The field naming the header line of a journal.
+const JOURNAL_FIELD: &str = "$jsonlt-journal";
+
+/// The version of the journal file format.
+const JOURNAL_VERSION: u64 = 1;
+
+/// A directory of JSONLT files, each a named table.
+///
+/// The database assumes it is the only writer of its files while a
+/// transaction commits: recovering from an interrupted commit truncates the
+/// tables it was writing, which would also drop lines appended by others in
+/// the meantime.
+///
+/// # Examples
+///
+/// ```rust
+/// use jsonlt::io::Database;
+/// use jsonlt::{Key, Record};
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// let mut db = Database::open(dir.path()).unwrap();
+/// db.create_table("users", "id").unwrap();
+/// db.create_table("orders", "id").unwrap();
+///
+/// let mut tx = db.transaction();
+/// tx.put("users", Record::try_from(r#"{"id":"alice"}"#).unwrap()).unwrap();
+/// tx.put("orders", Record::try_from(r#"{"id":1,"user":"alice"}"#).unwrap()).unwrap();
+/// tx.commit().unwrap();
+///
+/// assert!(db.table("users").unwrap().table().get(&Key::from("alice")).is_some());
+/// assert_eq!(db.table("orders").unwrap().table().len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct Database {
+    path: PathBuf,
+    durability: Durability,
+    tables: BTreeMap<String, FileTable>,
+    references: Vec<Reference>,
+}
+
+impl Database {
+    /// Opens the database in an existing directory.
+    ///
+    /// A commit interrupted by a crash is rolled back first, then every
+    /// `*.jsonlt` file in the directory is loaded as a table.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be read, the journal of an
+    /// interrupted commit cannot be applied, a table file cannot be loaded,
+    /// or the directory holds a compressed table file such as
+    /// `users.jsonlt.gz`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        recover(&path).context("recover", &path)?;
+        let mut tables = BTreeMap::new();
+        for entry in fs::read_dir(&path).context("read", &path)? {
+            let entry = entry.context("read", &path)?;
+            let file = entry.path();
+            if is_compressed_table(&file) {
+                return Err(Error::invalid_format(
+                    "compressed table files are not supported in a database",
+                )
+                .context("open", &file));
+            }
+            if file
+                .extension()
+                .is_none_or(|extension| extension != TABLE_EXTENSION)
+                || !entry.file_type().context("read", &file)?.is_file()
+            {
+                continue;
+            }
+            let Some(name) = file.file_stem().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if check_name(name).is_ok() {
+                tables.insert(name.to_string(), FileTable::open(&file)?);
+            }
+        }
+        Ok(Self {
+            path,
+            durability: Durability::default(),
+            tables,
+            references: Vec::new(),
+        })
+    }
+
+    /// Returns the path of the directory.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns when writes to the tables are synced.
+    #[must_use]
+    pub const fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    /// Sets when writes to the tables, including ones created later, are
+    /// synced.
+    ///
+    /// Transactions are synced as they commit unless `durability` is
+    /// [`Durability::None`].
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+        for table in self.tables.values_mut() {
+            table.set_durability(durability);
+        }
+    }
+
+    /// Returns the names of the tables, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.tables.keys().map(String::as_str)
+    }
+
+    /// Returns the table with the given name.
+    #[must_use]
+    pub fn table(&self, name: &str) -> Option<&FileTable> {
+        self.tables.get(name)
+    }
+
+    /// Returns the table with the given name for writing outside a
+    /// transaction.
+    ///
+    /// Writes made through the table are not checked against the
+    /// references of the database.
+    pub fn table_mut(&mut self, name: &str) -> Option<&mut FileTable> {
+        self.tables.get_mut(name)
+    }
+
+    /// Creates a table in a new file with a header declaring `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is not a plain file name, the key
+    /// specifier is invalid, or the file already exists or cannot be
+    /// written.
+    pub fn create_table(
+        &mut self,
+        name: &str,
+        key: impl Into<KeySpecifier>,
+    ) -> Result<&mut FileTable> {
+        check_name(name)?;
+        let path = self.path.join(format!("{name}.{TABLE_EXTENSION}"));
+        let mut table = FileTable::create(path, key)?;
+        table.set_durability(self.durability);
+        Ok(self.tables.entry(name.to_string()).or_insert(table))
+    }
+
+    /// Declares a reference between two tables, enforced by every
+    /// transaction from then on.
+    ///
+    /// A transaction then rejects writes that would leave a record
+    /// referencing a missing one, and deleting a referenced record either
+    /// fails or, with [`OnDelete::Cascade`](crate::OnDelete::Cascade), also
+    /// deletes the records referencing it. References are not stored in the
+    /// directory, so they are declared again after each open.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::TableNotFound`] if the reference names a table the
+    /// database does not have, or [`Error::DanglingReference`] if a record
+    /// already violates it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::io::Database;
+    /// use jsonlt::{ErrorKind, Record};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// let mut db = Database::open(dir.path()).unwrap();
+    /// db.create_table("users", "id").unwrap();
+    /// db.create_table("orders", "id").unwrap();
+    /// db.add_reference("orders.user_id -> users".parse().unwrap()).unwrap();
+    ///
+    /// let mut tx = db.transaction();
+    /// let order = Record::try_from(r#"{"id":1,"user_id":"alice"}"#).unwrap();
+    /// let err = tx.put("orders", order).unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::DanglingReference);
+    /// ```
+    pub fn add_reference(&mut self, reference: Reference) -> Result<()> {
+        let dangling = self.check_references(core::slice::from_ref(&reference))?;
+        if let Some(dangling) = dangling.into_iter().next() {
+            return Err(Error::DanglingReference(Box::new(dangling)));
+        }
+        self.references.push(reference);
+        Ok(())
+    }
+
+    /// Returns the references enforced by transactions.
+    #[must_use]
+    pub fn references(&self) -> &[Reference] {
+        &self.references
+    }
+
+    /// Finds the records of the tables whose references do not resolve.
+    ///
+    /// See [`check_references`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::TableNotFound`] if a reference names a table the
+    /// database does not have.
+    pub fn check_references(&self, references: &[Reference]) -> Result<Vec<DanglingReference>> {
+        check_references(references, |name| self.table(name).map(FileTable::table))
+    }
+
+    /// Starts a transaction.
+    ///
+    /// Writes made through the transaction are only applied, all together,
+    /// when it is committed.
+    pub fn transaction(&mut self) -> Transaction<'_> {
+        Transaction {
+            database: self,
+            writes: BTreeMap::new(),
+        }
+    }
+
+    fn require(&self, name: &str) -> Result<&FileTable> {
+        self.tables.get(name).ok_or_else(|| Error::TableNotFound {
+            name: name.to_string(),
+        })
+    }
+
+    fn commit(&mut self, writes: BTreeMap<String, Writes>) -> Result<()> {
+        if !self.references.is_empty() {
+            // Records appended by others since the writes were staged may
+            // reference records the transaction deletes.
+            for table in self.tables.values_mut() {
+                table.poll()?;
+            }
+            let staged = Staged {
+                tables: &self.tables,
+                writes: &writes,
+            };
+            for (name, writes) in &writes {
+                for (key, write) in writes {
+                    match write {
+                        Some(record) => {
+                            check_record(name, key, record, &staged, &self.references)?;
+                        }
+                        None => check_deleted(name, key, &staged, &self.references)?,
+                    }
+                }
+            }
+        }
+
+        let mut batches = Vec::new();
+        for (name, writes) in writes {
+            let Some(table) = self.tables.get_mut(&name) else {
+                continue;
+            };
+            table.poll()?;
+            let lines = writes
+                .into_iter()
+                .filter_map(|(key, write)| match (write, table.key_specifier()) {
+                    (Some(record), _) => Some(Ok(to_canonical_string(record.value()))),
+                    (None, Some(specifier)) if table.table().contains_key(&key) => {
+                        Some(tombstone_line(specifier, &key))
+                    }
+                    (None, _) => None,
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if !lines.is_empty() {
+                let length = fs::metadata(table.path())
+                    .context("read", table.path())?
+                    .len();
+                batches.push((name, length, lines));
+            }
+        }
+        if batches.is_empty() {
+            return Ok(());
+        }
+
+        let lengths: Vec<_> = batches
+            .iter()
+            .map(|(name, length, _)| (name.as_str(), *length))
+            .collect();
+        let journal = self.path.join(JOURNAL_NAME);
+        let sync = self.durability.syncs_commit();
+        write_journal(&self.path, &lengths, sync).context("write", &journal)?;
+        crash_point(CrashPoint::JournalWritten);
+        let applied = batches.iter().try_for_each(|(name, _, lines)| {
+            let result = self
+                .tables
+                .get_mut(name)
+                .map_or(Ok(()), |table| table.append(lines, true));
+            crash_point(CrashPoint::TableAppended);
+            result
+        });
+        if let Err(err) = applied {
+            for (name, length, _) in &batches {
+                if let Some(table) = self.tables.get_mut(name) {
+                    truncate(table.path(), *length).context("roll back", table.path())?;
+                    table.reload()?;
+                }
+            }
+            remove_journal(&self.path, sync).context("remove", &journal)?;
+            return Err(err);
+        }
+        crash_point(CrashPoint::TablesAppended);
+        remove_journal(&self.path, sync).context("remove", &journal)
+    }
+}
+
+/// The staged writes to one table: a record to put, or `None` to delete.
+type Writes = BTreeMap<Key, Option<Record>>;
+
+/// The tables of a database as the staged writes of a transaction leave
+/// them.
+struct Staged<'a> {
+    tables: &'a BTreeMap<String, FileTable>,
+    writes: &'a BTreeMap<String, Writes>,
+}
+
+impl Tables for Staged<'_> {
+    fn has_table(&self, name: &str) -> bool {
+        self.tables.contains_key(name)
+    }
+
+    fn contains(&self, name: &str, key: &Key) -> bool {
+        self.writes
+            .get(name)
+            .and_then(|writes| writes.get(key))
+            .map_or_else(
+                || {
+                    self.tables
+                        .get(name)
+                        .is_some_and(|file| file.table().contains_key(key))
+                },
+                Option::is_some,
+            )
+    }
+
+    fn records<'a>(&'a self, name: &str) -> Box<dyn Iterator<Item = (&'a Key, &'a Record)> + 'a> {
+        let writes = self.writes.get(name);
+        let stored = self
+            .tables
+            .get(name)
+            .into_iter()
+            .flat_map(|file| file.table().iter())
+            .filter(move |(key, _)| writes.is_none_or(|writes| !writes.contains_key(*key)));
+        let staged = writes
+            .into_iter()
+            .flatten()
+            .filter_map(|(key, write)| write.as_ref().map(|record| (key, record)));
+        Box::new(stored.chain(staged))
+    }
+}
+
+/// A set of writes to the tables of a [`Database`], applied all together
+/// or not at all.
+///
+/// Reads through the transaction see its own writes. Dropping the
+/// transaction without committing it discards the writes.
+#[derive(Debug)]
+#[must_use = "a transaction does nothing unless committed"]
+pub struct Transaction<'a> {
+    database: &'a mut Database,
+    writes: BTreeMap<String, Writes>,
+}
+
+impl Transaction<'_> {
+    /// Returns the record with the given key, including writes made in the
+    /// transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such table.
+    pub fn get(&self, table: &str, key: impl Into<Key>) -> Result<Option<&Record>> {
+        let key = key.into();
+        let file = self.database.require(table)?;
+        Ok(self
+            .writes
+            .get(table)
+            .and_then(|writes| writes.get(&key))
+            .map_or_else(|| file.table().get(&key), Option::as_ref))
+    }
+
+    /// Stages a record to be written to a table, replacing any record with
+    /// the same key. Returns the key of the record.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such table, it has no key specifier,
+    /// the record is not an object with a valid key, or it references a
+    /// missing record through a reference of the database.
+    pub fn put(&mut self, table: &str, record: Record) -> Result<Key> {
+        let specifier = self.key_specifier(table)?;
+        let object = record
+            .value()
+            .as_object()
+            .ok_or_else(|| Error::invalid_format("record must be a JSON object"))?;
+        let key = record_key(object, specifier)?;
+        check_record(
+            table,
+            &key,
+            &record,
+            &self.staged(),
+            &self.database.references,
+        )?;
+        self.writes
+            .entry(table.to_string())
+            .or_default()
+            .insert(key.clone(), Some(record));
+        Ok(key)
+    }
+
+    /// Stages the deletion of a record. Returns `true` if the record exists,
+    /// including writes made in the transaction.
+    ///
+    /// The records referencing it through a reference of the database with
+    /// [`OnDelete::Cascade`](crate::OnDelete::Cascade) are deleted too.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such table, it has no key specifier,
+    /// the key does not match it, or a record would be left referencing a
+    /// deleted one.
+    pub fn delete(&mut self, table: &str, key: impl Into<Key>) -> Result<bool> {
+        let key = key.into();
+        self.key_specifier(table)?.key_fields(&key)?;
+        let existed = self.get(table, key.clone())?.is_some();
+        let deleted = if existed {
+            deletions(table, key, &self.staged(), &self.database.references)?
+        } else {
+            BTreeSet::from([(table.to_string(), key)])
+        };
+        for (table, key) in deleted {
+            self.writes.entry(table).or_default().insert(key, None);
+        }
+        Ok(existed)
+    }
+
+    /// Applies the writes to every table.
+    ///
+    /// Tables are caught up with lines appended by others first, and the
+    /// writes are checked against the references of the database again. If
+    /// a write fails, the tables written so far are truncated back and
+    /// reloaded; if the process stops instead, [`Database::open`] rolls them
+    /// back.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writes now violate a reference, or the
+    /// journal or a table cannot be written, in which case no write was
+    /// applied.
+    pub fn commit(self) -> Result<()> {
+        self.database.commit(self.writes)
+    }
+
+    /// Discards the writes.
+    pub fn rollback(self) {}
+
+    fn staged(&self) -> Staged<'_> {
+        Staged {
+            tables: &self.database.tables,
+            writes: &self.writes,
+        }
+    }
+
+    fn key_specifier(&self, table: &str) -> Result<&KeySpecifier> {
+        self.database
+            .require(table)?
+            .key_specifier()
+            .ok_or_else(|| {
+                Error::invalid_format(format!(
+                    "table {table:?} has no header declaring a key specifier"
+                ))
+            })
+    }
+}
+
+/// Checks that a table name is a plain file name.
+/// Returns `true` if the path names a compressed table file, such as
+/// `users.jsonlt.gz`.
+fn is_compressed_table(path: &Path) -> bool {
+    Compression::from_path(path).is_compressed()
+        && path
+            .file_stem()
+            .map(Path::new)
+            .and_then(Path::extension)
+            .is_some_and(|extension| extension == TABLE_EXTENSION)
+}
+
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(Error::invalid_format(format!(
+            "table name {name:?} must be a plain file name"
+        )));
+    }
+    Ok(())
+}
+
+/// Writes the journal of a commit, replacing any existing one, and syncs it
+/// and the directory if `sync` is set.
+fn write_journal(dir: &Path, lengths: &[(&str, u64)], sync: bool) -> Result<()> {
+    let temp = dir.join(format!("{JOURNAL_NAME}.tmp"));
+    let mut header = Map::new();
+    let mut fields = Map::new();
+    fields.insert("version".into(), Value::from(JOURNAL_VERSION));
+    header.insert(JOURNAL_FIELD.into(), Value::Object(fields));
+    let mut text = to_canonical_string(&Value::Object(header));
+    text.push('\n');
+    for (table, length) in lengths {
+        let mut entry = Map::new();
+        entry.insert("table".into(), Value::from(*table));
+        entry.insert("length".into(), Value::from(*length));
+        text.push_str(&to_canonical_string(&Value::Object(entry)));
+        text.push('\n');
+    }
+    let mut file = File::create(&temp)?;
+    file.write_all(text.as_bytes())?;
+    if sync {
+        file.sync_all()?;
+    }
+    fs::rename(&temp, dir.join(JOURNAL_NAME))?;
+    if sync {
+        sync_dir(dir)?;
+    }
+    Ok(())
+}
+
+/// Removes the journal, and syncs the directory if `sync` is set.
+fn remove_journal(dir: &Path, sync: bool) -> Result<()> {
+    fs::remove_file(dir.join(JOURNAL_NAME))?;
+    if sync {
+        sync_dir(dir)?;
+    }
+    Ok(())
+}
+
+/// Rolls back the commit recorded in the journal, if there is one.
+fn recover(dir: &Path) -> Result<()> {
+    match fs::remove_file(dir.join(format!("{JOURNAL_NAME}.tmp"))) {
+        Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+        _ => {}
+    }
+    let file = match File::open(dir.join(JOURNAL_NAME)) {
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        file => file?,
+    };
+    let invalid = || Error::invalid_format("the journal of an interrupted commit is corrupt");
+    let mut lines = BufReader::new(file).lines();
+    let header = json::from_str(&lines.next().ok_or_else(invalid)??)?;
+    if header
+        .get(JOURNAL_FIELD)
+        .and_then(|fields| fields.get("version"))
+        .and_then(Value::as_u64)
+        != Some(JOURNAL_VERSION)
+    {
+        return Err(invalid());
+    }
+    for line in lines {
+        let entry = json::from_str(&line?)?;
+        let table = entry.get("table").and_then(Value::as_str);
+        let length = entry.get("length").and_then(Value::as_u64);
+        let (Some(table), Some(length)) = (table, length) else {
+            return Err(invalid());
+        };
+        check_name(table)?;
+        let path = dir.join(format!("{table}.{TABLE_EXTENSION}"));
+        truncate(&path, length).context("roll back", &path)?;
+    }
+    remove_journal(dir, true)
+}
+
+/// Truncates a file to `length` bytes, if it is longer, and syncs it.
+fn truncate(path: &Path, length: u64) -> Result<()> {
+    let file = OpenOptions::new().write(true).open(path)?;
+    if file.metadata()?.len() > length {
+        file.set_len(length)?;
+        file.sync_all()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,223 @@
+//! Durability settings, and simulated crashes for testing recovery.
+
+use crate::{Error, Result};
+#[cfg(feature = "test-util")]
+use std::cell::Cell;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// When writes to a file are synced to stable storage.
+///
+/// Data written but not yet synced survives the writing process crashing,
+/// but may be lost if the operating system crashes or the power fails.
+/// Syncing closes that gap at the cost of waiting for the disk.
+///
+/// Whatever the setting, [`FileTable::create`](super::FileTable::create)
+/// syncs the new file and its directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Durability {
+    /// Nothing is synced; the operating system writes data back when it
+    /// chooses.
+    ///
+    /// A [`Database`](super::Database) transaction is still all or nothing
+    /// if the process crashes, but not if the operating system does.
+    None,
+
+    /// Every append is synced before it returns.
+    Always,
+
+    /// Appends are synced in groups.
+    ///
+    /// An append syncs the file once `operations` appended lines are
+    /// unsynced or `interval` has passed since the last sync. No timer runs
+    /// in the background: after the last append of a burst, the group stays
+    /// unsynced until the next append, however long that takes. Call
+    /// [`FileTable::sync_if_due`](super::FileTable::sync_if_due) from a
+    /// timer of your own to bound that, and
+    /// [`FileTable::sync`](super::FileTable::sync) before stopping.
+    Group {
+        /// The number of lines written between syncs.
+        operations: usize,
+
+        /// The time after the last sync from which the next append syncs.
+        interval: Duration,
+    },
+
+    /// Only the files written by a [`Database`](super::Database)
+    /// transaction are synced, when it commits, and compacted files are
+    /// synced before they replace the original.
+    #[default]
+    Commit,
+}
+
+impl Durability {
+    /// Returns `true` if an append leaving `unsynced` lines, `elapsed` after
+    /// the last sync, must sync the file.
+    pub(super) fn syncs_append(self, unsynced: usize, elapsed: Duration) -> bool {
+        match self {
+            Self::None | Self::Commit => false,
+            Self::Always => true,
+            Self::Group {
+                operations,
+                interval,
+            } => unsynced >= operations || elapsed >= interval,
+        }
+    }
+
+    /// Returns `true` if `unsynced` lines left by earlier appends, `elapsed`
+    /// after the last sync, are due to be synced.
+    pub(super) fn syncs_idle(self, unsynced: usize, elapsed: Duration) -> bool {
+        unsynced > 0 && matches!(self, Self::Group { .. }) && self.syncs_append(unsynced, elapsed)
+    }
+
+    /// Returns `true` if transactions and compactions are synced.
+    pub(super) fn syncs_commit(self) -> bool {
+        self != Self::None
+    }
+}
+
+/// A point in a write made of several steps at which a crash can be
+/// simulated with `simulate_crash`, available with the `test-util` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CrashPoint {
+    /// The journal of a transaction has been written, before any table.
+    JournalWritten,
+
+    /// The first table written by a transaction has been appended to,
+    /// before the others.
+    TableAppended,
+
+    /// Every table written by a transaction has been appended to, before
+    /// the journal is removed to commit it.
+    TablesAppended,
+
+    /// A file rewritten in full, such as a compacted or formatted one, has
+    /// been written next to the original, before it is renamed over it.
+    CompactWritten,
+}
+
+#[cfg(feature = "test-util")]
+thread_local! {
+    static CRASH_POINT: Cell<Option<CrashPoint>> = const { Cell::new(None) };
+}
+
+/// Makes the next write on the current thread that reaches `point` panic
+/// there, as if the process had crashed.
+///
+/// This is meant for tests proving that files are recoverable after a
+/// crash: catch the panic with [`std::panic::catch_unwind`], then reopen
+/// the files.
+///
+/// The panic unwinds, so destructors run on the way out, unlike in a real
+/// crash. Each crash point is only reached once the writes before it are
+/// finished and their files closed, so no buffered data is flushed late and
+/// the files are left as a crash of the process there would leave them. What
+/// an operating system crash loses cannot be simulated this way.
+///
+/// # Examples
+///
+/// ```rust
+/// use jsonlt::io::{simulate_crash, CrashPoint, Database};
+/// use jsonlt::Record;
+/// use std::panic::{catch_unwind, AssertUnwindSafe};
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// let mut db = Database::open(dir.path()).unwrap();
+/// db.create_table("users", "id").unwrap();
+/// db.create_table("orders", "id").unwrap();
+///
+/// simulate_crash(CrashPoint::TableAppended);
+/// let crashed = catch_unwind(AssertUnwindSafe(|| {
+///     let mut tx = db.transaction();
+///     tx.put("users", Record::try_from(r#"{"id":"alice"}"#).unwrap()).unwrap();
+///     tx.put("orders", Record::try_from(r#"{"id":1}"#).unwrap()).unwrap();
+///     tx.commit()
+/// }));
+/// assert!(crashed.is_err());
+///
+/// let db = Database::open(dir.path()).unwrap();
+/// assert!(db.table("users").unwrap().table().is_empty());
+/// assert!(db.table("orders").unwrap().table().is_empty());
+/// ```
+#[cfg(feature = "test-util")]
+pub fn simulate_crash(point: CrashPoint) {
+    CRASH_POINT.set(Some(point));
+}
+
+/// Panics if a crash at `point` was asked for with [`simulate_crash`].
+#[cfg(feature = "test-util")]
+pub(super) fn crash_point(point: CrashPoint) {
+    if CRASH_POINT.get() == Some(point) {
+        CRASH_POINT.set(None);
+        panic!("simulated crash at {point:?}");
+    }
+}
+
+/// Does nothing: crashes are only simulated with the `test-util` feature.
+#[cfg(not(feature = "test-util"))]
+#[inline]
+pub(super) const fn crash_point(_point: CrashPoint) {}
+
+/// Replaces the file at `path` with a file written by `write`, which is
+/// handed the new file and returns it once written.
+///
+/// The new file is created next to `path`, named after it with `suffix`
+/// appended, and renamed over it, so readers see either the old or the new
+/// file. It takes the permissions of the file it replaces. With `sync`, it
+/// is synced before the rename and the directory after it. On error the new
+/// file is removed and `path` is left untouched.
+///
+/// A crash simulated at [`CrashPoint::CompactWritten`] leaves the new file
+/// next to `path`.
+pub fn replace_file(
+    path: &Path,
+    suffix: &str,
+    sync: bool,
+    write: impl FnOnce(File) -> Result<File>,
+) -> Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(suffix);
+    let temp = PathBuf::from(temp);
+
+    let written = File::create(&temp).map_err(Error::from).and_then(|file| {
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        let file = write(file)?;
+        if sync {
+            file.sync_all()?;
+        }
+        Ok(())
+    });
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(err);
+    }
+    crash_point(CrashPoint::CompactWritten);
+    fs::rename(&temp, path)?;
+    if sync {
+        sync_parent(path)?;
+    }
+    Ok(())
+}
+
+/// Syncs a directory so that files created, renamed or removed in it
+/// survive a crash.
+pub(super) fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// Syncs the directory holding `path`.
+pub(super) fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => sync_dir(dir),
+        _ => sync_dir(Path::new(".")),
+    }
+}
@@ -0,0 +1,681 @@
+//! A table backed by a JSONLT file on disk.
+
+use super::durability::sync_parent;
+use super::{replace_file, Compression, Decoder, Durability, Encoder, StreamReader, StreamWriter};
+use crate::error::ResultExt;
+use crate::json;
+use crate::line::LineParser;
+use crate::{
+    record_key, to_canonical_string, tombstone_line, ChangeEvent, Entry, Error, Header, Key,
+    KeySpecifier, LogEntry, Record, Result, SubscriptionId, Table,
+};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// The number of bytes before the read position remembered to detect a
+/// file that was rewritten in place.
+const FINGERPRINT_LEN: usize = 128;
+
+/// The result of polling a [`FileTable`] for changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollOutcome {
+    /// The file has not changed since the last poll.
+    Unchanged,
+
+    /// New lines were appended and applied to the table.
+    Appended {
+        /// The number of lines applied.
+        lines: usize,
+    },
+
+    /// The file was replaced or truncated and the table was fully reloaded.
+    Reloaded,
+}
+
+/// The position up to which the file has been read.
+#[derive(Debug, Clone, Default)]
+struct Cursor {
+    /// The number of bytes consumed.
+    offset: u64,
+
+    /// Whether the consumed bytes end with a line terminator.
+    terminated: bool,
+
+    /// Whether the consumed bytes are followed by an incomplete last line.
+    torn: bool,
+
+    /// The last bytes consumed, used to detect rewrites.
+    fingerprint: Vec<u8>,
+
+    /// The size of the file on disk when it was read, used to detect
+    /// changes to compressed files.
+    len: u64,
+
+    /// The inode of the file that was read.
+    #[cfg(unix)]
+    inode: u64,
+}
+
+/// A table loaded from a JSONLT file.
+///
+/// The table can follow the file while another process appends to it:
+/// [`FileTable::poll`] reads only the lines appended since the previous
+/// read, including tombstones, and falls back to a full reload when the
+/// file was compacted or otherwise replaced. Changes are applied through
+/// the table, so subscribers registered with [`FileTable::subscribe`] see
+/// every record that was added, replaced or deleted.
+///
+/// Following works by polling file metadata and contents, so it needs no
+/// platform-specific notification API.
+///
+/// Files ending in `.gz` or `.zst` are compressed; see [`Compression`].
+/// Appends are written as a new gzip member or Zstandard frame, which
+/// readers decode as part of a single stream, and compaction writes a
+/// compressed file again. Since compressed data cannot be read from the
+/// middle, polling a compressed file reloads it whenever its size changes.
+///
+/// Appends are not synced to disk by default; see [`Durability`] and
+/// [`FileTable::set_durability`].
+#[derive(Debug)]
+pub struct FileTable {
+    path: PathBuf,
+    compression: Compression,
+    key: Option<KeySpecifier>,
+    parser: LineParser,
+    table: Table,
+    cursor: Cursor,
+    durability: Durability,
+    /// The number of lines appended since the file was last synced.
+    unsynced: usize,
+    /// When the file was last synced, or opened.
+    synced_at: Instant,
+}
+
+impl FileTable {
+    /// Opens a JSONLT file whose header declares the key specifier.
+    ///
+    /// A last line that is still being appended by another process is left
+    /// for [`FileTable::poll`] to pick up once it is complete.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is not valid JSONLT, or
+    /// has records but no header declaring a key.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::load(path.as_ref().to_path_buf(), None)
+    }
+
+    /// Opens a JSONLT file using the given key specifier.
+    ///
+    /// If the file has a header declaring a key, it must match `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is not valid JSONLT, or
+    /// declares a different key.
+    pub fn open_with_key<P: AsRef<Path>>(path: P, key: impl Into<KeySpecifier>) -> Result<Self> {
+        let key = key.into();
+        key.validate()?;
+        Self::load(path.as_ref().to_path_buf(), Some(key))
+    }
+
+    /// Creates a new JSONLT file with a header declaring `key`.
+    ///
+    /// The file is compressed if its extension calls for it. The file and
+    /// its directory are synced, so the table exists after a crash.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key specifier is invalid, the file already
+    /// exists or it cannot be written.
+    pub fn create<P: AsRef<Path>>(path: P, key: impl Into<KeySpecifier>) -> Result<Self> {
+        let key = key.into();
+        key.validate()?;
+        let path = path.as_ref();
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(Error::from)
+            .and_then(|file| {
+                let encoder = Encoder::new(file, Compression::from_path(path))?;
+                let mut writer = StreamWriter::new(encoder, key.clone());
+                writer.write_header(&Header::new(key.clone()))?;
+                writer.finish()?.sync_all()?;
+                Ok(sync_parent(path)?)
+            })
+            .context("create", path)?;
+        Self::load(path.to_path_buf(), Some(key))
+    }
+
+    fn load(path: PathBuf, key: Option<KeySpecifier>) -> Result<Self> {
+        let mut this = Self {
+            compression: Compression::from_path(&path),
+            path,
+            parser: LineParser::new(key.clone()),
+            key,
+            table: Table::new(),
+            cursor: Cursor {
+                terminated: true,
+                ..Cursor::default()
+            },
+            durability: Durability::default(),
+            unsynced: 0,
+            synced_at: Instant::now(),
+        };
+        this.read_all().context("read", &this.path)?;
+        Ok(this)
+    }
+
+    /// Reads the whole file into the freshly created table.
+    ///
+    /// A last line without a terminator is only applied if it is complete
+    /// JSON. Otherwise it is still being appended, and like in a poll it is
+    /// left for a later one.
+    fn read_all(&mut self) -> Result<()> {
+        let file = File::open(&self.path)?;
+        let metadata = file.metadata()?;
+        let mut bytes = Vec::new();
+        Decoder::new(file, self.compression)?.read_to_end(&mut bytes)?;
+        let tail = bytes
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(&bytes[..], |newline| &bytes[newline + 1..]);
+        let complete = std::str::from_utf8(tail).is_ok_and(|tail| json::from_str(tail).is_ok());
+        self.consume(&bytes, tail.is_empty() || complete)?;
+        self.cursor.torn = self.cursor.offset < bytes.len() as u64;
+        self.cursor.len = metadata.len();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            self.cursor.inode = metadata.ino();
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the compression of the file.
+    #[must_use]
+    pub const fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Returns when appends to the file are synced.
+    #[must_use]
+    pub const fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    /// Sets when appends to the file are synced.
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+    }
+
+    /// Syncs the lines appended so far to disk, whatever the durability.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be synced.
+    pub fn sync(&mut self) -> Result<()> {
+        OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .and_then(|file| file.sync_data())
+            .context("sync", &self.path)?;
+        self.unsynced = 0;
+        self.synced_at = Instant::now();
+        Ok(())
+    }
+
+    /// Syncs the lines appended so far if [`Durability::Group`] says they
+    /// are due, without appending. Returns `true` if the file was synced.
+    ///
+    /// Appends only sync a group when they are made, so call this
+    /// periodically to sync the last group of a burst of appends.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be synced.
+    pub fn sync_if_due(&mut self) -> Result<bool> {
+        if !self
+            .durability
+            .syncs_idle(self.unsynced, self.synced_at.elapsed())
+        {
+            return Ok(false);
+        }
+        self.sync()?;
+        Ok(true)
+    }
+
+    /// Returns the number of lines appended since the file was last synced.
+    #[must_use]
+    pub const fn unsynced(&self) -> usize {
+        self.unsynced
+    }
+
+    /// Returns the header of the file, if it has one.
+    #[must_use]
+    pub const fn header(&self) -> Option<&Header> {
+        self.parser.header()
+    }
+
+    /// Returns the key specifier of the table.
+    #[must_use]
+    pub const fn key_specifier(&self) -> Option<&KeySpecifier> {
+        self.parser.key_specifier()
+    }
+
+    /// Returns the table holding the current state of the file.
+    #[must_use]
+    pub const fn table(&self) -> &Table {
+        &self.table
+    }
+
+    /// Returns every version of `key` in the file, oldest first, including
+    /// tombstones.
+    ///
+    /// See [`StreamReader::history`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not valid JSONLT.
+    pub fn history(&self, key: impl Into<Key>) -> Result<Vec<LogEntry>> {
+        self.stream()?.history(&key.into())
+    }
+
+    /// Reconstructs the table as it was after line `line` of the file was
+    /// written.
+    ///
+    /// See [`StreamReader::as_of`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not valid JSONLT.
+    pub fn as_of(&self, line: u64) -> Result<Table> {
+        self.stream()?.as_of(line)
+    }
+
+    fn stream(&self) -> Result<StreamReader<BufReader<Decoder<File>>>> {
+        let reader = BufReader::new(Decoder::new(File::open(&self.path)?, self.compression)?);
+        Ok(match self.key_specifier() {
+            Some(key) => StreamReader::with_key(reader, key.clone()),
+            None => StreamReader::new(reader),
+        })
+    }
+
+    /// Appends a record to the file, replacing any record with the same key.
+    ///
+    /// Lines appended by other writers are picked up first, so the record is
+    /// always written after them. An incomplete last line, such as one left
+    /// by a crash during an earlier append, is cut off first. Returns the key
+    /// of the record.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the table has no key specifier, the record is not
+    /// an object with a valid key, or the file cannot be written. A
+    /// compressed file ending in an incomplete line cannot be appended to.
+    pub fn put(&mut self, record: &Record) -> Result<Key> {
+        let key = self.require_key()?;
+        let object = record
+            .value()
+            .as_object()
+            .ok_or_else(|| Error::invalid_format("record must be a JSON object"))?;
+        let record_key = record_key(object, key)?;
+        self.append(&[to_canonical_string(record.value())], false)?;
+        Ok(record_key)
+    }
+
+    /// Appends a tombstone deleting `key`.
+    ///
+    /// Nothing is written if the key is not present. Returns `true` if a
+    /// record was deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the table has no key specifier, the key does not
+    /// match it, or the file cannot be written.
+    pub fn delete(&mut self, key: impl Into<Key>) -> Result<bool> {
+        let key = key.into();
+        self.poll()?;
+        if !self.table.contains_key(&key) {
+            return Ok(false);
+        }
+        let line = tombstone_line(self.require_key()?, &key)?;
+        self.append(&[line], false)?;
+        Ok(true)
+    }
+
+    /// Rewrites the file with only the live records, in key order.
+    ///
+    /// The header is preserved. The compacted file is written next to the
+    /// original and renamed over it, so readers see either the old or the
+    /// new file, never a partial one. It keeps the permissions of the
+    /// original. Unless the durability is [`Durability::None`], the
+    /// compacted file is synced before the rename and the directory after
+    /// it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the table has no key specifier or the file cannot
+    /// be written.
+    pub fn compact(&mut self) -> Result<()> {
+        self.poll()?;
+        let sync = self.durability.syncs_commit();
+        replace_file(&self.path, ".compact", sync, |file| {
+            self.write_compacted(file, self.compression)
+        })
+        .context("compact", &self.path)?;
+        self.unsynced = 0;
+        self.synced_at = Instant::now();
+        self.reload()
+    }
+
+    /// Writes the live records, in key order, to a new file at `path`,
+    /// leaving this file untouched.
+    ///
+    /// The new file is compressed according to its extension, so this can
+    /// archive a table as a compacted `.jsonlt.gz` or `.jsonlt.zst` file.
+    /// The header is preserved, and the new file is synced like a
+    /// compacted one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the table has no key specifier or the file cannot
+    /// be written.
+    pub fn compact_to<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.poll()?;
+        let path = path.as_ref();
+        File::create(path)
+            .map_err(Error::from)
+            .and_then(|file| self.write_compacted(file, Compression::from_path(path)))
+            .and_then(|file| {
+                if self.durability.syncs_commit() {
+                    file.sync_all()?;
+                    sync_parent(path)?;
+                }
+                Ok(())
+            })
+            .context("create", path)
+    }
+
+    /// Writes the header and the live records, in key order, to `file` and
+    /// returns it.
+    fn write_compacted(&self, file: File, compression: Compression) -> Result<File> {
+        let key = self.require_key()?.clone();
+        let mut writer = StreamWriter::new(Encoder::new(file, compression)?, key);
+        if let Some(header) = self.header() {
+            writer.write_header(header)?;
+        }
+        writer.write_table(&self.table)?;
+        writer.finish()
+    }
+
+    fn require_key(&self) -> Result<&KeySpecifier> {
+        self.key_specifier().ok_or_else(|| {
+            Error::invalid_format("no key specifier: the file has no header declaring one")
+        })
+    }
+
+    /// Appends lines after picking up concurrent appends, syncing them as
+    /// the durability asks for appends, or for transactions if `commit` is
+    /// set.
+    ///
+    /// An incomplete last line is truncated first, since the appended lines
+    /// would otherwise run on from it.
+    pub(crate) fn append(&mut self, lines: &[String], commit: bool) -> Result<()> {
+        self.poll()?;
+        let torn = self.cursor.torn;
+        if torn && self.compression.is_compressed() {
+            return Err(Error::invalid_format(
+                "the file ends in an incomplete line, which cannot be cut from compressed data",
+            )
+            .context("append to", &self.path));
+        }
+        let mut bytes = Vec::new();
+        if !self.cursor.terminated {
+            bytes.push(b'\n');
+        }
+        let start = bytes.len();
+        let unsynced = self.unsynced + lines.len();
+        let sync = if commit {
+            self.durability.syncs_commit()
+        } else {
+            self.durability
+                .syncs_append(unsynced, self.synced_at.elapsed())
+        };
+        for line in lines {
+            bytes.extend_from_slice(line.as_bytes());
+            bytes.push(b'\n');
+        }
+        self.cursor.len = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(Error::from)
+            .and_then(|file| {
+                if torn {
+                    file.set_len(self.cursor.offset)?;
+                }
+                let mut encoder = Encoder::new(file, self.compression)?;
+                encoder.write_all(&bytes)?;
+                let file = encoder.finish()?;
+                if sync {
+                    file.sync_data()?;
+                }
+                Ok(file.metadata()?.len())
+            })
+            .context("append to", &self.path)?;
+        self.cursor.torn = false;
+        if sync {
+            self.unsynced = 0;
+            self.synced_at = Instant::now();
+        } else {
+            self.unsynced = unsynced;
+        }
+        if !self.cursor.terminated {
+            self.advance(b"\n");
+        }
+        self.consume(&bytes[start..], false)?;
+        Ok(())
+    }
+
+    /// Registers a callback invoked with every change picked up from the
+    /// file.
+    ///
+    /// See [`Table::subscribe`].
+    pub fn subscribe<F>(&mut self, callback: F) -> SubscriptionId
+    where
+        F: Fn(&ChangeEvent) + Send + Sync + 'static,
+    {
+        self.table.subscribe(callback)
+    }
+
+    /// Returns a channel receiving every change picked up from the file.
+    ///
+    /// See [`Table::subscribe_channel`].
+    pub fn subscribe_channel(&mut self) -> (SubscriptionId, mpsc::Receiver<ChangeEvent>) {
+        self.table.subscribe_channel()
+    }
+
+    /// Removes a subscription.
+    ///
+    /// Returns `true` if the subscription was registered.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.table.unsubscribe(id)
+    }
+
+    /// Rereads the whole file.
+    ///
+    /// Subscribers are notified of the differences between the previous
+    /// state and the reloaded one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not valid JSONLT.
+    /// The table is left unchanged in that case.
+    pub fn reload(&mut self) -> Result<()> {
+        let fresh = Self::load(self.path.clone(), self.key.clone())?;
+        let stale: Vec<_> = self
+            .table
+            .keys()
+            .filter(|key| !fresh.table.contains_key(*key))
+            .cloned()
+            .collect();
+        for key in stale {
+            self.table.remove(&key);
+        }
+        for (key, record) in fresh.table.iter() {
+            if self.table.get(key) != Some(record) {
+                self.table.insert(key.clone(), record.clone());
+            }
+        }
+        self.parser = fresh.parser;
+        self.cursor = fresh.cursor;
+        Ok(())
+    }
+
+    /// Applies the lines appended to the file since it was last read.
+    ///
+    /// Only complete lines are applied; a partially written last line is
+    /// picked up by a later poll once its terminator has been written. If
+    /// the file was truncated, replaced or rewritten, the table is reloaded
+    /// from scratch.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or an appended line is
+    /// not valid JSONLT. Lines before the invalid one are still applied.
+    pub fn poll(&mut self) -> Result<PollOutcome> {
+        let path = self.path.clone();
+        self.poll_appended().context("read", &path)
+    }
+
+    fn poll_appended(&mut self) -> Result<PollOutcome> {
+        let mut file = File::open(&self.path)?;
+        let metadata = file.metadata()?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if metadata.ino() != self.cursor.inode {
+                self.reload()?;
+                return Ok(PollOutcome::Reloaded);
+            }
+        }
+        if self.compression.is_compressed() {
+            if metadata.len() == self.cursor.len {
+                return Ok(PollOutcome::Unchanged);
+            }
+            self.reload()?;
+            return Ok(PollOutcome::Reloaded);
+        }
+        if metadata.len() < self.cursor.offset {
+            self.reload()?;
+            return Ok(PollOutcome::Reloaded);
+        }
+
+        let fingerprint_len = self.cursor.fingerprint.len();
+        file.seek(SeekFrom::Start(self.cursor.offset - fingerprint_len as u64))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        if bytes.len() < fingerprint_len || bytes[..fingerprint_len] != self.cursor.fingerprint {
+            self.reload()?;
+            return Ok(PollOutcome::Reloaded);
+        }
+
+        let appended = &bytes[fingerprint_len..];
+        if appended.is_empty() {
+            return Ok(PollOutcome::Unchanged);
+        }
+        if !self.cursor.terminated {
+            // The last line was read before its terminator was written.
+            self.reload()?;
+            return Ok(PollOutcome::Reloaded);
+        }
+        let end = self.cursor.offset + appended.len() as u64;
+        let lines = self.consume(appended, false)?;
+        self.cursor.torn = self.cursor.offset < end;
+        match lines {
+            0 => Ok(PollOutcome::Unchanged),
+            lines => Ok(PollOutcome::Appended { lines }),
+        }
+    }
+
+    /// Polls the file repeatedly, sleeping for `interval` between polls.
+    ///
+    /// `on_poll` is called after every poll and stops following by
+    /// returning [`ControlFlow::Break`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error encountered while polling.
+    pub fn follow<F>(&mut self, interval: Duration, mut on_poll: F) -> Result<()>
+    where
+        F: FnMut(&Self, PollOutcome) -> ControlFlow<()>,
+    {
+        loop {
+            let outcome = self.poll()?;
+            if on_poll(self, outcome).is_break() {
+                return Ok(());
+            }
+            std::thread::sleep(interval);
+        }
+    }
+
+    /// Parses and applies the complete lines in `bytes`, which start at the
+    /// current cursor.
+    ///
+    /// When `to_end` is set, a final line without a terminator is applied as
+    /// well. Returns the number of lines applied.
+    fn consume(&mut self, bytes: &[u8], to_end: bool) -> Result<usize> {
+        let mut applied = 0;
+        let mut start = 0;
+        while start < bytes.len() {
+            let end = match bytes[start..].iter().position(|&b| b == b'\n') {
+                Some(newline) => start + newline + 1,
+                None if to_end => bytes.len(),
+                None => break,
+            };
+            let offset = Some(self.cursor.offset);
+            let line = std::str::from_utf8(&bytes[start..end]).map_err(|_| {
+                Error::invalid_format("invalid UTF-8")
+                    .at(Some(self.parser.line_number() + 1), offset)
+            })?;
+            match self
+                .parser
+                .parse(line)
+                .map_err(|err| err.at(None, offset))?
+            {
+                Some(Entry::Record { key, record }) => self.table.insert(key, record),
+                Some(Entry::Tombstone { key }) => {
+                    self.table.remove(&key);
+                }
+                None => {}
+            }
+            self.advance(&bytes[start..end]);
+            applied += 1;
+            start = end;
+        }
+        Ok(applied)
+    }
+
+    /// Moves the cursor past `line`.
+    fn advance(&mut self, line: &[u8]) {
+        self.cursor.offset += line.len() as u64;
+        self.cursor.terminated = line.ends_with(b"\n");
+        let fingerprint = &mut self.cursor.fingerprint;
+        fingerprint.extend_from_slice(line);
+        if fingerprint.len() > FINGERPRINT_LEN {
+            fingerprint.drain(..fingerprint.len() - FINGERPRINT_LEN);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! The sidecar index file of a lazily loaded table.
+//!
+//! The index is a JSON Lines file. The first line describes the prefix of
+//! the table file it covers:
+//!
+//! ```text
+//! {"$jsonlt-index":{"file":[2049,1835015],"hash":"9f1c…(64 hex digits)","head":"0f3a9c4e7d21b856","key":"id","length":4096,"lines":52,"tail":"0f3a9c4e7d21b856","version":2}}
+//! ```
+//!
+//! Every other line holds the key of a live record followed by the byte
+//! offset and length of its line, such as `["alice",45,30]`.
+
+use crate::{to_canonical_string, Key, KeySpecifier, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, File, Metadata};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The field naming the header line of an index file.
+const INDEX_FIELD: &str = "$jsonlt-index";
+
+/// The version of the index file format.
+const INDEX_VERSION: u64 = 2;
+
+/// The location of a record line in the table file, without its terminator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub offset: u64,
+    pub len: usize,
+}
+
+/// A hash of the bytes of a file prefix, stable across platforms and
+/// releases.
+///
+/// This is 64-bit FNV-1a run over four interleaved lanes of bytes, which
+/// lets the lanes be computed in parallel, then combined.
+#[derive(Debug, Clone)]
+pub struct PrefixHash {
+    lanes: [u64; 4],
+    len: u64,
+}
+
+impl PrefixHash {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    pub const fn new() -> Self {
+        Self {
+            lanes: [Self::OFFSET_BASIS; 4],
+            len: 0,
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        let mut lane = (self.len % 4) as usize;
+        for &byte in bytes {
+            self.lanes[lane] = (self.lanes[lane] ^ u64::from(byte)).wrapping_mul(Self::PRIME);
+            lane = (lane + 1) % 4;
+        }
+        self.len += bytes.len() as u64;
+    }
+
+    pub fn finish(&self) -> u64 {
+        let mut hash = Self::OFFSET_BASIS;
+        for value in self.lanes.iter().copied().chain([self.len]) {
+            for byte in value.to_le_bytes() {
+                hash = (hash ^ u64::from(byte)).wrapping_mul(Self::PRIME);
+            }
+        }
+        hash
+    }
+
+    /// Returns the state of the hash in hexadecimal, from which it can be
+    /// resumed.
+    pub fn state(&self) -> String {
+        let [a, b, c, d] = self.lanes;
+        format!("{a:016x}{b:016x}{c:016x}{d:016x}")
+    }
+
+    /// Resumes a hash of `len` bytes from its [`state`](Self::state).
+    pub fn resume(state: &str, len: u64) -> Option<Self> {
+        if state.len() != 64 || !state.is_ascii() {
+            return None;
+        }
+        let mut lanes = [0; 4];
+        for (lane, hex) in lanes.iter_mut().zip(state.as_bytes().chunks(16)) {
+            *lane = u64::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?;
+        }
+        Some(Self { lanes, len })
+    }
+}
+
+/// The header line of an index file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Coverage {
+    /// The key specifier the keys were extracted with.
+    pub key: KeySpecifier,
+
+    /// The length in bytes of the covered prefix, which ends with a line
+    /// terminator.
+    pub length: u64,
+
+    /// The number of lines in the covered prefix.
+    pub lines: u64,
+
+    /// The [state](PrefixHash::state) of the [`PrefixHash`] of the covered
+    /// prefix.
+    pub hash: String,
+
+    /// The [`PrefixHash`] of the first [`WINDOW`] bytes of the covered
+    /// prefix, in hexadecimal.
+    pub head: String,
+
+    /// The [`PrefixHash`] of the last [`WINDOW`] bytes of the covered
+    /// prefix, in hexadecimal.
+    pub tail: String,
+
+    /// The device and inode of the table file, where the platform has them.
+    pub file: Option<(u64, u64)>,
+
+    /// The version of the index file format.
+    pub version: u64,
+}
+
+impl Coverage {
+    /// Describes a prefix of `length` bytes and `lines` lines with the given
+    /// hash, window hashes and file identity.
+    pub fn new(
+        key: KeySpecifier,
+        (length, lines): (u64, u64),
+        hash: &PrefixHash,
+        [head, tail]: [String; 2],
+        file: Option<(u64, u64)>,
+    ) -> Self {
+        Self {
+            key,
+            length,
+            lines,
+            hash: hash.state(),
+            head,
+            tail,
+            file,
+            version: INDEX_VERSION,
+        }
+    }
+}
+
+/// The number of bytes at each end of the covered prefix whose hashes are
+/// checked before an index is used.
+pub const WINDOW: usize = 64 * 1024;
+
+/// Returns the device and inode of a file, so that an index is not used for
+/// a file that was replaced since, as compaction does.
+#[allow(clippy::unnecessary_wraps)] // Files have no inode outside Unix
+pub fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some((metadata.dev(), metadata.ino()))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        None
+    }
+}
+
+/// Returns the path of the index file of a table file.
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut index = path.as_os_str().to_owned();
+    index.push(".idx");
+    PathBuf::from(index)
+}
+
+/// Reads an index file.
+///
+/// Returns `None` if the file does not exist or is not a well-formed index,
+/// so that the caller falls back to scanning the table.
+pub fn read(path: &Path) -> Option<(Coverage, Vec<(Key, Span)>)> {
+    let mut lines = BufReader::new(File::open(path).ok()?).lines();
+    let header: Value = serde_json::from_str(&lines.next()?.ok()?).ok()?;
+    let coverage: Coverage = serde_json::from_value(header.get(INDEX_FIELD)?.clone()).ok()?;
+    if coverage.version != INDEX_VERSION {
+        return None;
+    }
+    let mut entries = Vec::new();
+    for line in lines {
+        let (key, offset, len): (Key, u64, usize) = serde_json::from_str(&line.ok()?).ok()?;
+        if offset.checked_add(len as u64)? >= coverage.length {
+            return None;
+        }
+        entries.push((key, Span { offset, len }));
+    }
+    Some((coverage, entries))
+}
+
+/// Writes an index file, replacing any existing one.
+///
+/// The index is written next to its final location and renamed over it, so
+/// readers never see a partial index.
+pub fn write<'a, I>(path: &Path, coverage: &Coverage, entries: I) -> Result<()>
+where
+    I: IntoIterator<Item = (&'a Key, Span)>,
+{
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    let written = File::create(&temp).map_err(Into::into).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        let mut header = serde_json::Map::new();
+        header.insert(INDEX_FIELD.into(), serde_json::to_value(coverage)?);
+        writeln!(writer, "{}", to_canonical_string(&Value::Object(header)))?;
+        for (key, span) in entries {
+            let entry = Value::Array(vec![key.to_value(), span.offset.into(), span.len.into()]);
+            writeln!(writer, "{}", to_canonical_string(&entry))?;
+        }
+        writer
+            .into_inner()
+            .map_err(std::io::IntoInnerError::into_error)?
+            .sync_all()?;
+        Ok(())
+    });
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(err);
+    }
+    fs::rename(&temp, path)?;
+    Ok(())
+}
@@ -0,0 +1,568 @@
+//! A table that parses records from its file on demand.
+
+use super::index::{self, Coverage, PrefixHash, Span, WINDOW};
+use super::Compression;
+use crate::error::ResultExt;
+use crate::line::LineParser;
+use crate::{record_key, Error, Header, Key, KeySpecifier, Record, Result};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// The default number of parsed records kept by a [`LazyTable`].
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+/// Options for opening a [`LazyTable`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LazyOptions {
+    /// The key specifier to use. If the file has a header declaring a key,
+    /// it must match.
+    pub key: Option<KeySpecifier>,
+
+    /// The number of parsed records kept in the cache.
+    pub cache_capacity: usize,
+
+    /// Whether to start from the sidecar index written by
+    /// [`LazyTable::save_index`], if there is a valid one.
+    pub use_index: bool,
+
+    /// Whether to hash the whole part of the file covered by the sidecar
+    /// index before using it. Turning this off only compares its first and
+    /// last 64 KiB, which is faster but misses edits in between that keep
+    /// the length of the file.
+    pub verify_index: bool,
+}
+
+impl Default for LazyOptions {
+    fn default() -> Self {
+        Self {
+            key: None,
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            use_index: true,
+            verify_index: true,
+        }
+    }
+}
+
+/// The state of a scan over the lines of a file.
+#[derive(Debug)]
+struct Scan {
+    parser: LineParser,
+    index: HashMap<Key, Span>,
+    hash: PrefixHash,
+    /// The length of the prefix of complete lines scanned so far.
+    covered: u64,
+    /// The number of lines in the covered prefix.
+    lines: u64,
+    /// The key of an unterminated last line, with its span before that line.
+    pending: Option<(Key, Option<Span>)>,
+    /// The length of the prefix taken from the sidecar index.
+    from_index: u64,
+}
+
+impl Scan {
+    fn new(key: Option<KeySpecifier>) -> Self {
+        Self {
+            parser: LineParser::new(key),
+            index: HashMap::new(),
+            hash: PrefixHash::new(),
+            covered: 0,
+            lines: 0,
+            pending: None,
+            from_index: 0,
+        }
+    }
+
+    /// Resumes from the sidecar index at `path`, checking that the prefix of
+    /// the file it covers is unchanged.
+    ///
+    /// The check compares the length and identity of the file and the hashes
+    /// of the first and last [`WINDOW`] bytes of the prefix, or with
+    /// `verify` the hash of the whole prefix.
+    ///
+    /// Returns `None` if there is no usable index, leaving `reader` at an
+    /// unspecified position.
+    fn resume(
+        reader: &mut BufReader<&File>,
+        key: Option<KeySpecifier>,
+        path: &Path,
+        verify: bool,
+    ) -> Result<Option<Self>> {
+        let Some((coverage, entries)) = index::read(path) else {
+            return Ok(None);
+        };
+        let file = *reader.get_ref();
+        let metadata = file.metadata()?;
+        if coverage.lines == 0
+            || coverage.length > metadata.len()
+            || coverage.file != index::file_id(&metadata)
+        {
+            return Ok(None);
+        }
+        let Some(hash) = PrefixHash::resume(&coverage.hash, coverage.length) else {
+            return Ok(None);
+        };
+        let mut scan = Self::new(key);
+
+        // The header determines the key specifier the index must match.
+        let mut buffer = Vec::new();
+        reader
+            .take(coverage.length)
+            .read_until(b'\n', &mut buffer)?;
+        if !buffer.ends_with(b"\n") {
+            return Ok(None);
+        }
+        let Ok(line) = std::str::from_utf8(&buffer) else {
+            return Ok(None);
+        };
+        if scan.parser.parse_key(line).is_err()
+            || scan.parser.key_specifier() != Some(&coverage.key)
+        {
+            return Ok(None);
+        }
+
+        let unchanged = if verify {
+            reader.rewind()?;
+            let mut prefix = reader.take(coverage.length);
+            let mut full = PrefixHash::new();
+            loop {
+                let bytes = prefix.fill_buf()?;
+                if bytes.is_empty() {
+                    break;
+                }
+                full.update(bytes);
+                let read = bytes.len();
+                prefix.consume(read);
+            }
+            full.state() == coverage.hash
+        } else {
+            windows(file, coverage.length)? == [coverage.head, coverage.tail]
+        };
+        if !unchanged {
+            return Ok(None);
+        }
+
+        reader.seek(SeekFrom::Start(coverage.length))?;
+        scan.hash = hash;
+        scan.parser.skip_lines(coverage.lines - 1);
+        scan.index = entries.into_iter().collect();
+        scan.covered = coverage.length;
+        scan.lines = coverage.lines;
+        scan.from_index = coverage.length;
+        Ok(Some(scan))
+    }
+
+    /// Scans the lines from the current position of `reader` to the end of
+    /// the file.
+    fn run(&mut self, reader: &mut BufReader<&File>) -> Result<()> {
+        let mut buffer = Vec::new();
+        loop {
+            buffer.clear();
+            let read = reader.read_until(b'\n', &mut buffer)?;
+            if read == 0 {
+                return Ok(());
+            }
+            let at = Some(self.covered);
+            let line = std::str::from_utf8(&buffer).map_err(|_| {
+                Error::invalid_format("invalid UTF-8").at(Some(self.parser.line_number() + 1), at)
+            })?;
+            let entry = self
+                .parser
+                .parse_key(line)
+                .map_err(|err| err.at(None, at))?;
+            let terminated = buffer.ends_with(b"\n");
+            if let Some((key, _)) = entry.as_ref().filter(|_| !terminated) {
+                self.pending = Some((key.clone(), self.index.get(key).copied()));
+            }
+            match entry {
+                Some((key, false)) => {
+                    let len = line.trim_end_matches(['\n', '\r']).len();
+                    let offset = self.covered;
+                    self.index.insert(key, Span { offset, len });
+                }
+                Some((key, true)) => {
+                    self.index.remove(&key);
+                }
+                None => {}
+            }
+            if terminated {
+                self.hash.update(&buffer);
+                self.covered += read as u64;
+                self.lines = self.parser.line_number();
+            }
+        }
+    }
+}
+
+/// The most recently used parsed records.
+#[derive(Debug, Default)]
+struct Cache {
+    capacity: usize,
+    records: HashMap<Key, (Arc<Record>, u64)>,
+    recency: BTreeMap<u64, Key>,
+    tick: u64,
+}
+
+impl Cache {
+    fn get(&mut self, key: &Key) -> Option<Arc<Record>> {
+        let (record, used) = self.records.get_mut(key)?;
+        self.recency.remove(used);
+        self.tick += 1;
+        *used = self.tick;
+        self.recency.insert(self.tick, key.clone());
+        Some(Arc::clone(record))
+    }
+
+    fn insert(&mut self, key: Key, record: Arc<Record>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, used)) = self.records.insert(key.clone(), (record, self.tick)) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.tick, key);
+        while self.records.len() > self.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.records.remove(&oldest);
+        }
+    }
+}
+
+/// A read-only table that keeps records unparsed in its file.
+///
+/// Opening the file scans it once to build an index from each live key to
+/// the byte range of its latest record; only the key fields of each line
+/// are parsed. Records are read with positioned reads and parsed when they
+/// are first requested, and the most recently used ones are kept in a
+/// cache. This makes looking up a few keys in a very large file cheap,
+/// where [`FileTable`](super::FileTable) would parse every record up front.
+///
+/// The index reflects the file as it was when opened. Lines appended later
+/// are not seen, and reading a record from a file that was rewritten since
+/// returns an error; open the file again to pick up changes. Compressed
+/// files cannot be read lazily.
+///
+/// Scanning a very large file on every start can be avoided by saving a
+/// sidecar index with [`LazyTable::save_index`]; see
+/// [`LazyTable::open_with`].
+///
+/// The table can be shared between threads.
+///
+/// # Examples
+///
+/// ```rust
+/// use jsonlt::io::LazyTable;
+/// # let dir = tempfile::tempdir().unwrap();
+/// # let path = dir.path().join("users.jsonlt");
+/// # std::fs::write(&path, "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n{\"id\":\"alice\",\"age\":30}\n").unwrap();
+///
+/// let table = LazyTable::open(&path).unwrap();
+/// assert_eq!(table.len(), 1);
+///
+/// let alice = table.get("alice").unwrap().unwrap();
+/// assert_eq!(alice.value()["age"], 30);
+/// ```
+#[derive(Debug)]
+pub struct LazyTable {
+    path: PathBuf,
+    file: File,
+    scan: Scan,
+    cache: Mutex<Cache>,
+}
+
+impl LazyTable {
+    /// Opens and indexes a JSONLT file whose header declares the key
+    /// specifier.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is compressed, is not
+    /// valid JSONLT, or has records but no header declaring a key.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_with(path, &LazyOptions::default())
+    }
+
+    /// Opens and indexes a JSONLT file using the given key specifier.
+    ///
+    /// If the file has a header declaring a key, it must match `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is compressed, is not
+    /// valid JSONLT, or declares a different key.
+    pub fn open_with_key<P: AsRef<Path>>(path: P, key: impl Into<KeySpecifier>) -> Result<Self> {
+        let options = LazyOptions {
+            key: Some(key.into()),
+            ..LazyOptions::default()
+        };
+        Self::open_with(path, &options)
+    }
+
+    /// Opens and indexes a JSONLT file with the given options.
+    ///
+    /// With [`LazyOptions::use_index`], a sidecar index saved by
+    /// [`LazyTable::save_index`] is used if the part of the file it covers is
+    /// unchanged, so only the lines appended since are scanned. An index that
+    /// is missing, stale or was built with a different key specifier is
+    /// ignored and the whole file is scanned.
+    ///
+    /// The part of the file the index covers counts as unchanged if the file
+    /// is still as long and, where the platform tells, the same file, and
+    /// that part hashes the same. Hashing is much cheaper than parsing, but
+    /// still reads the whole part; without [`LazyOptions::verify_index`],
+    /// only its first and last 64 KiB are hashed, so an edit in the middle
+    /// of a large file that keeps its length goes unnoticed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is compressed, is not
+    /// valid JSONLT, or declares a different key.
+    pub fn open_with<P: AsRef<Path>>(path: P, options: &LazyOptions) -> Result<Self> {
+        if let Some(key) = &options.key {
+            key.validate()?;
+        }
+        let path = path.as_ref().to_path_buf();
+        let compression = Compression::from_path(&path);
+        if compression.is_compressed() {
+            return Err(Error::invalid_format(format!(
+                "{compression} files cannot be loaded lazily"
+            )));
+        }
+        let (file, scan) = Self::scan(&path, options).context("read", &path)?;
+        Ok(Self {
+            path,
+            file,
+            scan,
+            cache: Mutex::new(Cache {
+                capacity: options.cache_capacity,
+                ..Cache::default()
+            }),
+        })
+    }
+
+    /// Scans the file, resuming from its index if it has a usable one.
+    fn scan(path: &Path, options: &LazyOptions) -> Result<(File, Scan)> {
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(&file);
+        let resumed = if options.use_index {
+            Scan::resume(
+                &mut reader,
+                options.key.clone(),
+                &index::index_path(path),
+                options.verify_index,
+            )?
+        } else {
+            None
+        };
+        let mut scan = if let Some(scan) = resumed {
+            scan
+        } else {
+            reader.rewind()?;
+            Scan::new(options.key.clone())
+        };
+        scan.run(&mut reader)?;
+        Ok((file, scan))
+    }
+
+    /// Writes a sidecar index next to the file, named after it with an
+    /// `.idx` extension appended, replacing any existing one.
+    ///
+    /// The index records where every live record is, and the length, hashes
+    /// and identity of the part of the file it covers, so that opening the
+    /// file again only scans the lines appended since. An unterminated last
+    /// line is left out of the index, since it may still be completed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the table has no key specifier or the index cannot
+    /// be written.
+    pub fn save_index(&self) -> Result<()> {
+        let key = self
+            .key_specifier()
+            .ok_or_else(|| Error::invalid_format("cannot index a table without a key specifier"))?;
+        let scan = &self.scan;
+        let path = index::index_path(&self.path);
+        let coverage = self
+            .file
+            .metadata()
+            .and_then(|metadata| {
+                Ok(Coverage::new(
+                    key.clone(),
+                    (scan.covered, scan.lines),
+                    &scan.hash,
+                    windows(&self.file, scan.covered)?,
+                    index::file_id(&metadata),
+                ))
+            })
+            .context("read", &self.path)?;
+        let pending = scan.pending.as_ref();
+        let entries = scan
+            .index
+            .iter()
+            .filter(|(key, _)| pending.is_none_or(|(pending, _)| pending != *key))
+            .map(|(key, span)| (key, *span))
+            .chain(pending.and_then(|(key, span)| span.map(|span| (key, span))));
+        index::write(&path, &coverage, entries).context("write", &path)
+    }
+
+    /// Returns the number of bytes at the start of the file whose lines were
+    /// taken from the sidecar index rather than scanned, or zero if no index
+    /// was used.
+    #[must_use]
+    pub const fn index_coverage(&self) -> u64 {
+        self.scan.from_index
+    }
+
+    /// Sets the number of parsed records kept in the cache.
+    ///
+    /// A capacity of zero disables the cache, so every lookup reads and
+    /// parses the record again.
+    #[must_use]
+    pub fn with_cache_capacity(self, capacity: usize) -> Self {
+        let mut cache = self
+            .cache
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        cache.capacity = capacity;
+        cache.records.clear();
+        cache.recency.clear();
+        Self {
+            cache: Mutex::new(cache),
+            ..self
+        }
+    }
+
+    /// Returns the path of the file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the header of the file, if it has one.
+    #[must_use]
+    pub const fn header(&self) -> Option<&Header> {
+        self.scan.parser.header()
+    }
+
+    /// Returns the key specifier of the table.
+    #[must_use]
+    pub const fn key_specifier(&self) -> Option<&KeySpecifier> {
+        self.scan.parser.key_specifier()
+    }
+
+    /// Returns the number of records in the table.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.scan.index.len()
+    }
+
+    /// Returns `true` if the table contains no records.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.scan.index.is_empty()
+    }
+
+    /// Returns `true` if the table contains a record with the given key.
+    ///
+    /// The record is not read.
+    pub fn contains_key(&self, key: impl Into<Key>) -> bool {
+        self.scan.index.contains_key(&key.into())
+    }
+
+    /// Returns an iterator over the keys of the table, in arbitrary order.
+    pub fn keys(&self) -> impl Iterator<Item = &Key> {
+        self.scan.index.keys()
+    }
+
+    /// Returns the record with the given key, reading and parsing it from
+    /// the file unless it is cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record cannot be read, or if the file was
+    /// changed since it was indexed.
+    pub fn get(&self, key: impl Into<Key>) -> Result<Option<Arc<Record>>> {
+        let key = key.into();
+        let Some(span) = self.scan.index.get(&key) else {
+            return Ok(None);
+        };
+        let cached = self.lock_cache().get(&key);
+        if cached.is_some() {
+            return Ok(cached);
+        }
+        let record = Arc::new(self.read(&key, *span).context("read", &self.path)?);
+        self.lock_cache().insert(key, Arc::clone(&record));
+        Ok(Some(record))
+    }
+
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn read(&self, key: &Key, span: Span) -> Result<Record> {
+        let changed =
+            || Error::invalid_format("the file changed since it was indexed; open it again");
+        let mut buffer = vec![0; span.len];
+        match read_exact_at(&self.file, &mut buffer, span.offset) {
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Err(changed()),
+            result => result?,
+        }
+        let Ok(Value::Object(object)) = serde_json::from_slice(&buffer) else {
+            return Err(changed());
+        };
+        let specifier = self.key_specifier().ok_or_else(changed)?;
+        if record_key(&object, specifier).ok().as_ref() != Some(key) {
+            return Err(changed());
+        }
+        Record::try_from_value(Value::Object(object))
+    }
+}
+
+/// Returns the hashes of the first and last [`WINDOW`] bytes of the first
+/// `length` bytes of `file`, in hexadecimal.
+fn windows(file: &File, length: u64) -> std::io::Result<[String; 2]> {
+    let size = usize::try_from(length).map_or(WINDOW, |length| length.min(WINDOW));
+    let mut buffer = vec![0; size];
+    let mut hash = |offset| {
+        read_exact_at(file, &mut buffer, offset)?;
+        let mut hash = PrefixHash::new();
+        hash.update(&buffer);
+        Ok::<_, std::io::Error>(format!("{:016x}", hash.finish()))
+    };
+    Ok([hash(0)?, hash(length - size as u64)?])
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buffer: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buffer, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buffer: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buffer.is_empty() {
+        match file.seek_read(buffer, offset)? {
+            0 => return Err(ErrorKind::UnexpectedEof.into()),
+            read => {
+                buffer = &mut buffer[read..];
+                offset += read as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn read_exact_at(mut file: &File, buffer: &mut [u8], offset: u64) -> std::io::Result<()> {
+    // Without positioned reads, concurrent lookups could interleave their
+    // seeks; such platforms have no threads sharing files in practice.
+    use std::io::{Read, Seek, SeekFrom};
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buffer)
+}
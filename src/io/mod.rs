@@ -1,112 +1,55 @@
-//! Async I/O operations for JSONLT tables.
+//! File I/O for JSONLT tables.
 //!
-//! This module provides async file I/O operations using tokio.
-
-use crate::{Result, Table};
-use std::path::Path;
-use tokio::fs::File;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
-
-/// Reads a JSONLT table from a file asynchronously.
-///
-/// # Errors
-///
-/// Returns an error if the file cannot be read or contains invalid JSON.
-#[allow(clippy::unused_async)] // TODO: Implement async file reading
-pub async fn read_table<P: AsRef<Path>>(_path: P) -> Result<Table> {
-    Ok(Table::new())
-}
-
-/// Writes a JSONLT table to a file asynchronously.
-///
-/// # Errors
-///
-/// Returns an error if the file cannot be written.
-#[allow(clippy::unused_async)] // TODO: Implement async file writing
-pub async fn write_table<P: AsRef<Path>>(_path: P, _table: &Table) -> Result<()> {
-    Ok(())
-}
-
-/// A reader for JSONLT files.
-pub struct TableReader<R> {
-    reader: BufReader<R>,
-}
-
-impl<R: tokio::io::AsyncRead + Unpin> TableReader<R> {
-    /// Creates a new table reader.
-    pub fn new(reader: R) -> Self {
-        Self {
-            reader: BufReader::new(reader),
-        }
-    }
-
-    /// Reads the next line from the file.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the line cannot be read.
-    pub async fn read_line(&mut self) -> Result<Option<String>> {
-        let mut line = String::new();
-        let bytes_read = self.reader.read_line(&mut line).await?;
-        if bytes_read == 0 {
-            Ok(None)
-        } else {
-            Ok(Some(line))
-        }
-    }
-}
-
-/// A writer for JSONLT files.
-pub struct TableWriter<W> {
-    writer: BufWriter<W>,
-}
-
-impl<W: tokio::io::AsyncWrite + Unpin> TableWriter<W> {
-    /// Creates a new table writer.
-    pub fn new(writer: W) -> Self {
-        Self {
-            writer: BufWriter::new(writer),
-        }
-    }
-
-    /// Writes a line to the file.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the line cannot be written.
-    pub async fn write_line(&mut self, line: &str) -> Result<()> {
-        self.writer.write_all(line.as_bytes()).await?;
-        self.writer.write_all(b"\n").await?;
-        Ok(())
-    }
-
-    /// Flushes the writer.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the writer cannot be flushed.
-    pub async fn flush(&mut self) -> Result<()> {
-        self.writer.flush().await?;
-        Ok(())
-    }
-}
-
-/// Opens a JSONLT file for reading.
-///
-/// # Errors
-///
-/// Returns an error if the file cannot be opened.
-pub async fn open<P: AsRef<Path>>(path: P) -> Result<TableReader<File>> {
-    let file = File::open(path).await?;
-    Ok(TableReader::new(file))
-}
-
-/// Creates a JSONLT file for writing.
-///
-/// # Errors
-///
-/// Returns an error if the file cannot be created.
-pub async fn create<P: AsRef<Path>>(path: P) -> Result<TableWriter<File>> {
-    let file = File::create(path).await?;
-    Ok(TableWriter::new(file))
-}
+//! [`FileTable`] keeps a table in sync with a JSONLT file on disk, while
+//! [`StreamReader`] and [`StreamWriter`] process files one line at a time
+//! without loading them into memory. [`LazyTable`] indexes a large file and
+//! parses only the records that are looked up, and [`Database`] treats a
+//! directory of files as tables written together in transactions. Plain
+//! JSON arrays and JSON Lines can be converted to and from tables with
+//! [`read_json_array`], [`read_jsonl`] and the `write_*` functions. With the
+//! `async` feature, this module also provides async readers and writers
+//! using tokio.
+//!
+//! Files ending in `.gz` or `.zst` are compressed and decompressed
+//! transparently when the `compression` feature is enabled; see
+//! [`Compression`]. How often writes are synced to disk is set with
+//! [`Durability`].
+
+#[cfg(feature = "async")]
+mod async_io;
+mod compress;
+#[cfg(feature = "serde")]
+mod convert;
+mod database;
+mod durability;
+mod file;
+#[cfg(feature = "serde")]
+mod index;
+#[cfg(feature = "serde")]
+mod lazy;
+mod stream;
+
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub use async_io::{create, open, read_table, write_table, TableReader, TableWriter};
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub use async_io::{AsyncStreamReader, AsyncStreamWriter};
+pub use compress::{Compression, Decoder, Encoder};
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub use convert::{read_json_array, read_jsonl, write_json_array, write_json_object, write_jsonlt};
+pub use database::{Database, Transaction};
+pub(crate) use durability::replace_file;
+pub use durability::Durability;
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub use durability::{simulate_crash, CrashPoint};
+pub use file::{FileTable, PollOutcome};
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub use lazy::{LazyOptions, LazyTable, DEFAULT_CACHE_CAPACITY};
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub use stream::RawEntry;
+pub use stream::{StreamReader, StreamWriter};
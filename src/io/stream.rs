@@ -0,0 +1,514 @@
+//! Streaming readers and writers for JSONLT files.
+
+use super::{Compression, Decoder, Encoder};
+use crate::error::ResultExt;
+use crate::line::LineParser;
+#[cfg(feature = "serde")]
+use crate::RecordRef;
+use crate::{
+    record_key, to_canonical_string, tombstone_line, Entry, Error, Header, Key, KeySpecifier,
+    LogEntry, Record, Result, Table,
+};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// A record or tombstone borrowed from the line buffer of a
+/// [`StreamReader`].
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+#[derive(Debug, Clone)]
+pub struct RawEntry<'a> {
+    /// The one-based line number of the entry.
+    pub line: u64,
+
+    /// The key of the record or tombstone.
+    pub key: Key,
+
+    /// The unparsed record, or `None` for a tombstone.
+    pub record: Option<RecordRef<'a>>,
+}
+
+/// Reads the entries of a JSONLT file one line at a time.
+///
+/// Unlike loading a [`Table`](crate::Table), the reader never holds more than
+/// one line in memory, so it can scan files much larger than the available
+/// memory. Every record and tombstone is yielded in file order together with
+/// its line number; the header is consumed and exposed through
+/// [`StreamReader::header`].
+///
+/// # Examples
+///
+/// ```rust
+/// use jsonlt::io::StreamReader;
+///
+/// let data = "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n{\"id\":\"alice\"}\n";
+/// let mut reader = StreamReader::new(data.as_bytes());
+///
+/// let entry = reader.next().unwrap().unwrap();
+/// assert_eq!(entry.line, 2);
+/// assert_eq!(entry.key().as_str(), Some("alice"));
+/// assert!(reader.next().is_none());
+/// ```
+#[derive(Debug)]
+pub struct StreamReader<R> {
+    reader: R,
+    parser: LineParser,
+    buffer: Vec<u8>,
+    offset: u64,
+}
+
+impl StreamReader<BufReader<Decoder<File>>> {
+    /// Opens a JSONLT file for streaming.
+    ///
+    /// Files ending in `.gz` or `.zst` are decompressed as they are read; see
+    /// [`Compression::from_path`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        Self::open_with_compression(path, Compression::from_path(path))
+    }
+
+    /// Opens a JSONLT file compressed with `compression`, whatever its
+    /// extension.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened or the compression is
+    /// not supported.
+    pub fn open_with_compression<P: AsRef<Path>>(
+        path: P,
+        compression: Compression,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        File::open(path)
+            .map_err(Error::from)
+            .and_then(|file| Decoder::new(file, compression))
+            .map(|decoder| Self::new(BufReader::new(decoder)))
+            .context("open", path)
+    }
+}
+
+impl<R: BufRead> StreamReader<R> {
+    /// Creates a reader taking the key specifier from the file header.
+    pub const fn new(reader: R) -> Self {
+        Self {
+            reader,
+            parser: LineParser::new(None),
+            buffer: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Creates a reader using the given key specifier.
+    ///
+    /// If the file has a header declaring a key, it must match `key`.
+    pub fn with_key(reader: R, key: impl Into<KeySpecifier>) -> Self {
+        Self {
+            reader,
+            parser: LineParser::new(Some(key.into())),
+            buffer: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Returns the header, once the first line has been read.
+    #[must_use]
+    pub const fn header(&self) -> Option<&Header> {
+        self.parser.header()
+    }
+
+    /// Returns the key specifier in effect.
+    #[must_use]
+    pub const fn key_specifier(&self) -> Option<&KeySpecifier> {
+        self.parser.key_specifier()
+    }
+
+    /// Returns the number of lines read so far.
+    #[must_use]
+    pub const fn line_number(&self) -> u64 {
+        self.parser.line_number()
+    }
+
+    /// Reads the next record or tombstone.
+    ///
+    /// Returns `None` at the end of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line cannot be read or is not valid JSONLT.
+    pub fn read_entry(&mut self) -> Result<Option<LogEntry>> {
+        loop {
+            let Some(offset) = self.read_line()? else {
+                return Ok(None);
+            };
+            let line = utf8(&self.buffer, self.parser.line_number() + 1, offset)?;
+            let entry = self.parser.parse(line);
+            if let Some(entry) = entry.map_err(|err| err.at(None, Some(offset)))? {
+                return Ok(Some(LogEntry {
+                    line: self.parser.line_number(),
+                    entry,
+                }));
+            }
+        }
+    }
+
+    /// Reads the next record or tombstone without parsing the record.
+    ///
+    /// Only the key fields are parsed; the record is borrowed from the line
+    /// buffer until the next read. The line is checked exactly as by
+    /// [`StreamReader::read_entry`].
+    ///
+    /// Returns `None` at the end of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line cannot be read or is not valid JSONLT.
+    #[cfg(feature = "serde")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn read_raw_entry(&mut self) -> Result<Option<RawEntry<'_>>> {
+        let ((key, deleted), offset) = loop {
+            let Some(offset) = self.read_line()? else {
+                return Ok(None);
+            };
+            let line = utf8(&self.buffer, self.parser.line_number() + 1, offset)?;
+            let entry = self.parser.parse_key(line);
+            if let Some(entry) = entry.map_err(|err| err.at(None, Some(offset)))? {
+                break (entry, offset);
+            }
+        };
+        let record = if deleted {
+            None
+        } else {
+            // The line was checked to be valid UTF-8 and a JSON object.
+            let line = utf8(&self.buffer, self.parser.line_number(), offset)?;
+            Some(RecordRef::parse(line)?)
+        };
+        Ok(Some(RawEntry {
+            line: self.parser.line_number(),
+            key,
+            record,
+        }))
+    }
+
+    /// Builds the table of the live records for which `predicate` returns
+    /// `true`.
+    ///
+    /// The predicate sees each record unparsed, and only the records it
+    /// keeps are parsed in full, so filtering on a few fields of large
+    /// records is much cheaper than reading every entry. A record that no
+    /// longer matches after an update is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line cannot be read or is not valid JSONLT.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::io::StreamReader;
+    /// use jsonlt::Key;
+    ///
+    /// let data = concat!(
+    ///     "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+    ///     "{\"id\":\"alice\",\"role\":\"admin\"}\n",
+    ///     "{\"id\":\"bob\",\"role\":\"user\"}\n",
+    /// );
+    /// let admins = StreamReader::new(data.as_bytes())
+    ///     .select(|record| record.pointer_as::<&str>("/role").ok().flatten() == Some("admin"))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(admins.len(), 1);
+    /// assert!(admins.get(&Key::from("alice")).is_some());
+    /// ```
+    #[cfg(feature = "serde")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn select<F>(mut self, mut predicate: F) -> Result<Table>
+    where
+        F: FnMut(RecordRef<'_>) -> bool,
+    {
+        let mut table = Table::new();
+        while let Some(entry) = self.read_raw_entry()? {
+            match entry.record {
+                Some(record) if predicate(record) => table.insert(entry.key, record.to_record()?),
+                _ => {
+                    table.remove(&entry.key);
+                }
+            }
+        }
+        Ok(table)
+    }
+
+    /// Returns every version of `key` remaining in the file, oldest first.
+    ///
+    /// Because JSONLT files are append-only, each line writing or deleting
+    /// the key is a version; tombstones are included.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line cannot be read or is not valid JSONLT.
+    pub fn history(self, key: &Key) -> Result<Vec<LogEntry>> {
+        self.filter(|entry| entry.as_ref().map_or(true, |entry| entry.key() == key))
+            .collect()
+    }
+
+    /// Reconstructs the table as it was after line `line` was written.
+    ///
+    /// Reading stops at the first entry after `line`, so later lines are
+    /// neither parsed nor validated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line cannot be read or is not valid JSONLT.
+    pub fn as_of(mut self, line: u64) -> Result<Table> {
+        let mut table = Table::new();
+        while self.line_number() < line {
+            let Some(entry) = self.read_entry()? else {
+                break;
+            };
+            if entry.line > line {
+                break;
+            }
+            match entry.entry {
+                Entry::Record { key, record } => table.insert(key, record),
+                Entry::Tombstone { key } => {
+                    table.remove(&key);
+                }
+            }
+        }
+        Ok(table)
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the next line into the buffer, returning the byte offset it
+    /// starts at, or `None` at the end of the input.
+    fn read_line(&mut self) -> Result<Option<u64>> {
+        self.buffer.clear();
+        let read = self.reader.read_until(b'\n', &mut self.buffer)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let offset = self.offset;
+        self.offset += read as u64;
+        Ok(Some(offset))
+    }
+}
+
+/// Checks that a line read at `offset` is valid UTF-8.
+fn utf8(buffer: &[u8], line: u64, offset: u64) -> Result<&str> {
+    std::str::from_utf8(buffer)
+        .map_err(|_| Error::invalid_format("invalid UTF-8").at(Some(line), Some(offset)))
+}
+
+impl<R: BufRead> Iterator for StreamReader<R> {
+    type Item = Result<LogEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_entry().transpose()
+    }
+}
+
+/// Writes records and tombstones to a JSONLT file in canonical form.
+///
+/// Each record is checked to carry a valid key before it is written, so the
+/// output can always be read back with the same key specifier.
+#[derive(Debug)]
+pub struct StreamWriter<W: Write> {
+    writer: BufWriter<W>,
+    key: KeySpecifier,
+}
+
+impl StreamWriter<Encoder<File>> {
+    /// Creates a JSONLT file for streaming writes, truncating any existing
+    /// file.
+    ///
+    /// Files ending in `.gz` or `.zst` are compressed as they are written;
+    /// see [`Compression::from_path`]. Call [`StreamWriter::finish`] to
+    /// complete the compressed stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn create<P: AsRef<Path>>(path: P, key: impl Into<KeySpecifier>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::create_with_compression(path, key, Compression::from_path(path))
+    }
+
+    /// Creates a JSONLT file compressed with `compression`, whatever its
+    /// extension.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created or the compression is
+    /// not supported.
+    pub fn create_with_compression<P: AsRef<Path>>(
+        path: P,
+        key: impl Into<KeySpecifier>,
+        compression: Compression,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let encoder = File::create(path)
+            .map_err(Error::from)
+            .and_then(|file| Encoder::new(file, compression))
+            .context("create", path)?;
+        Ok(Self::new(encoder, key))
+    }
+
+    /// Flushes buffered lines, completes the compressed stream and returns
+    /// the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn finish(self) -> Result<File> {
+        self.into_inner()?.finish()
+    }
+}
+
+impl<W: Write> StreamWriter<W> {
+    /// Creates a writer for records keyed by `key`.
+    pub fn new(writer: W, key: impl Into<KeySpecifier>) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+            key: key.into(),
+        }
+    }
+
+    /// Returns the key specifier used to validate records.
+    #[must_use]
+    pub const fn key_specifier(&self) -> &KeySpecifier {
+        &self.key
+    }
+
+    /// Writes a header line.
+    ///
+    /// The header should be written before any record.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header declares a different key specifier or
+    /// the line cannot be written.
+    pub fn write_header(&mut self, header: &Header) -> Result<()> {
+        let line = header_line(&self.key, header)?;
+        self.write_line(&line)
+    }
+
+    /// Writes a record and returns its key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record is not an object with a valid key or
+    /// the line cannot be written.
+    pub fn write_record(&mut self, record: &Record) -> Result<Key> {
+        let (key, line) = record_line(&self.key, record)?;
+        self.write_line(&line)?;
+        Ok(key)
+    }
+
+    /// Writes a tombstone deleting `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key does not match the key specifier or the
+    /// line cannot be written.
+    pub fn write_tombstone(&mut self, key: &Key) -> Result<()> {
+        let line = tombstone_line(&self.key, key)?;
+        self.write_line(&line)
+    }
+
+    /// Writes a record or tombstone.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry is invalid or the line cannot be
+    /// written.
+    pub fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        match entry {
+            Entry::Record { record, .. } => self.write_record(record).map(drop),
+            Entry::Tombstone { key } => self.write_tombstone(key),
+        }
+    }
+
+    /// Writes every record of a table in key order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a record is invalid, is stored under a key other
+    /// than its own, or a line cannot be written.
+    pub fn write_table(&mut self, table: &Table) -> Result<()> {
+        for (key, record) in table.iter() {
+            let line = table_line(&self.key, key, record)?;
+            self.write_line(&line)?;
+        }
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Flushes buffered lines to the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writer cannot be flushed.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Flushes buffered lines and returns the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writer cannot be flushed.
+    pub fn into_inner(self) -> Result<W> {
+        self.writer
+            .into_inner()
+            .map_err(|err| Error::Io(err.into_error()))
+    }
+}
+
+/// Serializes the header line of a file keyed by `key`, checking that the
+/// header declares no other key specifier.
+pub(super) fn header_line(key: &KeySpecifier, header: &Header) -> Result<String> {
+    if let Some(found) = header.key.as_ref().filter(|found| *found != key) {
+        return Err(Error::KeyMismatch {
+            expected: key.clone(),
+            found: found.clone(),
+        });
+    }
+    Ok(to_canonical_string(&header.to_value()))
+}
+
+/// Serializes a record as a line, returning the key it has under `key`.
+pub(super) fn record_line(key: &KeySpecifier, record: &Record) -> Result<(Key, String)> {
+    let object = record
+        .value()
+        .as_object()
+        .ok_or_else(|| Error::invalid_format("record must be a JSON object"))?;
+    let key = record_key(object, key)?;
+    Ok((key, to_canonical_string(record.value())))
+}
+
+/// Serializes the record a table stores under `stored` as a line, checking
+/// that the record has that key under `key`, so that the line reads back
+/// into the same table.
+pub(super) fn table_line(key: &KeySpecifier, stored: &Key, record: &Record) -> Result<String> {
+    let (found, line) = record_line(key, record)?;
+    if found != *stored {
+        return Err(Error::invalid_key(format!(
+            "record stored under key {stored} has key {found}"
+        )));
+    }
+    Ok(line)
+}
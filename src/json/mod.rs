@@ -0,0 +1,48 @@
+//! JSON values.
+//!
+//! With the `serde` feature, the types of this module are those of
+//! `serde_json`. Without it, the crate brings a small JSON parser and value
+//! type of its own with the same names and the parts of the `serde_json` API
+//! that the crate uses, so code written against [`Value`] builds either way.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use jsonlt::json::{self, Value};
+//!
+//! let value = json::from_str(r#"{"id":"alice","tags":["a","b"]}"#).unwrap();
+//! assert_eq!(value["id"].as_str(), Some("alice"));
+//! assert_eq!(value["tags"].as_array().map(Vec::len), Some(2));
+//! assert_eq!(value["missing"], Value::Null);
+//! ```
+
+#[cfg(not(feature = "serde"))]
+mod parse;
+#[cfg(not(feature = "serde"))]
+mod value;
+
+#[cfg(not(feature = "serde"))]
+pub use parse::Error;
+#[cfg(feature = "serde")]
+pub use serde_json::{Error, Map, Number, Value};
+#[cfg(not(feature = "serde"))]
+pub use value::{Map, Number, Value};
+
+/// Parses a JSON value from a string.
+///
+/// Whitespace around the value is allowed; anything else after it is an
+/// error.
+///
+/// # Errors
+///
+/// Returns an error if `text` is not valid JSON or nests too deeply.
+pub fn from_str(text: &str) -> Result<Value, Error> {
+    #[cfg(feature = "serde")]
+    {
+        serde_json::from_str(text)
+    }
+    #[cfg(not(feature = "serde"))]
+    {
+        parse::parse(text)
+    }
+}
@@ -0,0 +1,376 @@
+//! The built-in JSON value type.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Index;
+
+/// A JSON object, with members sorted by name.
+pub type Map<K, V> = BTreeMap<K, V>;
+
+/// A JSON value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Value {
+    /// The `null` literal.
+    #[default]
+    Null,
+
+    /// A boolean.
+    Bool(bool),
+
+    /// A number.
+    Number(Number),
+
+    /// A string.
+    String(String),
+
+    /// An array.
+    Array(Vec<Self>),
+
+    /// An object.
+    Object(Map<String, Self>),
+}
+
+static NULL: Value = Value::Null;
+
+impl Value {
+    /// Returns the member of an object with the given name.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Self> {
+        self.as_object()?.get(name)
+    }
+
+    /// Returns `true` if the value is `null`.
+    #[must_use]
+    pub const fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+
+    /// Returns `true` if the value is an object.
+    #[must_use]
+    pub const fn is_object(&self) -> bool {
+        matches!(self, Self::Object(_))
+    }
+
+    /// Returns `true` if the value is an array.
+    #[must_use]
+    pub const fn is_array(&self) -> bool {
+        matches!(self, Self::Array(_))
+    }
+
+    /// Returns `true` if the value is a string.
+    #[must_use]
+    pub const fn is_string(&self) -> bool {
+        matches!(self, Self::String(_))
+    }
+
+    /// Returns `true` if the value is a number.
+    #[must_use]
+    pub const fn is_number(&self) -> bool {
+        matches!(self, Self::Number(_))
+    }
+
+    /// Returns `true` if the value is a boolean.
+    #[must_use]
+    pub const fn is_boolean(&self) -> bool {
+        matches!(self, Self::Bool(_))
+    }
+
+    /// Returns the boolean if the value is one.
+    #[must_use]
+    pub const fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns the string if the value is one.
+    #[must_use]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the number as an `i64` if it is an integer that fits.
+    #[must_use]
+    pub const fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Number(n) => n.as_i64(),
+            _ => None,
+        }
+    }
+
+    /// Returns the number as a `u64` if it is a non-negative integer.
+    #[must_use]
+    pub const fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::Number(n) => n.as_u64(),
+            _ => None,
+        }
+    }
+
+    /// Returns the number as an `f64`.
+    #[must_use]
+    pub const fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(n) => Some(n.as_f64()),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements if the value is an array.
+    #[must_use]
+    pub const fn as_array(&self) -> Option<&Vec<Self>> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements mutably if the value is an array.
+    pub fn as_array_mut(&mut self) -> Option<&mut Vec<Self>> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Returns the members if the value is an object.
+    #[must_use]
+    pub const fn as_object(&self) -> Option<&Map<String, Self>> {
+        match self {
+            Self::Object(object) => Some(object),
+            _ => None,
+        }
+    }
+
+    /// Returns the members mutably if the value is an object.
+    pub fn as_object_mut(&mut self) -> Option<&mut Map<String, Self>> {
+        match self {
+            Self::Object(object) => Some(object),
+            _ => None,
+        }
+    }
+
+    /// Returns the value a JSON pointer refers to.
+    #[must_use]
+    pub fn pointer(&self, pointer: &str) -> Option<&Self> {
+        if pointer.is_empty() {
+            return Some(self);
+        }
+        pointer
+            .strip_prefix('/')?
+            .split('/')
+            .try_fold(self, |value, segment| {
+                let segment = segment.replace("~1", "/").replace("~0", "~");
+                match value {
+                    Self::Object(object) => object.get(&segment),
+                    Self::Array(items) => {
+                        if segment.starts_with('+')
+                            || (segment.starts_with('0') && segment.len() > 1)
+                        {
+                            return None;
+                        }
+                        items.get(segment.parse::<usize>().ok()?)
+                    }
+                    _ => None,
+                }
+            })
+    }
+}
+
+/// Compact JSON text, with object members in name order.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&crate::to_canonical_string(self))
+    }
+}
+
+/// Looks up an object member, yielding `null` if there is none.
+impl Index<&str> for Value {
+    type Output = Self;
+
+    fn index(&self, name: &str) -> &Self {
+        self.get(name).unwrap_or(&NULL)
+    }
+}
+
+/// Looks up an array element, yielding `null` if there is none.
+impl Index<usize> for Value {
+    type Output = Self;
+
+    fn index(&self, index: usize) -> &Self {
+        self.as_array()
+            .and_then(|items| items.get(index))
+            .unwrap_or(&NULL)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Self::Number(n.into())
+    }
+}
+
+impl From<i32> for Value {
+    fn from(n: i32) -> Self {
+        Self::Number(i64::from(n).into())
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self {
+        Self::Number(n.into())
+    }
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Self {
+        Self::Number((n as u64).into())
+    }
+}
+
+/// Converts a finite float to a number, and anything else to `null`.
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Number::from_f64(n).map_or(Self::Null, Self::Number)
+    }
+}
+
+impl From<Vec<Self>> for Value {
+    fn from(items: Vec<Self>) -> Self {
+        Self::Array(items)
+    }
+}
+
+impl From<Map<String, Self>> for Value {
+    fn from(object: Map<String, Self>) -> Self {
+        Self::Object(object)
+    }
+}
+
+impl<T: Into<Self>> FromIterator<T> for Value {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::Array(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+/// A JSON number.
+///
+/// Integers are kept exactly when they fit in an `i64` or a `u64`; other
+/// numbers are stored as finite `f64` values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Number(N);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum N {
+    PosInt(u64),
+    NegInt(i64),
+    Float(f64),
+}
+
+// Floats are always finite, so equality is reflexive.
+impl Eq for Number {}
+
+impl Number {
+    /// Creates a number from a float, or returns `None` if it is not finite.
+    #[must_use]
+    pub fn from_f64(n: f64) -> Option<Self> {
+        n.is_finite().then_some(Self(N::Float(n)))
+    }
+
+    /// Returns `true` if the number is an integer that fits in an `i64`.
+    #[must_use]
+    pub const fn is_i64(&self) -> bool {
+        self.as_i64().is_some()
+    }
+
+    /// Returns `true` if the number is a non-negative integer.
+    #[must_use]
+    pub const fn is_u64(&self) -> bool {
+        matches!(self.0, N::PosInt(_))
+    }
+
+    /// Returns `true` if the number is stored as a float.
+    #[must_use]
+    pub const fn is_f64(&self) -> bool {
+        matches!(self.0, N::Float(_))
+    }
+
+    /// Returns the number as an `i64` if it is an integer that fits.
+    #[must_use]
+    pub const fn as_i64(&self) -> Option<i64> {
+        match self.0 {
+            #[allow(clippy::cast_possible_wrap)] // Checked against i64::MAX
+            N::PosInt(n) if n <= i64::MAX as u64 => Some(n as i64),
+            N::NegInt(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Returns the number as a `u64` if it is a non-negative integer.
+    #[must_use]
+    pub const fn as_u64(&self) -> Option<u64> {
+        match self.0 {
+            N::PosInt(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Returns the number as an `f64`, rounding large integers.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub const fn as_f64(&self) -> f64 {
+        match self.0 {
+            N::PosInt(n) => n as f64,
+            N::NegInt(n) => n as f64,
+            N::Float(n) => n,
+        }
+    }
+}
+
+/// Integers are written exactly and floats in their shortest form that
+/// reads back the same, with a fractional part or an exponent.
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            N::PosInt(n) => write!(f, "{n}"),
+            N::NegInt(n) => write!(f, "{n}"),
+            N::Float(n) => write!(f, "{n:?}"),
+        }
+    }
+}
+
+impl From<u64> for Number {
+    fn from(n: u64) -> Self {
+        Self(N::PosInt(n))
+    }
+}
+
+impl From<i64> for Number {
+    fn from(n: i64) -> Self {
+        u64::try_from(n).map_or(Self(N::NegInt(n)), |n| Self(N::PosInt(n)))
+    }
+}
@@ -0,0 +1,325 @@
+//! Key types identifying JSONLT records.
+
+use crate::json::{Map, Value};
+use crate::{Error, Result};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The largest integer magnitude allowed in a key.
+///
+/// Integer keys are limited to the range exactly representable by an IEEE 754
+/// double so that they round-trip through every JSON implementation.
+pub const MAX_INTEGER_KEY: i64 = (1 << 53) - 1;
+
+/// The maximum number of elements in a tuple key.
+pub const MAX_TUPLE_ELEMENTS: usize = 16;
+
+/// The key identifying a record in a JSONLT table.
+///
+/// Keys are strings, integers, or tuples of strings and integers for tables
+/// with compound keys. Keys are ordered with integers before strings and
+/// strings before tuples; tuples compare element by element.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum Key {
+    /// An integer key.
+    Integer(i64),
+
+    /// A string key.
+    String(String),
+
+    /// A compound key made of string and integer elements.
+    Tuple(Vec<Self>),
+}
+
+impl Key {
+    /// Extracts a scalar key from a JSON value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value is not a string or an integer within
+    /// [`MAX_INTEGER_KEY`].
+    pub fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::String(s) => Ok(Self::String(s.clone())),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) if (-MAX_INTEGER_KEY..=MAX_INTEGER_KEY).contains(&i) => {
+                    Ok(Self::Integer(i))
+                }
+                _ => Err(Error::invalid_key(format!(
+                    "key must be an integer within ±{MAX_INTEGER_KEY}, found {n}"
+                ))),
+            },
+            other => Err(Error::invalid_key(format!(
+                "key must be a string or an integer, found {other}"
+            ))),
+        }
+    }
+
+    /// Returns the key as a JSON value.
+    #[must_use]
+    pub fn to_value(&self) -> Value {
+        match self {
+            Self::Integer(i) => Value::from(*i),
+            Self::String(s) => Value::from(s.as_str()),
+            Self::Tuple(elements) => elements.iter().map(Self::to_value).collect(),
+        }
+    }
+
+    /// Returns the string if this is a string key.
+    #[must_use]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer if this is an integer key.
+    #[must_use]
+    pub const fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(i) => write!(f, "{i}"),
+            Self::String(s) => f.write_str(s),
+            Self::Tuple(elements) => {
+                f.write_str("[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    match element {
+                        Self::String(s) => write!(f, "{s:?}")?,
+                        other => write!(f, "{other}")?,
+                    }
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+impl From<String> for Key {
+    fn from(key: String) -> Self {
+        Self::String(key)
+    }
+}
+
+impl From<&str> for Key {
+    fn from(key: &str) -> Self {
+        Self::String(key.to_string())
+    }
+}
+
+impl From<&String> for Key {
+    fn from(key: &String) -> Self {
+        Self::String(key.clone())
+    }
+}
+
+impl From<i64> for Key {
+    fn from(key: i64) -> Self {
+        Self::Integer(key)
+    }
+}
+
+impl From<i32> for Key {
+    fn from(key: i32) -> Self {
+        Self::Integer(i64::from(key))
+    }
+}
+
+impl From<Vec<Self>> for Key {
+    fn from(elements: Vec<Self>) -> Self {
+        Self::Tuple(elements)
+    }
+}
+
+impl From<&Self> for Key {
+    fn from(key: &Self) -> Self {
+        key.clone()
+    }
+}
+
+/// Names the field or fields that hold the key of each record.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum KeySpecifier {
+    /// The key is the value of a single field.
+    Field(String),
+
+    /// The key is a tuple built from several fields, in order.
+    Fields(Vec<String>),
+}
+
+impl KeySpecifier {
+    /// Returns the names of the key fields.
+    #[must_use]
+    pub fn fields(&self) -> &[String] {
+        match self {
+            Self::Field(field) => core::slice::from_ref(field),
+            Self::Fields(fields) => fields,
+        }
+    }
+
+    /// Returns `true` if `name` is one of the key fields.
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.fields().iter().any(|field| field == name)
+    }
+
+    /// Reads a specifier from its JSON form, a string or an array of
+    /// strings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value has any other shape. The specifier is
+    /// not validated.
+    pub fn from_value(value: &Value) -> Result<Self> {
+        let invalid =
+            || Error::invalid_key("key specifier must be a string or an array of strings");
+        match value {
+            Value::String(field) => Ok(Self::Field(field.clone())),
+            Value::Array(fields) => fields
+                .iter()
+                .map(|field| field.as_str().map(str::to_string).ok_or_else(invalid))
+                .collect::<Result<_>>()
+                .map(Self::Fields),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Returns the JSON form of the specifier.
+    #[must_use]
+    pub fn to_value(&self) -> Value {
+        match self {
+            Self::Field(field) => Value::from(field.as_str()),
+            Self::Fields(fields) => fields.iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// Checks that the specifier is well formed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a compound specifier is empty, has too many or
+    /// duplicate fields, or names a reserved `$` field.
+    pub fn validate(&self) -> Result<()> {
+        let fields = self.fields();
+        if fields.is_empty() || fields.len() > MAX_TUPLE_ELEMENTS {
+            return Err(Error::invalid_key(format!(
+                "key specifier must name between 1 and {MAX_TUPLE_ELEMENTS} fields"
+            )));
+        }
+        for (i, field) in fields.iter().enumerate() {
+            if field.starts_with('$') {
+                return Err(Error::invalid_key(format!(
+                    "key field {field:?} uses the reserved '$' prefix"
+                )));
+            }
+            if fields[..i].contains(field) {
+                return Err(Error::invalid_key(format!(
+                    "key field {field:?} is listed more than once"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Extracts the key from a JSON object.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a key field is missing or holds an invalid key
+    /// value.
+    pub fn extract(&self, object: &Map<String, Value>) -> Result<Key> {
+        let value_of = |field: &String| {
+            object
+                .get(field)
+                .ok_or_else(|| Error::invalid_key(format!("missing key field {field:?}")))
+                .and_then(Key::from_value)
+        };
+        match self {
+            Self::Field(field) => value_of(field),
+            Self::Fields(fields) => fields
+                .iter()
+                .map(value_of)
+                .collect::<Result<Vec<_>>>()
+                .map(Key::Tuple),
+        }
+    }
+
+    /// Builds the JSON object holding only the key fields for `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shape of the key does not match the
+    /// specifier.
+    pub fn key_fields(&self, key: &Key) -> Result<Map<String, Value>> {
+        let mut object = Map::new();
+        match (self, key) {
+            (Self::Field(field), Key::Integer(_) | Key::String(_)) => {
+                object.insert(field.clone(), key.to_value());
+            }
+            (Self::Fields(fields), Key::Tuple(elements)) if fields.len() == elements.len() => {
+                for (field, element) in fields.iter().zip(elements) {
+                    object.insert(field.clone(), element.to_value());
+                }
+            }
+            _ => {
+                return Err(Error::invalid_key(format!(
+                    "key {key} does not match the key specifier"
+                )))
+            }
+        }
+        Ok(object)
+    }
+}
+
+impl fmt::Display for KeySpecifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Field(field) => write!(f, "{field:?}"),
+            Self::Fields(fields) => write!(f, "{fields:?}"),
+        }
+    }
+}
+
+impl From<&str> for KeySpecifier {
+    fn from(field: &str) -> Self {
+        Self::Field(field.to_string())
+    }
+}
+
+impl From<String> for KeySpecifier {
+    fn from(field: String) -> Self {
+        Self::Field(field)
+    }
+}
+
+impl From<Vec<String>> for KeySpecifier {
+    fn from(fields: Vec<String>) -> Self {
+        Self::Fields(fields)
+    }
+}
+
+impl From<&[&str]> for KeySpecifier {
+    fn from(fields: &[&str]) -> Self {
+        Self::Fields(fields.iter().map(ToString::to_string).collect())
+    }
+}
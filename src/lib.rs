@@ -8,11 +8,30 @@
 //!
 //! ## Features
 //!
+//! - **std** (default): Enables file I/O and channel subscriptions; without
+//!   it the crate is `no_std` and only needs `alloc`
 //! - **serde** (default): Enables serialization/deserialization support via serde
+//!   and uses `serde_json` for JSON; without it, a small built-in JSON parser
+//!   is used (see [`json`])
 //! - **async**: Enables async I/O operations via tokio
-//! - **derive**: Enables derive macros for error types
+//! - **derive**: Deprecated and does nothing; error types always derive
+//!   their trait implementations with `thiserror`
 //! - **wasm**: Enables WebAssembly bindings
-//! - **full**: Enables all features
+//! - **cli**: Builds the `jsonlt` command-line tool
+//! - **csv**: Enables CSV import and export of tables
+//! - **arrow**: Enables conversion to Apache Arrow record batches and Parquet files
+//! - **sqlite**: Enables export to and import from SQLite databases
+//! - **compression**: Enables reading and writing gzip and Zstandard compressed files
+//! - **test-util**: Enables simulated crashes for testing recovery; see
+//!   `io::simulate_crash`
+//! - **full**: Enables all features except **test-util**
+//!
+//! ## `no_std`
+//!
+//! With default features disabled, the crate builds for `no_std` targets
+//! with an allocator. [`Table`], [`Record`], [`Operations`] and the
+//! [`LineParser`] are available, using the built-in [`json`] parser, so a
+//! JSONLT file read by other means can be loaded line by line.
 //!
 //! ## Example
 //!
@@ -23,26 +42,74 @@
 //! let table = Table::new();
 //! ```
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![warn(missing_docs)]
 #![warn(clippy::all)]
 #![warn(clippy::pedantic)]
 #![warn(clippy::nursery)]
 
+extern crate alloc;
+
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "csv")]
+mod csv;
+#[cfg(feature = "serde")]
+mod diff;
 mod error;
+mod event;
+#[cfg(feature = "serde")]
+mod format;
+mod header;
+mod key;
+mod line;
+#[cfg(feature = "serde")]
+mod merge;
 mod ops;
 mod record;
+mod reference;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 mod table;
+#[cfg(feature = "serde")]
+mod validate;
 
-#[cfg(feature = "async")]
-#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 pub mod io;
+pub mod json;
 
 #[cfg(feature = "wasm")]
 #[cfg_attr(docsrs, doc(cfg(feature = "wasm")))]
 pub mod wasm;
 
-pub use error::{Error, Result};
+#[cfg(feature = "arrow")]
+pub use crate::arrow::{infer_schema, ArrowOptions, RecordBatches, DEFAULT_BATCH_SIZE};
+#[cfg(feature = "csv")]
+pub use crate::csv::{ColumnType, CsvOptions};
+#[cfg(feature = "serde")]
+pub use diff::{FieldChange, RecordDiff, TableDiff};
+pub use error::{Error, ErrorKind, Result};
+pub use event::{ChangeEvent, ChangeKind, SubscriptionId};
+#[cfg(feature = "serde")]
+pub use format::{check_format, format, format_reader, format_with, FormatReport};
+pub use header::{Header, HEADER_FIELD, VERSION};
+pub use key::{Key, KeySpecifier, MAX_INTEGER_KEY, MAX_TUPLE_ELEMENTS};
+pub use line::{
+    record_key, to_canonical_string, tombstone_line, Entry, LineParser, LogEntry, DELETED_FIELD,
+};
+#[cfg(feature = "serde")]
+pub use merge::{merge, merge_files, merge_with, Conflict, FileMerge, MergeOptions, MergeResult};
 pub use ops::Operations;
-pub use record::Record;
+#[cfg(feature = "serde")]
+pub use record::RecordRef;
+pub use record::{Record, MAX_RECORD_DEPTH, MAX_RECORD_SIZE};
+pub use reference::{check_references, DanglingReference, OnDelete, Reference};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteOptions;
 pub use table::Table;
+#[cfg(feature = "serde")]
+pub use validate::{
+    validate, validate_reader, validate_with, Issue, Severity, ValidateOptions, ValidationReport,
+};
@@ -0,0 +1,429 @@
+//! Parsing and serialization of individual JSONLT lines.
+
+use crate::json::{self, Map, Value};
+#[cfg(feature = "serde")]
+use crate::HEADER_FIELD;
+use crate::{Error, Header, Key, KeySpecifier, Record, Result};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "serde")]
+use core::fmt;
+use core::fmt::Write as _;
+#[cfg(feature = "serde")]
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, Visitor};
+
+/// The field that marks a line as a tombstone.
+pub const DELETED_FIELD: &str = "$deleted";
+
+/// A record or tombstone read from a JSONLT line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    /// A record stored under a key.
+    Record {
+        /// The key extracted from the record.
+        key: Key,
+        /// The record.
+        record: Record,
+    },
+
+    /// A tombstone marking a key as deleted.
+    Tombstone {
+        /// The deleted key.
+        key: Key,
+    },
+}
+
+impl Entry {
+    /// Parses an entry from a line object.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key is missing or invalid, if a tombstone
+    /// carries fields other than its key, or if a record is not valid as
+    /// described for [`Record::try_from_value`].
+    pub fn from_object(object: Map<String, Value>, key: &KeySpecifier) -> Result<Self> {
+        if let Some(deleted) = object.get(DELETED_FIELD) {
+            if deleted != &Value::Bool(true) {
+                return Err(Error::invalid_format(format!(
+                    "{DELETED_FIELD} must be true"
+                )));
+            }
+            if let Some(field) = object
+                .keys()
+                .find(|field| *field != DELETED_FIELD && !key.contains(field))
+            {
+                return Err(Error::invalid_format(format!(
+                    "tombstone must contain only key fields, found {field:?}"
+                )));
+            }
+            return Ok(Self::Tombstone {
+                key: key.extract(&object)?,
+            });
+        }
+
+        Ok(Self::Record {
+            key: record_key(&object, key)?,
+            record: Record::try_from_value(Value::Object(object))?,
+        })
+    }
+
+    /// Returns the key of the entry.
+    #[must_use]
+    pub const fn key(&self) -> &Key {
+        match self {
+            Self::Record { key, .. } | Self::Tombstone { key } => key,
+        }
+    }
+
+    /// Returns `true` if the entry is a tombstone.
+    #[must_use]
+    pub const fn is_tombstone(&self) -> bool {
+        matches!(self, Self::Tombstone { .. })
+    }
+
+    /// Returns the record, or `None` for a tombstone.
+    #[must_use]
+    pub const fn record(&self) -> Option<&Record> {
+        match self {
+            Self::Record { record, .. } => Some(record),
+            Self::Tombstone { .. } => None,
+        }
+    }
+
+    /// Serializes the entry as a canonical line, without the trailing newline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key does not match the key specifier.
+    pub fn to_line(&self, key: &KeySpecifier) -> Result<String> {
+        match self {
+            Self::Record { record, .. } => Ok(to_canonical_string(record.value())),
+            Self::Tombstone { key: deleted } => tombstone_line(key, deleted),
+        }
+    }
+}
+
+/// An entry read from a JSONLT file together with its line number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    /// The one-based line number of the entry.
+    pub line: u64,
+
+    /// The record or tombstone.
+    pub entry: Entry,
+}
+
+impl LogEntry {
+    /// Returns the key of the entry.
+    #[must_use]
+    pub const fn key(&self) -> &Key {
+        self.entry.key()
+    }
+}
+
+/// Extracts the key of a record object, checking that it uses no reserved
+/// `$` field.
+///
+/// # Errors
+///
+/// Returns an error if a key field is missing or invalid, or if the object
+/// contains a reserved field.
+pub fn record_key(object: &Map<String, Value>, key: &KeySpecifier) -> Result<Key> {
+    if let Some(field) = object.keys().find(|field| field.starts_with('$')) {
+        return Err(Error::invalid_format(format!(
+            "field {field:?} uses the reserved '$' prefix"
+        )));
+    }
+    key.extract(object)
+}
+
+/// Serializes a tombstone for `deleted` as a canonical line.
+///
+/// # Errors
+///
+/// Returns an error if the key does not match the key specifier.
+pub fn tombstone_line(key: &KeySpecifier, deleted: &Key) -> Result<String> {
+    let mut object = key.key_fields(deleted)?;
+    object.insert(DELETED_FIELD.into(), Value::Bool(true));
+    Ok(to_canonical_string(&Value::Object(object)))
+}
+
+/// Serializes a JSON value in canonical form.
+///
+/// The canonical form has no insignificant whitespace and object members
+/// sorted by key, so equal values always produce identical lines.
+#[must_use]
+pub fn to_canonical_string(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => {
+            let _ = write!(out, "{n}");
+        }
+        Value::String(s) => write_string(s, out),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(object) => {
+            let mut members: Vec<_> = object.iter().collect();
+            members.sort_unstable_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (name, item)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(name, out);
+                out.push(':');
+                write_canonical(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Parses the lines of a JSONLT file in order.
+///
+/// The parser tracks line numbers, recognizes the optional header on the
+/// first line and extracts keys with the key specifier taken from the
+/// header or supplied by the caller.
+#[derive(Debug, Clone, Default)]
+pub struct LineParser {
+    key: Option<KeySpecifier>,
+    header: Option<Header>,
+    line: u64,
+}
+
+impl LineParser {
+    /// Creates a parser, optionally with the key specifier to use when the
+    /// file has no header.
+    #[must_use]
+    pub const fn new(key: Option<KeySpecifier>) -> Self {
+        Self {
+            key,
+            header: None,
+            line: 0,
+        }
+    }
+
+    /// Returns the header, if one has been parsed.
+    #[must_use]
+    pub const fn header(&self) -> Option<&Header> {
+        self.header.as_ref()
+    }
+
+    /// Returns the key specifier in effect.
+    #[must_use]
+    pub const fn key_specifier(&self) -> Option<&KeySpecifier> {
+        self.key.as_ref()
+    }
+
+    /// Returns the number of lines parsed so far.
+    #[must_use]
+    pub const fn line_number(&self) -> u64 {
+        self.line
+    }
+
+    /// Advances the line number past `count` lines that are known to be
+    /// valid without parsing them.
+    pub fn skip_lines(&mut self, count: u64) {
+        self.line += count;
+    }
+
+    /// Parses the next line.
+    ///
+    /// A trailing line terminator is ignored. Returns `None` for the header
+    /// line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the line number if the line is not valid
+    /// JSONLT.
+    pub fn parse(&mut self, line: &str) -> Result<Option<Entry>> {
+        self.line += 1;
+        let number = self.line;
+        self.parse_numbered(line)
+            .map_err(|err| err.at(Some(number), None))
+    }
+
+    /// Parses the next line, returning only its key and whether it is a
+    /// tombstone.
+    ///
+    /// The line is checked as by [`LineParser::parse`], but with the `serde`
+    /// feature the values of fields other than the key fields are skipped
+    /// rather than parsed, which is much cheaper for large records. The
+    /// size and depth limits of a record are then left for whoever parses
+    /// it in full.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the line number if the line is not valid
+    /// JSONLT.
+    pub fn parse_key(&mut self, line: &str) -> Result<Option<(Key, bool)>> {
+        self.line += 1;
+        let number = self.line;
+        self.parse_key_numbered(line)
+            .map_err(|err| err.at(Some(number), None))
+    }
+
+    fn parse_key_numbered(&mut self, line: &str) -> Result<Option<(Key, bool)>> {
+        let full = |this: &mut Self| {
+            this.parse_numbered(line)
+                .map(|entry| entry.map(|entry| (entry.key().clone(), entry.is_tombstone())))
+        };
+        let Some(key) = self.key.as_ref().filter(|_| self.line > 1) else {
+            return full(self);
+        };
+        // Anything unusual is parsed in full to report the same error.
+        let Some(object) = skeleton(key, line) else {
+            return full(self);
+        };
+        if Header::is_header(&object) {
+            return full(self);
+        }
+        if object.contains_key(DELETED_FIELD) {
+            return Entry::from_object(object, key).map(|entry| Some((entry.key().clone(), true)));
+        }
+        record_key(&object, key).map(|key| Some((key, false)))
+    }
+
+    fn parse_numbered(&mut self, line: &str) -> Result<Option<Entry>> {
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let line = if self.line == 1 {
+            line.strip_prefix('\u{feff}').unwrap_or(line)
+        } else {
+            line
+        };
+        if line.trim().is_empty() {
+            return Err(Error::invalid_format("empty line"));
+        }
+
+        let Value::Object(object) = json::from_str(line)? else {
+            return Err(Error::invalid_format("line must be a JSON object"));
+        };
+
+        if Header::is_header(&object) {
+            if self.line != 1 {
+                return Err(Error::invalid_format("header must be the first line"));
+            }
+            let header = Header::from_object(&object)?;
+            match (&self.key, &header.key) {
+                (Some(expected), Some(found)) if expected != found => {
+                    return Err(Error::KeyMismatch {
+                        expected: expected.clone(),
+                        found: found.clone(),
+                    });
+                }
+                (None, Some(found)) => self.key = Some(found.clone()),
+                _ => {}
+            }
+            self.header = Some(header);
+            return Ok(None);
+        }
+
+        let key = self.key.as_ref().ok_or_else(|| {
+            Error::invalid_format("no key specifier: the file has no header declaring one")
+        })?;
+        Entry::from_object(object, key).map(Some)
+    }
+}
+
+/// Parses a line object, keeping the values of the key fields and reserved
+/// fields and replacing all other values with `null`.
+///
+/// Returns `None` if the line is not valid JSON or not an object.
+#[cfg(feature = "serde")]
+fn skeleton(key: &KeySpecifier, line: &str) -> Option<Map<String, Value>> {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let mut deserializer = serde_json::Deserializer::from_str(line);
+    let object = Skeleton { key }.deserialize(&mut deserializer).ok()?;
+    deserializer.end().ok()?;
+    Some(object)
+}
+
+/// Without serde, values cannot be skipped without parsing them, so lines
+/// are always parsed in full.
+#[cfg(not(feature = "serde"))]
+const fn skeleton(_: &KeySpecifier, _: &str) -> Option<Map<String, Value>> {
+    None
+}
+
+/// Deserializes a line object as [`skeleton`] describes.
+#[cfg(feature = "serde")]
+struct Skeleton<'a> {
+    key: &'a KeySpecifier,
+}
+
+#[cfg(feature = "serde")]
+impl<'de> DeserializeSeed<'de> for Skeleton<'_> {
+    type Value = Map<String, Value>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> core::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Visitor<'de> for Skeleton<'_> {
+    type Value = Map<String, Value>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a JSON object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(
+        self,
+        mut map: A,
+    ) -> core::result::Result<Self::Value, A::Error> {
+        let mut object = Map::new();
+        while let Some(field) = map.next_key::<String>()? {
+            let value =
+                if field == DELETED_FIELD || field == HEADER_FIELD || self.key.contains(&field) {
+                    map.next_value()?
+                } else {
+                    map.next_value::<IgnoredAny>()?;
+                    Value::Null
+                };
+            object.insert(field, value);
+        }
+        Ok(object)
+    }
+}
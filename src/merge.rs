@@ -0,0 +1,400 @@
+//! Three-way merge of JSONLT tables.
+
+use crate::diff::push_segment;
+use crate::error::ResultExt;
+use crate::io::{replace_file, FileTable};
+use crate::{
+    to_canonical_string, tombstone_line, Error, Header, Key, KeySpecifier, Record, Result, Table,
+};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufWriter, IntoInnerError, Write};
+use std::path::Path;
+
+/// Options controlling a three-way merge.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeOptions {
+    /// Merge records changed on both sides field by field.
+    ///
+    /// When disabled, any record changed differently on both sides is a
+    /// conflict. When enabled, object records are merged recursively and
+    /// only fields changed differently on both sides conflict.
+    pub field_level: bool,
+}
+
+/// A key that could not be merged automatically.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// The conflicting key.
+    pub key: Key,
+
+    /// The record in the common ancestor, if present.
+    pub base: Option<Record>,
+
+    /// The record on our side, or `None` if we deleted it.
+    pub ours: Option<Record>,
+
+    /// The record on their side, or `None` if they deleted it.
+    pub theirs: Option<Record>,
+
+    /// JSON pointers to the conflicting fields.
+    ///
+    /// Empty when the whole record conflicts, for example when one side
+    /// deleted a record the other side modified.
+    pub fields: Vec<String>,
+}
+
+/// The outcome of a three-way merge.
+#[derive(Debug, Clone, Default)]
+pub struct MergeResult {
+    /// The merged table.
+    ///
+    /// Conflicting keys hold our version of the record, or are absent if we
+    /// deleted it.
+    pub table: Table,
+
+    /// The keys that could not be merged, in key order.
+    pub conflicts: Vec<Conflict>,
+}
+
+impl MergeResult {
+    /// Returns `true` if the merge produced no conflicts.
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    /// Writes the merged table as a compacted JSONLT file.
+    ///
+    /// Records are written in key order after the optional header. Each
+    /// conflicting key is written in place as a block of conflict markers in
+    /// the style of `git merge --diff3`:
+    ///
+    /// ```text
+    /// <<<<<<< ours
+    /// {"id":"alice","role":"admin"}
+    /// ||||||| base
+    /// {"id":"alice","role":"user"}
+    /// =======
+    /// {"$deleted":true,"id":"alice"}
+    /// >>>>>>> theirs
+    /// ```
+    ///
+    /// A side that deleted the record is shown as a tombstone, and a side
+    /// without the record at all is shown as an empty section. Every line
+    /// between the markers is a valid JSONLT line, so resolving a conflict
+    /// means keeping the right lines and removing the markers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a key does not match `key` or the output cannot
+    /// be written.
+    pub fn write<W: Write>(
+        &self,
+        writer: &mut W,
+        header: Option<&Header>,
+        key: &KeySpecifier,
+    ) -> Result<()> {
+        if let Some(header) = header {
+            writeln!(writer, "{}", to_canonical_string(&header.to_value()))?;
+        }
+        let mut lines: BTreeMap<&Key, Option<&Conflict>> =
+            self.table.keys().map(|key| (key, None)).collect();
+        for conflict in &self.conflicts {
+            lines.insert(&conflict.key, Some(conflict));
+        }
+        for (record_key, conflict) in lines {
+            match conflict {
+                Some(conflict) => write_conflict(writer, conflict, key)?,
+                None => write_record(writer, self.table.get(record_key))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A three-way merge of JSONLT files, with the header and key specifier its
+/// result is written with.
+#[derive(Debug, Clone)]
+pub struct FileMerge {
+    /// The merged table and its conflicts.
+    pub result: MergeResult,
+
+    /// The header of our file, or else of theirs or of the base.
+    pub header: Option<Header>,
+
+    /// The key specifier of our file, or else of theirs or of the base.
+    pub key: KeySpecifier,
+}
+
+impl FileMerge {
+    /// Writes the merged table as a compacted JSONLT file; see
+    /// [`MergeResult::write`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output cannot be written.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.result.write(writer, self.header.as_ref(), &self.key)
+    }
+
+    /// Replaces the file at `path` with the merged table.
+    ///
+    /// The result is written to a new file next to `path`, synced, and
+    /// renamed over it, so the file is never left half written. The new
+    /// file keeps the permissions of the one it replaces.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written, in which case it is
+    /// left untouched.
+    pub fn write_to_path<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        replace_file(path, ".merge", true, |file| {
+            let mut writer = BufWriter::new(file);
+            self.write(&mut writer)?;
+            Ok(writer.into_inner().map_err(IntoInnerError::into_error)?)
+        })
+        .context("write", path)
+    }
+}
+
+fn write_record<W: Write>(writer: &mut W, record: Option<&Record>) -> Result<()> {
+    if let Some(record) = record {
+        writeln!(writer, "{}", to_canonical_string(record.value()))?;
+    }
+    Ok(())
+}
+
+fn write_conflict<W: Write>(writer: &mut W, conflict: &Conflict, key: &KeySpecifier) -> Result<()> {
+    let side = |record: Option<&Record>, present: bool| -> Result<Option<String>> {
+        match record {
+            Some(record) => Ok(Some(to_canonical_string(record.value()))),
+            None if present => tombstone_line(key, &conflict.key).map(Some),
+            None => Ok(None),
+        }
+    };
+    let base_present = conflict.base.is_some();
+    let sections = [
+        ("<<<<<<< ours", side(conflict.ours.as_ref(), base_present)?),
+        ("||||||| base", side(conflict.base.as_ref(), false)?),
+        ("=======", side(conflict.theirs.as_ref(), base_present)?),
+    ];
+    for (marker, line) in sections {
+        writeln!(writer, "{marker}")?;
+        if let Some(line) = line {
+            writeln!(writer, "{line}")?;
+        }
+    }
+    writeln!(writer, ">>>>>>> theirs")?;
+    Ok(())
+}
+
+/// Merges two tables derived from a common ancestor, key by key.
+///
+/// A key changed on only one side takes that side's version, and a key
+/// changed identically on both sides is merged cleanly. A key changed
+/// differently on both sides is reported as a [`Conflict`].
+///
+/// # Examples
+///
+/// ```rust
+/// use jsonlt::{merge, Key, Record, Table};
+/// use serde_json::json;
+///
+/// let mut base = Table::new();
+/// base.insert("a", Record::try_from_value(json!({"id": "a", "v": 1})).unwrap());
+///
+/// let mut ours = base.clone();
+/// ours.insert("b", Record::try_from_value(json!({"id": "b"})).unwrap());
+///
+/// let mut theirs = base.clone();
+/// theirs.insert("a", Record::try_from_value(json!({"id": "a", "v": 2})).unwrap());
+///
+/// let result = merge(&base, &ours, &theirs);
+/// assert!(result.is_clean());
+/// assert_eq!(result.table.get(&Key::from("a")).unwrap().value()["v"], 2);
+/// assert!(result.table.contains_key(&Key::from("b")));
+/// ```
+#[must_use]
+pub fn merge(base: &Table, ours: &Table, theirs: &Table) -> MergeResult {
+    merge_with(base, ours, theirs, MergeOptions::default())
+}
+
+/// Merges two versions of a JSONLT file derived from a common ancestor.
+///
+/// The tables are merged with [`merge_with`]. The result is written with
+/// the header and key specifier of our file, falling back to those of
+/// their file and then of the base.
+///
+/// # Errors
+///
+/// Returns an error if none of the files has a key specifier.
+///
+/// # Examples
+///
+/// ```rust
+/// use jsonlt::io::FileTable;
+/// use jsonlt::{merge_files, MergeOptions, Record};
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// let path = |name| dir.path().join(name);
+/// let mut base = FileTable::create(path("base.jsonlt"), "id").unwrap();
+/// base.put(&Record::try_from(r#"{"id":"a","v":1}"#).unwrap()).unwrap();
+/// let mut ours = FileTable::create(path("ours.jsonlt"), "id").unwrap();
+/// ours.put(&Record::try_from(r#"{"id":"a","v":1}"#).unwrap()).unwrap();
+/// ours.put(&Record::try_from(r#"{"id":"b"}"#).unwrap()).unwrap();
+/// let mut theirs = FileTable::create(path("theirs.jsonlt"), "id").unwrap();
+/// theirs.put(&Record::try_from(r#"{"id":"a","v":2}"#).unwrap()).unwrap();
+///
+/// let merged = merge_files(&base, &ours, &theirs, MergeOptions::default()).unwrap();
+/// assert!(merged.result.is_clean());
+/// merged.write_to_path(path("ours.jsonlt")).unwrap();
+/// assert_eq!(FileTable::open(path("ours.jsonlt")).unwrap().table().len(), 2);
+/// ```
+pub fn merge_files(
+    base: &FileTable,
+    ours: &FileTable,
+    theirs: &FileTable,
+    options: MergeOptions,
+) -> Result<FileMerge> {
+    let [first, second, third] = [ours, theirs, base];
+    let header = first
+        .header()
+        .or_else(|| second.header())
+        .or_else(|| third.header());
+    let key = first
+        .key_specifier()
+        .or_else(|| second.key_specifier())
+        .or_else(|| third.key_specifier())
+        .ok_or_else(|| {
+            Error::invalid_format("no key specifier: none of the files has a header declaring one")
+        })?;
+    Ok(FileMerge {
+        result: merge_with(base.table(), ours.table(), theirs.table(), options),
+        header: header.cloned(),
+        key: key.clone(),
+    })
+}
+
+/// Merges two tables derived from a common ancestor with the given options.
+///
+/// See [`merge`].
+#[must_use]
+pub fn merge_with(
+    base: &Table,
+    ours: &Table,
+    theirs: &Table,
+    options: MergeOptions,
+) -> MergeResult {
+    let keys: BTreeSet<&Key> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+    let mut result = MergeResult::default();
+    for key in keys {
+        let (b, o, t) = (base.get(key), ours.get(key), theirs.get(key));
+        let merged = if o == t || t == b {
+            Ok(o.cloned())
+        } else if o == b {
+            Ok(t.cloned())
+        } else {
+            merge_records(b, o, t, options)
+        };
+        match merged {
+            Ok(Some(record)) => result.table.insert(key.clone(), record),
+            Ok(None) => {}
+            Err(fields) => {
+                if let Some(record) = o {
+                    result.table.insert(key.clone(), record.clone());
+                }
+                result.conflicts.push(Conflict {
+                    key: key.clone(),
+                    base: b.cloned(),
+                    ours: o.cloned(),
+                    theirs: t.cloned(),
+                    fields,
+                });
+            }
+        }
+    }
+    result
+}
+
+/// Merges a record changed differently on both sides.
+///
+/// Returns the conflicting field pointers on failure.
+fn merge_records(
+    base: Option<&Record>,
+    ours: Option<&Record>,
+    theirs: Option<&Record>,
+    options: MergeOptions,
+) -> std::result::Result<Option<Record>, Vec<String>> {
+    let (Some(ours), Some(theirs)) = (ours, theirs) else {
+        return Err(Vec::new());
+    };
+    if !options.field_level || !ours.value().is_object() || !theirs.value().is_object() {
+        return Err(Vec::new());
+    }
+    let mut conflicts = Vec::new();
+    let merged = merge_values(
+        base.map(Record::value),
+        Some(ours.value()),
+        Some(theirs.value()),
+        &mut String::new(),
+        &mut conflicts,
+    );
+    if conflicts.is_empty() {
+        Ok(merged.map(Record::from_value_unchecked))
+    } else {
+        Err(conflicts)
+    }
+}
+
+/// Merges a JSON value recursively, recording the pointers of conflicting
+/// values in `conflicts` and keeping our side for them.
+fn merge_values(
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    path: &mut String,
+    conflicts: &mut Vec<String>,
+) -> Option<Value> {
+    if ours == theirs || theirs == base {
+        return ours.cloned();
+    }
+    if ours == base {
+        return theirs.cloned();
+    }
+    let base_object = match base {
+        None => Some(None),
+        Some(Value::Object(object)) => Some(Some(object)),
+        Some(_) => None,
+    };
+    let (Some(base_object), Some(Value::Object(ours)), Some(Value::Object(theirs))) =
+        (base_object, ours, theirs)
+    else {
+        conflicts.push(path.clone());
+        return ours.cloned();
+    };
+
+    let fields: BTreeSet<&String> = ours.keys().chain(theirs.keys()).collect();
+    let mut merged = Map::new();
+    for field in fields {
+        let len = path.len();
+        push_segment(path, field);
+        let value = merge_values(
+            base_object.and_then(|object| object.get(field)),
+            ours.get(field),
+            theirs.get(field),
+            path,
+            conflicts,
+        );
+        path.truncate(len);
+        if let Some(value) = value {
+            merged.insert(field.clone(), value);
+        }
+    }
+    Some(Value::Object(merged))
+}
@@ -1,6 +1,9 @@
 //! Operations for JSONLT tables.
 
-use crate::{Error, Record, Result, Table};
+use crate::reference::{check_record, deletions};
+use crate::{Error, Key, Record, Reference, Result, Table};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
 
 /// Operations that can be performed on a JSONLT table.
 #[derive(Debug, Clone)]
@@ -8,7 +11,7 @@ pub enum Operations {
     /// Insert a new record.
     Insert {
         /// The key for the new record.
-        key: String,
+        key: Key,
         /// The record to insert.
         record: Record,
     },
@@ -16,7 +19,7 @@ pub enum Operations {
     /// Update an existing record.
     Update {
         /// The key of the record to update.
-        key: String,
+        key: Key,
         /// The updated record data.
         record: Record,
     },
@@ -24,13 +27,13 @@ pub enum Operations {
     /// Delete a record.
     Delete {
         /// The key of the record to delete.
-        key: String,
+        key: Key,
     },
 
     /// Upsert a record (insert or update).
     Upsert {
         /// The key for the record.
-        key: String,
+        key: Key,
         /// The record data.
         record: Record,
     },
@@ -48,19 +51,19 @@ impl Operations {
         match self {
             Self::Insert { key, record } => {
                 if table.contains_key(&key) {
-                    return Err(Error::AlreadyExists(key));
+                    return Err(Error::AlreadyExists { key });
                 }
                 table.insert(key, record);
             }
             Self::Update { key, record } => {
                 if !table.contains_key(&key) {
-                    return Err(Error::NotFound(key));
+                    return Err(Error::NotFound { key });
                 }
                 table.insert(key, record);
             }
             Self::Delete { key } => {
                 if table.remove(&key).is_none() {
-                    return Err(Error::NotFound(key));
+                    return Err(Error::NotFound { key });
                 }
             }
             Self::Upsert { key, record } => {
@@ -69,4 +72,91 @@ impl Operations {
         }
         Ok(())
     }
+
+    /// Applies this operation to the table named `table`, enforcing
+    /// `references` between the tables.
+    ///
+    /// Inserted and updated records must reference existing records. A
+    /// delete also deletes the records referencing the deleted one through
+    /// a reference with [`OnDelete::Cascade`](crate::OnDelete::Cascade),
+    /// recursively, and is rejected if that would leave any record with a
+    /// dangling reference. Nothing is changed when an error is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - [`Operations::apply`] would
+    /// - a table named by the operation or a reference does not exist
+    /// - the operation would leave a dangling reference
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::{ErrorKind, OnDelete, Operations, Record, Reference, Table};
+    /// use std::collections::BTreeMap;
+    ///
+    /// let mut tables = BTreeMap::from([
+    ///     ("users".to_string(), Table::new()),
+    ///     ("orders".to_string(), Table::new()),
+    /// ]);
+    /// let references = [Reference::new("orders", "user_id", "users")];
+    ///
+    /// let order = Record::try_from(r#"{"id":1,"user_id":"alice"}"#).unwrap();
+    /// let insert = Operations::Insert { key: 1.into(), record: order };
+    /// let err = insert.clone().apply_with_references("orders", &mut tables, &references);
+    /// assert_eq!(err.unwrap_err().kind(), ErrorKind::DanglingReference);
+    ///
+    /// let user = Record::try_from(r#"{"id":"alice"}"#).unwrap();
+    /// Operations::Insert { key: "alice".into(), record: user }
+    ///     .apply_with_references("users", &mut tables, &references)
+    ///     .unwrap();
+    /// insert.apply_with_references("orders", &mut tables, &references).unwrap();
+    ///
+    /// let references = [references[0].clone().on_delete(OnDelete::Cascade)];
+    /// Operations::Delete { key: "alice".into() }
+    ///     .apply_with_references("users", &mut tables, &references)
+    ///     .unwrap();
+    /// assert!(tables["orders"].is_empty());
+    /// ```
+    pub fn apply_with_references(
+        self,
+        table: &str,
+        tables: &mut BTreeMap<String, Table>,
+        references: &[Reference],
+    ) -> Result<()> {
+        let missing = || Error::TableNotFound {
+            name: table.to_string(),
+        };
+        match self {
+            Self::Insert {
+                ref key,
+                ref record,
+            }
+            | Self::Update {
+                ref key,
+                ref record,
+            }
+            | Self::Upsert {
+                ref key,
+                ref record,
+            } => {
+                if !tables.contains_key(table) {
+                    return Err(missing());
+                }
+                check_record(table, key, record, tables, references)?;
+                self.apply(tables.get_mut(table).ok_or_else(missing)?)
+            }
+            Self::Delete { key } => {
+                if !tables.get(table).ok_or_else(missing)?.contains_key(&key) {
+                    return Err(Error::NotFound { key });
+                }
+                for (name, key) in deletions(table, key, tables, references)? {
+                    if let Some(table) = tables.get_mut(&name) {
+                        table.remove(&key);
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
 }
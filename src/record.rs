@@ -1,68 +1,654 @@
 //! Record type representing a single JSONLT record.
 
+use crate::json::{self, Map, Value};
+use crate::{Error, Key, KeySpecifier, Result, DELETED_FIELD};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "serde")]
+use core::fmt;
+#[cfg(feature = "serde")]
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_json::value::RawValue;
+
+/// The largest size of a record, in bytes of its canonical serialization.
+pub const MAX_RECORD_SIZE: usize = 1 << 20;
+
+/// The deepest nesting of objects and arrays in a record, counting the record
+/// itself as depth 1.
+pub const MAX_RECORD_DEPTH: usize = 64;
 
 /// A single record in a JSONLT table.
 ///
-/// Records are stored as JSON objects with a key field that identifies them.
-#[derive(Debug, Clone, Default)]
+/// Records are JSON objects with a key field that identifies them. Records
+/// built with [`Record::try_from_value`] and the other checked conversions
+/// are always objects that use no reserved `$` field and stay within
+/// [`MAX_RECORD_SIZE`] and [`MAX_RECORD_DEPTH`]. Tombstones are therefore
+/// never records; they are read as [`Entry::Tombstone`](crate::Entry).
+///
+/// With the `serde` feature, a record serializes as its object, and
+/// deserializing one makes the same checks as [`Record::try_from_value`].
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "Value", into = "Value"))]
 pub struct Record {
     /// The record data as a JSON value.
-    #[cfg(feature = "serde")]
-    data: serde_json::Value,
-
-    /// The record data as raw bytes (when serde is not enabled).
-    #[cfg(not(feature = "serde"))]
-    data: Vec<u8>,
+    data: Value,
 }
 
 impl Record {
-    /// Creates a new empty record.
+    /// Creates a new record with no fields.
     #[must_use]
     pub fn new() -> Self {
         Self::default()
     }
 
-    /// Creates a record from a JSON value.
-    #[cfg(feature = "serde")]
+    /// Creates a record from a JSON value, checking that it is a valid
+    /// record.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value is not an object, has a reserved `$`
+    /// field such as `$deleted`, or exceeds [`MAX_RECORD_SIZE`] or
+    /// [`MAX_RECORD_DEPTH`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::json::from_str;
+    /// use jsonlt::Record;
+    ///
+    /// assert!(Record::try_from_value(from_str(r#"{"id":1}"#).unwrap()).is_ok());
+    /// assert!(Record::try_from_value(from_str("[1]").unwrap()).is_err());
+    /// assert!(Record::try_from_value(from_str(r#"{"$id":1}"#).unwrap()).is_err());
+    /// assert!(Record::try_from_value(from_str(r#"{"id":1,"$deleted":true}"#).unwrap()).is_err());
+    /// ```
+    pub fn try_from_value(value: Value) -> Result<Self> {
+        check(&value)?;
+        Ok(Self { data: value })
+    }
+
+    /// Creates a record from a JSON value without checking it.
+    ///
+    /// This is for values already known to be valid records, such as those
+    /// taken from another record. A record built from anything else may be
+    /// rejected when it is written, or written as an invalid line.
     #[must_use]
-    pub const fn from_value(value: serde_json::Value) -> Self {
+    pub const fn from_value_unchecked(value: Value) -> Self {
         Self { data: value }
     }
 
+    /// Checks that the record is still valid, as described for
+    /// [`Record::try_from_value`], after changes through
+    /// [`Record::value_mut`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record is not valid.
+    pub fn validate(&self) -> Result<()> {
+        check(&self.data)
+    }
+
     /// Returns the record data as a JSON value reference.
-    #[cfg(feature = "serde")]
     #[must_use]
-    pub const fn value(&self) -> &serde_json::Value {
+    pub const fn value(&self) -> &Value {
         &self.data
     }
 
     /// Returns the record data as a mutable JSON value reference.
-    #[cfg(feature = "serde")]
-    pub fn value_mut(&mut self) -> &mut serde_json::Value {
+    ///
+    /// Changes made through the reference are not checked; see
+    /// [`Record::validate`].
+    pub fn value_mut(&mut self) -> &mut Value {
         &mut self.data
     }
 
-    /// Creates a record from raw bytes.
-    #[cfg(not(feature = "serde"))]
+    /// Serializes a value into a record.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value cannot be serialized or does not
+    /// serialize to a valid record, as described for
+    /// [`Record::try_from_value`].
+    #[cfg(feature = "serde")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn from_serialize<T: Serialize + ?Sized>(value: &T) -> Result<Self> {
+        Self::try_from_value(serde_json::to_value(value)?)
+    }
+
+    /// Deserializes the whole record as a `T`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record does not have the shape of a `T`.
+    #[cfg(feature = "serde")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn deserialize_as<'a, T: Deserialize<'a>>(&'a self) -> Result<T> {
+        Ok(T::deserialize(&self.data)?)
+    }
+
+    /// Returns the value at `path`.
+    ///
+    /// A path starting with `/` is a JSON pointer, and the empty path is the
+    /// whole record; any other path names a top-level field, so
+    /// `record.get("name")` and `record.get("/name")` are the same.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::Record;
+    ///
+    /// let record = Record::try_from(r#"{"id":1,"tags":["a","b"],"a/b":true}"#).unwrap();
+    /// assert_eq!(record.get("id").and_then(|id| id.as_i64()), Some(1));
+    /// assert_eq!(record.get("/tags/1").and_then(|tag| tag.as_str()), Some("b"));
+    /// assert!(record.get("a/b").is_some());
+    /// assert!(record.get("/missing").is_none());
+    /// ```
     #[must_use]
-    pub fn from_bytes(bytes: Vec<u8>) -> Self {
-        Self { data: bytes }
+    pub fn get(&self, path: &str) -> Option<&Value> {
+        tokens(path)
+            .iter()
+            .try_fold(&self.data, |value, token| member(value, token))
+    }
+
+    /// Deserializes the value at `path`, as described for [`Record::get`].
+    ///
+    /// Returns `None` if there is no value at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value does not have the shape of a `T`.
+    #[cfg(feature = "serde")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn get_as<'a, T: Deserialize<'a>>(&'a self, path: &str) -> Result<Option<T>> {
+        self.get(path)
+            .map(T::deserialize)
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Sets the value at `path`, as described for [`Record::get`], returning
+    /// the value it replaces.
+    ///
+    /// Missing objects along the path are created, and the last segment may
+    /// name an object member, the index of an existing array element, or
+    /// `-` or the array length to append to an array. The record is left
+    /// unchanged if the change fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a value along the path is neither an object nor an
+    /// array, an array index is invalid or out of range, or the change would
+    /// make the record invalid, as described for [`Record::try_from_value`].
+    pub fn set(&mut self, path: &str, value: impl Into<Value>) -> Result<Option<Value>> {
+        let mut data = self.data.clone();
+        let previous = set_at(&mut data, path, value.into())?;
+        check(&data)?;
+        self.data = data;
+        Ok(previous)
+    }
+
+    /// Removes the value at `path`, as described for [`Record::get`], and
+    /// returns it.
+    ///
+    /// Removing an array element shifts the elements after it. Returns
+    /// `None` if there is no value at `path`, or for the empty path, since
+    /// the record itself cannot be removed.
+    pub fn remove(&mut self, path: &str) -> Option<Value> {
+        let tokens = tokens(path);
+        let (last, parents) = tokens.split_last()?;
+        let mut current = &mut self.data;
+        for token in parents {
+            current = match current {
+                Value::Object(object) => object.get_mut(token.as_str())?,
+                Value::Array(items) => items.get_mut(index(token)?)?,
+                _ => return None,
+            };
+        }
+        match current {
+            Value::Object(object) => object.remove(last.as_str()),
+            Value::Array(items) => {
+                let index = index(last).filter(|&index| index < items.len())?;
+                Some(items.remove(index))
+            }
+            _ => None,
+        }
     }
 
-    /// Returns the record data as raw bytes.
-    #[cfg(not(feature = "serde"))]
+    /// Extracts the key of the record with a key specifier.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record is not an object, or a key field is
+    /// missing or holds an invalid key value.
+    pub fn key(&self, key: &KeySpecifier) -> Result<Key> {
+        let object = self
+            .data
+            .as_object()
+            .ok_or_else(|| Error::invalid_format("record must be a JSON object"))?;
+        key.extract(object)
+    }
+
+    /// Returns an iterator over the top-level fields of the record, in name
+    /// order.
+    pub fn fields(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.data
+            .as_object()
+            .into_iter()
+            .flat_map(|object| object.iter())
+    }
+
+    /// Returns `true` if the record is a tombstone, an object whose
+    /// `$deleted` field is `true`.
+    ///
+    /// Records built with the checked conversions, including every record
+    /// read from a file, are never tombstones; only one built with
+    /// [`Record::from_value_unchecked`] can be.
     #[must_use]
-    pub fn as_bytes(&self) -> &[u8] {
-        &self.data
+    pub fn is_tombstone(&self) -> bool {
+        self.data.get(DELETED_FIELD) == Some(&Value::Bool(true))
+    }
+}
+
+/// Sets the value at `path` in `data`, as described for [`Record::set`],
+/// without checking the result.
+fn set_at(data: &mut Value, path: &str, value: Value) -> Result<Option<Value>> {
+    let tokens = tokens(path);
+    let Some((last, parents)) = tokens.split_last() else {
+        return Ok(Some(core::mem::replace(data, value)));
+    };
+    let unreachable = |depth: usize| {
+        Error::invalid_format(format!(
+            "cannot set {path}: {} is not an object or array",
+            pointer(&tokens[..depth])
+        ))
+    };
+    let mut current = data;
+    for (depth, token) in parents.iter().enumerate() {
+        current = match current {
+            Value::Object(object) => object
+                .entry(token.clone())
+                .or_insert_with(|| Value::Object(Map::new())),
+            Value::Array(items) => {
+                let index = index(token).filter(|&index| index < items.len());
+                &mut items[index.ok_or_else(|| unreachable(depth + 1))?]
+            }
+            _ => return Err(unreachable(depth)),
+        };
+    }
+    match current {
+        Value::Object(object) => Ok(object.insert(last.clone(), value)),
+        Value::Array(items) => {
+            let index = if last == "-" {
+                Some(items.len())
+            } else {
+                index(last)
+            };
+            match index {
+                Some(index) if index < items.len() => {
+                    Ok(Some(core::mem::replace(&mut items[index], value)))
+                }
+                Some(index) if index == items.len() => {
+                    items.push(value);
+                    Ok(None)
+                }
+                _ => Err(Error::invalid_format(format!(
+                    "cannot set {path}: {last:?} is not a valid index"
+                ))),
+            }
+        }
+        _ => Err(unreachable(parents.len())),
+    }
+}
+
+/// Checks that a value is a valid record.
+fn check(value: &Value) -> Result<()> {
+    let Value::Object(object) = value else {
+        return Err(Error::invalid_format("record must be a JSON object"));
+    };
+    for field in object.keys() {
+        if field.starts_with('$') {
+            return Err(Error::invalid_format(format!(
+                "field {field:?} uses the reserved '$' prefix"
+            )));
+        }
+    }
+    if depth(value) > MAX_RECORD_DEPTH {
+        return Err(Error::invalid_format(format!(
+            "record nests deeper than {MAX_RECORD_DEPTH} levels"
+        )));
+    }
+    let size = crate::to_canonical_string(value).len();
+    if size > MAX_RECORD_SIZE {
+        return Err(Error::invalid_format(format!(
+            "record is {size} bytes, more than the limit of {MAX_RECORD_SIZE}"
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the nesting depth of a value, where scalars have depth 0.
+fn depth(value: &Value) -> usize {
+    match value {
+        Value::Array(items) => 1 + items.iter().map(depth).max().unwrap_or(0),
+        Value::Object(object) => 1 + object.values().map(depth).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Splits a path into unescaped JSON pointer segments.
+fn tokens(path: &str) -> Vec<String> {
+    match path.strip_prefix('/') {
+        Some(pointer) => pointer
+            .split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect(),
+        None if path.is_empty() => Vec::new(),
+        None => alloc::vec![path.into()],
+    }
+}
+
+/// Builds the JSON pointer of a list of segments.
+fn pointer(tokens: &[String]) -> String {
+    if tokens.is_empty() {
+        return "the record".into();
+    }
+    let mut pointer = String::new();
+    for token in tokens {
+        pointer.push('/');
+        pointer.push_str(&token.replace('~', "~0").replace('/', "~1"));
+    }
+    pointer
+}
+
+/// Parses an array index segment, rejecting leading zeros and signs as JSON
+/// pointers do.
+fn index(token: &str) -> Option<usize> {
+    if token.starts_with('+') || (token.starts_with('0') && token.len() > 1) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+/// Returns the member or element a segment names.
+fn member<'a>(value: &'a Value, token: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(object) => object.get(token),
+        Value::Array(items) => items.get(index(token)?),
+        _ => None,
+    }
+}
+
+impl Default for Record {
+    fn default() -> Self {
+        Self {
+            data: Value::Object(Map::new()),
+        }
+    }
+}
+
+impl TryFrom<Value> for Record {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<Self> {
+        Self::try_from_value(value)
+    }
+}
+
+impl TryFrom<Map<String, Value>> for Record {
+    type Error = Error;
+
+    fn try_from(object: Map<String, Value>) -> Result<Self> {
+        Self::try_from_value(Value::Object(object))
+    }
+}
+
+impl From<Record> for Value {
+    fn from(record: Record) -> Self {
+        record.data
+    }
+}
+
+/// Parses a record from JSON text, checking it as
+/// [`Record::try_from_value`] does.
+impl TryFrom<&str> for Record {
+    type Error = Error;
+
+    fn try_from(text: &str) -> Result<Self> {
+        Self::try_from_value(json::from_str(text)?)
     }
 }
 
+/// A borrowed view of a record that has not been parsed.
+///
+/// The view wraps the JSON text of a record object, typically a line of a
+/// file, and looks up fields by scanning the text without building a
+/// [`serde_json::Value`]. Filters that only look at a few fields of large
+/// records avoid most of the cost of parsing them; records that are kept
+/// can be parsed in full with [`RecordRef::to_record`].
+///
+/// # Examples
+///
+/// ```rust
+/// use jsonlt::RecordRef;
+///
+/// let line = r#"{"id":"alice","address":{"city":"Paris"},"tags":["a","b"]}"#;
+/// let record = RecordRef::parse(line).unwrap();
+///
+/// assert_eq!(record.pointer("/address/city").unwrap().get(), r#""Paris""#);
+/// assert_eq!(record.pointer_as::<String>("/tags/1").unwrap(), Some("b".into()));
+/// assert!(record.get("missing").is_none());
+/// ```
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Copy)]
+pub struct RecordRef<'a> {
+    raw: &'a RawValue,
+}
+
+#[cfg(feature = "serde")]
+impl<'a> RecordRef<'a> {
+    /// Checks that `json` is a JSON object and wraps it.
+    ///
+    /// Surrounding whitespace, including a line terminator, is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `json` is not valid JSON or not an object.
+    pub fn parse(json: &'a str) -> Result<Self> {
+        let raw: &RawValue = serde_json::from_str(json.trim())?;
+        if !raw.get().starts_with('{') {
+            return Err(Error::invalid_format("record must be a JSON object"));
+        }
+        Ok(Self { raw })
+    }
+
+    /// Returns the JSON text of the record.
+    #[must_use]
+    pub fn as_str(&self) -> &'a str {
+        self.raw.get()
+    }
+
+    /// Returns the record as a raw JSON value.
+    #[must_use]
+    pub const fn as_raw(&self) -> &'a RawValue {
+        self.raw
+    }
+
+    /// Returns the raw value of a top-level field.
+    #[must_use]
+    pub fn get(&self, field: &str) -> Option<&'a RawValue> {
+        child(self.raw, field)
+    }
+
+    /// Returns the raw value a JSON pointer refers to, as
+    /// [`serde_json::Value::pointer`] would.
+    #[must_use]
+    pub fn pointer(&self, pointer: &str) -> Option<&'a RawValue> {
+        if pointer.is_empty() {
+            return Some(self.raw);
+        }
+        let mut value = self.raw;
+        for segment in pointer.strip_prefix('/')?.split('/') {
+            let segment = segment.replace("~1", "/").replace("~0", "~");
+            value = child(value, &segment)?;
+        }
+        Some(value)
+    }
+
+    /// Deserializes the value a JSON pointer refers to.
+    ///
+    /// Returns `None` if there is no such value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value cannot be deserialized as `T`.
+    pub fn pointer_as<T: Deserialize<'a>>(&self, pointer: &str) -> Result<Option<T>> {
+        self.pointer(pointer)
+            .map(|value| serde_json::from_str(value.get()))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Parses the whole record.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record cannot be parsed or is not a valid
+    /// record, as described for [`Record::try_from_value`].
+    pub fn to_record(&self) -> Result<Record> {
+        Record::try_from_value(serde_json::from_str(self.raw.get())?)
+    }
+}
+
+/// Returns the member of an object or the element of an array that a JSON
+/// pointer segment names.
+#[cfg(feature = "serde")]
+fn child<'a>(value: &'a RawValue, segment: &str) -> Option<&'a RawValue> {
+    let mut deserializer = serde_json::Deserializer::from_str(value.get());
+    Child { segment }
+        .deserialize(&mut deserializer)
+        .ok()
+        .flatten()
+}
+
+/// Scans an object or array for one of its members without parsing the
+/// others.
+#[cfg(feature = "serde")]
+struct Child<'s> {
+    segment: &'s str,
+}
+
 #[cfg(feature = "serde")]
-impl From<serde_json::Value> for Record {
-    fn from(value: serde_json::Value) -> Self {
-        Self::from_value(value)
+impl<'de> DeserializeSeed<'de> for Child<'_> {
+    type Value = Option<&'de RawValue>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Visitor<'de> for Child<'_> {
+    type Value = Option<&'de RawValue>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(
+        self,
+        mut map: A,
+    ) -> std::result::Result<Self::Value, A::Error> {
+        // Like serde_json, the last of duplicate members wins.
+        let mut found = None;
+        while let Some(matches) = map.next_key_seed(Name(self.segment))? {
+            if matches {
+                found = Some(map.next_value()?);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(found)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut seq: A,
+    ) -> std::result::Result<Self::Value, A::Error> {
+        let segment = self.segment;
+        let index = if segment.starts_with('+') || (segment.starts_with('0') && segment.len() > 1) {
+            None
+        } else {
+            segment.parse::<usize>().ok()
+        };
+        let mut found = None;
+        let mut i = 0;
+        while let Some(element) = seq.next_element()? {
+            if Some(i) == index {
+                found = Some(element);
+            }
+            i += 1;
+        }
+        Ok(found)
+    }
+
+    fn visit_bool<E>(self, _: bool) -> std::result::Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_i64<E>(self, _: i64) -> std::result::Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_u64<E>(self, _: u64) -> std::result::Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_f64<E>(self, _: f64) -> std::result::Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_str<E>(self, _: &str) -> std::result::Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E>(self) -> std::result::Result<Self::Value, E> {
+        Ok(None)
+    }
+}
+
+/// Compares a member name with a pointer segment without allocating,
+/// unless the name contains escapes.
+#[cfg(feature = "serde")]
+struct Name<'s>(&'s str);
+
+#[cfg(feature = "serde")]
+impl<'de> DeserializeSeed<'de> for Name<'_> {
+    type Value = bool;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<bool, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Visitor<'_> for Name<'_> {
+    type Value = bool;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a member name")
+    }
+
+    fn visit_str<E>(self, name: &str) -> std::result::Result<bool, E> {
+        Ok(name == self.0)
     }
 }
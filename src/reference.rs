@@ -0,0 +1,372 @@
+//! References from the records of one table to the keys of another.
+
+use crate::json::Value;
+use crate::{Error, Key, Record, Result, Table};
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+/// What deleting a referenced record does to the records referencing it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum OnDelete {
+    /// The delete is rejected.
+    #[default]
+    Restrict,
+
+    /// The referencing records are deleted too.
+    Cascade,
+}
+
+/// A declaration that a field of the records in one table holds keys of
+/// another table, like a foreign key.
+///
+/// A record whose field is missing or `null` references nothing. A
+/// reference to a table with a compound key holds an array of the key's
+/// elements, such as `["eu",1]`.
+///
+/// # Examples
+///
+/// ```rust
+/// use jsonlt::{OnDelete, Reference};
+///
+/// let reference: Reference = "orders.user_id -> users".parse().unwrap();
+/// assert_eq!(reference, Reference::new("orders", "user_id", "users"));
+///
+/// let reference = reference.on_delete(OnDelete::Cascade);
+/// assert_eq!(reference.to_string(), "orders.user_id -> users");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Reference {
+    /// The table holding the referencing records.
+    pub table: String,
+
+    /// The referencing field, or a JSON pointer to it; see [`Record::get`].
+    pub field: String,
+
+    /// The table whose keys are referenced.
+    pub target: String,
+
+    /// What deleting a referenced record does, when references are enforced
+    /// by [`Operations::apply_with_references`](crate::Operations::apply_with_references)
+    /// or a database transaction.
+    pub on_delete: OnDelete,
+}
+
+impl Reference {
+    /// Creates a reference from `table.field` to the keys of `target`, which
+    /// restricts deletes.
+    #[must_use]
+    pub fn new(
+        table: impl Into<String>,
+        field: impl Into<String>,
+        target: impl Into<String>,
+    ) -> Self {
+        Self {
+            table: table.into(),
+            field: field.into(),
+            target: target.into(),
+            on_delete: OnDelete::default(),
+        }
+    }
+
+    /// Sets what deleting a referenced record does.
+    #[must_use]
+    pub const fn on_delete(mut self, on_delete: OnDelete) -> Self {
+        self.on_delete = on_delete;
+        self
+    }
+
+    /// Returns the value `record` references, unless it references nothing.
+    fn value<'a>(&self, record: &'a Record) -> Option<&'a Value> {
+        record.get(&self.field).filter(|value| !value.is_null())
+    }
+
+    /// Returns the key `record` references, if it references a valid one.
+    fn key(&self, record: &Record) -> Option<Key> {
+        self.value(record).and_then(referenced)
+    }
+
+    fn dangling(&self, key: &Key, value: &Value) -> DanglingReference {
+        DanglingReference {
+            reference: self.clone(),
+            key: key.clone(),
+            value: value.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{} -> {}", self.table, self.field, self.target)
+    }
+}
+
+impl FromStr for Reference {
+    type Err = Error;
+
+    /// Parses a reference written as `table.field -> target`.
+    ///
+    /// The table name ends at the first dot, so the field name may contain
+    /// dots.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            Error::invalid_format(alloc::format!(
+                "reference must be written as `table.field -> target`, found `{s}`"
+            ))
+        };
+        let (source, target) = s.split_once("->").ok_or_else(invalid)?;
+        let (table, field) = source.trim().split_once('.').ok_or_else(invalid)?;
+        let target = target.trim();
+        if [table, field, target].iter().any(|name| name.is_empty()) {
+            return Err(invalid());
+        }
+        Ok(Self::new(table, field, target))
+    }
+}
+
+/// A record referencing a key that its target table does not have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DanglingReference {
+    /// The reference the record violates.
+    pub reference: Reference,
+
+    /// The key of the referencing record.
+    pub key: Key,
+
+    /// The value of the referencing field.
+    pub value: Value,
+}
+
+impl fmt::Display for DanglingReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Reference {
+            table,
+            field,
+            target,
+            ..
+        } = &self.reference;
+        write!(
+            f,
+            "{table} record {}: {field} references missing {target} record {}",
+            self.key, self.value
+        )
+    }
+}
+
+/// Returns the key named by a referencing value: a string or an integer,
+/// or an array of them for a compound key.
+fn referenced(value: &Value) -> Option<Key> {
+    match value {
+        Value::Array(elements) => elements
+            .iter()
+            .map(|element| Key::from_value(element).ok())
+            .collect::<Option<Vec<_>>>()
+            .map(Key::Tuple),
+        other => Key::from_value(other).ok(),
+    }
+}
+
+/// Returns `true` if `value` is a key of the `target` table.
+fn resolves(value: &Value, target: &Table) -> bool {
+    referenced(value).is_some_and(|key| target.contains_key(&key))
+}
+
+/// Finds the records whose references do not resolve.
+///
+/// `tables` looks up a table by name. Dangling references are returned in
+/// the order of `references`, then by key.
+///
+/// # Errors
+///
+/// Returns [`Error::TableNotFound`] if a table named by a reference cannot
+/// be looked up.
+///
+/// # Examples
+///
+/// ```rust
+/// use jsonlt::{check_references, Record, Reference, Table};
+/// use std::collections::BTreeMap;
+///
+/// let mut tables = BTreeMap::from([
+///     ("users".to_string(), Table::new()),
+///     ("orders".to_string(), Table::new()),
+/// ]);
+/// let order = Record::try_from(r#"{"id":1,"user_id":"alice"}"#).unwrap();
+/// tables.get_mut("orders").unwrap().insert(1, order);
+///
+/// let references = ["orders.user_id -> users".parse::<Reference>().unwrap()];
+/// let dangling = check_references(&references, |name| tables.get(name)).unwrap();
+/// assert_eq!(dangling.len(), 1);
+/// assert_eq!(
+///     dangling[0].to_string(),
+///     r#"orders record 1: user_id references missing users record "alice""#
+/// );
+/// ```
+pub fn check_references<'a>(
+    references: &[Reference],
+    tables: impl Fn(&str) -> Option<&'a Table>,
+) -> Result<Vec<DanglingReference>> {
+    let mut dangling = Vec::new();
+    for reference in references {
+        let source = require(&tables, &reference.table)?;
+        let target = require(&tables, &reference.target)?;
+        dangling.extend(source.iter().filter_map(|(key, record)| {
+            reference
+                .value(record)
+                .filter(|value| !resolves(value, target))
+                .map(|value| reference.dangling(key, value))
+        }));
+    }
+    Ok(dangling)
+}
+
+/// A set of named tables that references are enforced on.
+pub trait Tables {
+    /// Returns `true` if there is a table named `name`.
+    fn has_table(&self, name: &str) -> bool;
+
+    /// Returns `true` if table `name` has a record with key `key`.
+    fn contains(&self, name: &str, key: &Key) -> bool;
+
+    /// Returns the records of table `name`.
+    fn records<'a>(&'a self, name: &str) -> Box<dyn Iterator<Item = (&'a Key, &'a Record)> + 'a>;
+}
+
+impl Tables for BTreeMap<String, Table> {
+    fn has_table(&self, name: &str) -> bool {
+        self.contains_key(name)
+    }
+
+    fn contains(&self, name: &str, key: &Key) -> bool {
+        self.get(name).is_some_and(|table| table.contains_key(key))
+    }
+
+    fn records<'a>(&'a self, name: &str) -> Box<dyn Iterator<Item = (&'a Key, &'a Record)> + 'a> {
+        Box::new(self.get(name).into_iter().flat_map(Table::iter))
+    }
+}
+
+/// Checks that `record`, about to be stored under `key` in table `name`,
+/// only references existing records.
+pub fn check_record(
+    name: &str,
+    key: &Key,
+    record: &Record,
+    tables: &impl Tables,
+    references: &[Reference],
+) -> Result<()> {
+    for reference in references.iter().filter(|r| r.table == name) {
+        let Some(value) = reference.value(record) else {
+            continue;
+        };
+        has_table(tables, &reference.target)?;
+        let target = referenced(value);
+        let refers_to_itself = reference.target == name && target.as_ref() == Some(key);
+        if !refers_to_itself
+            && !target.is_some_and(|target| tables.contains(&reference.target, &target))
+        {
+            return Err(Error::DanglingReference(Box::new(
+                reference.dangling(key, value),
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that no record references `key` of table `name`, which is about
+/// to be deleted without following references.
+#[cfg(feature = "std")]
+pub fn check_deleted(
+    name: &str,
+    key: &Key,
+    tables: &impl Tables,
+    references: &[Reference],
+) -> Result<()> {
+    match referencing(name, key, None, tables, references)?.first() {
+        Some((reference, referencing)) => Err(Error::DanglingReference(Box::new(
+            reference.dangling(referencing, &key.to_value()),
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Returns every record deleted by deleting `key` from table `name`,
+/// following cascading references.
+///
+/// Each deleted record scans the tables referencing its table, so this is
+/// linear in their size.
+pub fn deletions(
+    name: &str,
+    key: Key,
+    tables: &impl Tables,
+    references: &[Reference],
+) -> Result<BTreeSet<(String, Key)>> {
+    let mut deleted = BTreeSet::new();
+    let mut pending = alloc::vec![(name.to_string(), key)];
+    while let Some((name, key)) = pending.pop() {
+        for (reference, referencing) in
+            referencing(&name, &key, Some(OnDelete::Cascade), tables, references)?
+        {
+            pending.push((reference.table.clone(), referencing.clone()));
+        }
+        deleted.insert((name, key));
+        pending.retain(|entry| !deleted.contains(entry));
+    }
+
+    for (name, key) in &deleted {
+        for (reference, referencing) in
+            referencing(name, key, Some(OnDelete::Restrict), tables, references)?
+        {
+            if !deleted.contains(&(reference.table.clone(), referencing.clone())) {
+                return Err(Error::DanglingReference(Box::new(
+                    reference.dangling(referencing, &key.to_value()),
+                )));
+            }
+        }
+    }
+    Ok(deleted)
+}
+
+/// Returns the records referencing `key` of table `name` through the
+/// references with the given delete behaviour, or through any reference.
+fn referencing<'a>(
+    name: &str,
+    key: &Key,
+    on_delete: Option<OnDelete>,
+    tables: &'a impl Tables,
+    references: &'a [Reference],
+) -> Result<Vec<(&'a Reference, &'a Key)>> {
+    let mut found = Vec::new();
+    for reference in references
+        .iter()
+        .filter(|r| r.target == name && on_delete.is_none_or(|on_delete| r.on_delete == on_delete))
+    {
+        has_table(tables, &reference.table)?;
+        found.extend(
+            tables
+                .records(&reference.table)
+                .filter(|(_, record)| reference.key(record).as_ref() == Some(key))
+                .map(|(key, _)| (reference, key)),
+        );
+    }
+    Ok(found)
+}
+
+fn has_table(tables: &impl Tables, name: &str) -> Result<()> {
+    if tables.has_table(name) {
+        Ok(())
+    } else {
+        Err(Error::TableNotFound {
+            name: name.to_string(),
+        })
+    }
+}
+
+fn require<'a>(tables: &impl Fn(&str) -> Option<&'a Table>, name: &str) -> Result<&'a Table> {
+    tables(name).ok_or_else(|| Error::TableNotFound {
+        name: name.to_string(),
+    })
+}
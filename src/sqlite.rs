@@ -0,0 +1,200 @@
+//! Materializing tables into SQLite and importing query results.
+
+use crate::{record_key, to_canonical_string, Error, Key, KeySpecifier, Record, Result, Table};
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params, Connection};
+use serde_json::{Map, Value};
+
+/// Options for SQLite export and import.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqliteOptions {
+    /// The name of the SQLite table to create.
+    pub table: String,
+
+    /// The name of the primary key column.
+    pub key_column: String,
+
+    /// The name of the column holding each record as JSON text.
+    pub json_column: String,
+
+    /// Record fields to expose as generated columns of the same name.
+    ///
+    /// Each column is computed from the JSON column with `json_extract`, so
+    /// it can be queried and indexed like an ordinary column.
+    pub columns: Vec<String>,
+}
+
+impl Default for SqliteOptions {
+    fn default() -> Self {
+        Self {
+            table: "records".into(),
+            key_column: "key".into(),
+            json_column: "record".into(),
+            columns: Vec::new(),
+        }
+    }
+}
+
+/// Quotes an SQL identifier.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Builds a JSON path for `json_extract` selecting a top-level field.
+fn json_path(field: &str) -> String {
+    format!(
+        "'$.{}'",
+        to_canonical_string(&Value::from(field)).replace('\'', "''")
+    )
+}
+
+impl Table {
+    /// Writes the table into a new SQLite table, replacing any existing
+    /// table of the same name.
+    ///
+    /// The table has a primary key column holding the key and a column
+    /// holding the canonical JSON text of each record, plus a generated
+    /// column for each of [`SqliteOptions::columns`]. String and integer keys
+    /// are stored as SQLite text and integers; tuple keys are stored as their
+    /// JSON text, such as `["acme",1]`. Records are inserted in key order in
+    /// a single transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an SQL statement fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::{Record, SqliteOptions, Table};
+    /// use rusqlite::Connection;
+    /// use serde_json::json;
+    ///
+    /// let mut table = Table::new();
+    /// table.insert("alice", Record::try_from_value(json!({"id": "alice", "age": 30})).unwrap());
+    /// table.insert("bob", Record::try_from_value(json!({"id": "bob", "age": 25})).unwrap());
+    ///
+    /// let conn = Connection::open_in_memory().unwrap();
+    /// let options = SqliteOptions { columns: vec!["age".into()], ..SqliteOptions::default() };
+    /// table.to_sqlite(&conn, &options).unwrap();
+    ///
+    /// let oldest: String = conn
+    ///     .query_row("SELECT key FROM records ORDER BY age DESC LIMIT 1", [], |row| row.get(0))
+    ///     .unwrap();
+    /// assert_eq!(oldest, "alice");
+    /// ```
+    pub fn to_sqlite(&self, conn: &Connection, options: &SqliteOptions) -> Result<()> {
+        let table = quote(&options.table);
+        let json_column = quote(&options.json_column);
+        let mut columns = vec![
+            format!("{} PRIMARY KEY NOT NULL", quote(&options.key_column)),
+            format!("{json_column} TEXT NOT NULL"),
+        ];
+        for field in &options.columns {
+            columns.push(format!(
+                "{} GENERATED ALWAYS AS (json_extract({json_column}, {})) VIRTUAL",
+                quote(field),
+                json_path(field)
+            ));
+        }
+
+        let records: Vec<_> = self.iter().collect();
+
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch(&format!(
+            "DROP TABLE IF EXISTS {table}; CREATE TABLE {table} ({});",
+            columns.join(", ")
+        ))?;
+        {
+            let mut insert = tx.prepare(&format!(
+                "INSERT INTO {table} ({}, {json_column}) VALUES (?1, ?2)",
+                quote(&options.key_column)
+            ))?;
+            for (key, record) in records {
+                let key = match key {
+                    Key::Integer(i) => SqlValue::Integer(*i),
+                    Key::String(s) => SqlValue::Text(s.clone()),
+                    Key::Tuple(_) => SqlValue::Text(to_canonical_string(&key.to_value())),
+                };
+                insert.execute(params![key, to_canonical_string(record.value())])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Builds a table from the rows of an SQLite query.
+    ///
+    /// Each row becomes a record whose fields are the result columns. A
+    /// column named [`SqliteOptions::json_column`] must hold a JSON object,
+    /// whose fields are used as the base of the record; the other columns
+    /// add or replace fields. This means a table written by
+    /// [`Table::to_sqlite`] can be read back with
+    /// `SELECT record FROM records`, and edited with queries such as
+    /// `SELECT record, upper(name) AS name FROM records`.
+    ///
+    /// SQL `NULL` becomes JSON `null`, and integers, reals and text map to
+    /// the corresponding JSON values. Later rows replace earlier rows with
+    /// the same key. The result can be written as a JSONLT file with
+    /// [`io::write_jsonlt`](crate::io::write_jsonlt).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails, a column holds a blob or invalid
+    /// JSON, or a row has no valid key.
+    pub fn from_sqlite(
+        conn: &Connection,
+        query: &str,
+        key: &KeySpecifier,
+        options: &SqliteOptions,
+    ) -> Result<Self> {
+        let mut statement = conn.prepare(query)?;
+        let names: Vec<String> = statement
+            .column_names()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let mut rows = statement.query([])?;
+        let mut table = Self::new();
+        let mut number = 0;
+        while let Some(row) = rows.next()? {
+            number += 1;
+            let at_row =
+                |message: String| Error::invalid_format(format!("row {number}: {message}"));
+            let mut object = Map::new();
+            let mut fields = Vec::with_capacity(names.len());
+            for (i, name) in names.iter().enumerate() {
+                let value = match row.get_ref(i)? {
+                    ValueRef::Null => Value::Null,
+                    ValueRef::Integer(i) => Value::from(i),
+                    ValueRef::Real(f) => Value::from(f),
+                    ValueRef::Text(text) => {
+                        let text = std::str::from_utf8(text)
+                            .map_err(|_| at_row(format!("column {name:?} is not UTF-8")))?;
+                        Value::from(text)
+                    }
+                    ValueRef::Blob(_) => {
+                        return Err(at_row(format!("column {name:?} holds a blob")));
+                    }
+                };
+                if *name == options.json_column {
+                    let Some(Value::Object(base)) = value
+                        .as_str()
+                        .and_then(|text| serde_json::from_str(text).ok())
+                    else {
+                        return Err(at_row(format!("column {name:?} must hold a JSON object")));
+                    };
+                    object.extend(base);
+                } else {
+                    fields.push((name.clone(), value));
+                }
+            }
+            object.extend(fields);
+            let record_key = record_key(&object, key).map_err(|err| at_row(err.to_string()))?;
+            let record = Record::try_from_value(Value::Object(object))
+                .map_err(|err| at_row(err.to_string()))?;
+            table.insert(record_key, record);
+        }
+        Ok(table)
+    }
+}
@@ -1,16 +1,27 @@
 //! Table type representing a JSONLT table.
 
-use crate::Record;
-use std::collections::HashMap;
+use crate::event::Observers;
+use crate::{ChangeEvent, ChangeKind, Key, Record, SubscriptionId};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::borrow::Borrow;
+#[cfg(feature = "std")]
+use std::sync::mpsc;
 
 /// A JSONLT table containing keyed records.
 ///
 /// The table stores records in an append-only manner, optimized for
 /// version control diffs and human readability.
+///
+/// Subscribers registered with [`Table::subscribe`] or
+/// [`Table::subscribe_channel`] are not carried over by [`Clone`].
 #[derive(Debug, Clone, Default)]
 pub struct Table {
     /// The records in the table, keyed by their identifier.
-    records: HashMap<String, Record>,
+    records: BTreeMap<Key, Record>,
+
+    /// The subscribers notified of changes to the table.
+    observers: Observers,
 }
 
 impl Table {
@@ -42,38 +53,136 @@ impl Table {
     }
 
     /// Returns a reference to a record by key.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::{Key, Record, Table};
+    ///
+    /// let mut table = Table::new();
+    /// table.insert("alice", Record::new());
+    /// assert!(table.get(&Key::from("alice")).is_some());
+    /// assert!(table.get(&Key::from(7)).is_none());
+    /// ```
     #[must_use]
-    pub fn get(&self, key: &str) -> Option<&Record> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&Record>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
         self.records.get(key)
     }
 
     /// Returns `true` if the table contains a record with the specified key.
     #[must_use]
-    pub fn contains_key(&self, key: &str) -> bool {
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
         self.records.contains_key(key)
     }
 
     /// Inserts a record into the table.
     ///
     /// If a record with the same key already exists, it is replaced.
-    pub fn insert(&mut self, key: String, record: Record) {
-        self.records.insert(key, record);
+    pub fn insert(&mut self, key: impl Into<Key>, record: Record) {
+        let key = key.into();
+        if self.observers.is_empty() {
+            self.records.insert(key, record);
+            return;
+        }
+        let old = self.records.insert(key.clone(), record.clone());
+        let kind = if old.is_some() {
+            ChangeKind::Update
+        } else {
+            ChangeKind::Insert
+        };
+        self.observers.notify(&ChangeEvent {
+            kind,
+            key,
+            old,
+            new: Some(record),
+        });
     }
 
     /// Removes a record from the table by key.
     ///
     /// Returns the removed record if it existed.
-    pub fn remove(&mut self, key: &str) -> Option<Record> {
-        self.records.remove(key)
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Record>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (key, old) = self.records.remove_entry(key)?;
+        if !self.observers.is_empty() {
+            self.observers.notify(&ChangeEvent {
+                kind: ChangeKind::Delete,
+                key,
+                old: Some(old.clone()),
+                new: None,
+            });
+        }
+        Some(old)
+    }
+
+    /// Registers a callback invoked with every change made to the table.
+    ///
+    /// The callback runs synchronously after each `insert`, `remove` or
+    /// applied [`Operations`](crate::Operations), once the table has been
+    /// updated.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use jsonlt::{ChangeKind, Record, Table};
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let inserts = Arc::new(AtomicUsize::new(0));
+    /// let counter = Arc::clone(&inserts);
+    ///
+    /// let mut table = Table::new();
+    /// table.subscribe(move |event| {
+    ///     if event.kind == ChangeKind::Insert {
+    ///         counter.fetch_add(1, Ordering::SeqCst);
+    ///     }
+    /// });
+    /// table.insert("key-1".to_string(), Record::new());
+    /// assert_eq!(inserts.load(Ordering::SeqCst), 1);
+    /// ```
+    pub fn subscribe<F>(&mut self, callback: F) -> SubscriptionId
+    where
+        F: Fn(&ChangeEvent) + Send + Sync + 'static,
+    {
+        self.observers.add_callback(Arc::new(callback))
+    }
+
+    /// Returns a channel receiving every change made to the table.
+    ///
+    /// The subscription is dropped automatically once the receiver is
+    /// dropped.
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    pub fn subscribe_channel(&mut self) -> (SubscriptionId, mpsc::Receiver<ChangeEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        (self.observers.add_channel(sender), receiver)
+    }
+
+    /// Removes a subscription.
+    ///
+    /// Returns `true` if the subscription was registered.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.observers.remove(id)
     }
 
-    /// Returns an iterator over the records in the table.
-    pub fn iter(&self) -> impl Iterator<Item = (&String, &Record)> {
+    /// Returns an iterator over the records in the table, in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &Record)> {
         self.records.iter()
     }
 
-    /// Returns an iterator over the keys in the table.
-    pub fn keys(&self) -> impl Iterator<Item = &String> {
+    /// Returns an iterator over the keys in the table, in order.
+    pub fn keys(&self) -> impl Iterator<Item = &Key> {
         self.records.keys()
     }
 }
@@ -0,0 +1,617 @@
+//! Checking JSONLT files against the specification.
+
+use crate::diff::push_segment;
+use crate::error::ResultExt;
+use crate::io::{Compression, Decoder};
+use crate::{to_canonical_string, Entry, Error, Header, KeySpecifier, Result, DELETED_FIELD};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// How serious a validation issue is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The file is valid but does not follow a recommendation, such as
+    /// canonical formatting.
+    Warning,
+
+    /// The file violates the specification and may be rejected by readers.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// A problem found while validating a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// The one-based line number.
+    pub line: u64,
+
+    /// The one-based byte column within the line.
+    ///
+    /// Issues concerning the whole line are reported at column 1.
+    pub column: u64,
+
+    /// How serious the issue is.
+    pub severity: Severity,
+
+    /// A description of the issue.
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.line, self.column, self.severity, self.message
+        )
+    }
+}
+
+/// The issues found in a JSONLT file, in line order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Every issue found.
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    /// Returns `true` if no errors were found; warnings are allowed.
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    /// Returns the issues with [`Severity::Error`].
+    pub fn errors(&self) -> impl Iterator<Item = &Issue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Error)
+    }
+
+    /// Returns the issues with [`Severity::Warning`].
+    pub fn warnings(&self) -> impl Iterator<Item = &Issue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Warning)
+    }
+}
+
+/// Options controlling validation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidateOptions {
+    /// The key specifier to use when the file has no header.
+    ///
+    /// If the file has a header declaring a key, it must match.
+    pub key: Option<KeySpecifier>,
+
+    /// Check records against the inline schema declared in the header.
+    pub check_schema: bool,
+
+    /// A schema to check records against instead of the header's.
+    pub schema: Option<Value>,
+}
+
+/// Validates a JSONLT file.
+///
+/// The file is read one line at a time and never loaded into a
+/// [`Table`](crate::Table). Files ending in `.gz` or `.zst` are decompressed
+/// as they are read; see [`Compression::from_path`]. See
+/// [`validate_reader`] for the checks made.
+///
+/// # Errors
+///
+/// Returns an error only if the file cannot be read; problems with its
+/// contents are reported as issues.
+pub fn validate<P: AsRef<Path>>(path: P) -> Result<ValidationReport> {
+    validate_with(path, &ValidateOptions::default())
+}
+
+/// Validates a JSONLT file with the given options.
+///
+/// See [`validate_reader`].
+///
+/// # Errors
+///
+/// Returns an error only if the file cannot be read.
+pub fn validate_with<P: AsRef<Path>>(
+    path: P,
+    options: &ValidateOptions,
+) -> Result<ValidationReport> {
+    let path = path.as_ref();
+    let decoder = File::open(path)
+        .map_err(Error::from)
+        .and_then(|file| Decoder::new(file, Compression::from_path(path)))
+        .context("open", path)?;
+    validate_reader(BufReader::new(decoder), options).context("read", path)
+}
+
+/// Validates JSONLT lines read from `reader`.
+///
+/// Every line is checked, and checking continues after errors. Errors are
+/// reported for:
+///
+/// - lines that are empty, not UTF-8, or not a JSON object
+/// - object members appearing more than once
+/// - a malformed header, or a header after the first line
+/// - records without a valid key, or using reserved `$` fields
+/// - tombstones that are not `true` or carry fields other than the key
+/// - records not matching the schema, when schema checking is enabled
+///
+/// Warnings are reported for lines not in canonical form, carriage
+/// returns before line feeds, and a missing newline at the end of the file.
+///
+/// Schema checking supports the `type`, `enum`, `const`, `properties`,
+/// `required`, `additionalProperties`, `items`, `minLength`, `maxLength`,
+/// `minItems`, `maxItems`, `minimum` and `maximum` keywords; other keywords
+/// are ignored.
+///
+/// # Errors
+///
+/// Returns an error only if the input cannot be read.
+///
+/// # Examples
+///
+/// ```rust
+/// use jsonlt::{validate_reader, Severity, ValidateOptions};
+///
+/// let data = "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n{\"id\":1, \"id\":2}\n";
+/// let report = validate_reader(data.as_bytes(), &ValidateOptions::default()).unwrap();
+///
+/// assert!(!report.is_valid());
+/// let issue = report.errors().next().unwrap();
+/// assert_eq!((issue.line, issue.column), (2, 10));
+/// assert_eq!(issue.message, "duplicate member \"id\"");
+/// ```
+pub fn validate_reader<R: BufRead>(
+    mut reader: R,
+    options: &ValidateOptions,
+) -> Result<ValidationReport> {
+    let mut validator = Validator {
+        key: options.key.clone(),
+        schema: options.schema.clone(),
+        check_schema: options.check_schema || options.schema.is_some(),
+        ..Validator::default()
+    };
+    let mut buffer = Vec::new();
+    let mut terminated = true;
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            break;
+        }
+        validator.line += 1;
+        terminated = buffer.last() == Some(&b'\n');
+        validator.check_line(&buffer);
+    }
+    if !terminated {
+        validator.warning(1, "missing newline at end of file");
+    }
+    Ok(validator.report)
+}
+
+#[derive(Default)]
+struct Validator {
+    key: Option<KeySpecifier>,
+    schema: Option<Value>,
+    check_schema: bool,
+    missing_key_reported: bool,
+    line: u64,
+    report: ValidationReport,
+}
+
+impl Validator {
+    fn push(&mut self, column: u64, severity: Severity, message: impl Into<String>) {
+        self.report.issues.push(Issue {
+            line: self.line,
+            column,
+            severity,
+            message: message.into(),
+        });
+    }
+
+    fn error(&mut self, column: u64, message: impl Into<String>) {
+        self.push(column, Severity::Error, message);
+    }
+
+    fn warning(&mut self, column: u64, message: impl Into<String>) {
+        self.push(column, Severity::Warning, message);
+    }
+
+    fn check_line(&mut self, bytes: &[u8]) {
+        let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+        if bytes.ends_with(b"\r") {
+            self.warning(column(bytes.len() - 1), "line ends with a carriage return");
+        }
+        let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(err) => {
+                self.error(column(err.valid_up_to()), "invalid UTF-8");
+                return;
+            }
+        };
+        let text = if self.line == 1 {
+            text.strip_prefix('\u{feff}').map_or(text, |rest| {
+                self.warning(1, "byte order mark");
+                rest
+            })
+        } else {
+            text
+        };
+        let offset = bytes.len() - text.len();
+        if text.trim().is_empty() {
+            self.error(1, "empty line");
+            return;
+        }
+
+        let value: Value = match serde_json::from_str(text) {
+            Ok(value) => value,
+            Err(err) => {
+                let message = err.to_string();
+                let message = message
+                    .rfind(" at line ")
+                    .map_or(message.as_str(), |end| &message[..end]);
+                self.error(column(offset + err.column().saturating_sub(1)), message);
+                return;
+            }
+        };
+        let members = scan_members(text);
+        for (position, name) in &members.duplicates {
+            self.error(
+                column(offset + position),
+                format!("duplicate member {name:?}"),
+            );
+        }
+        let member_column = |name: &str| {
+            members
+                .top_level
+                .get(name)
+                .map_or(1, |p| column(offset + p))
+        };
+
+        let canonical = to_canonical_string(&value);
+        let Value::Object(object) = value else {
+            self.error(1, "line must be a JSON object");
+            return;
+        };
+
+        if Header::is_header(&object) {
+            self.check_header(&object);
+        } else if let Some(key) = self.key.clone() {
+            let deleted = object.contains_key(DELETED_FIELD);
+            match Entry::from_object(object.clone(), &key) {
+                Ok(_) if deleted || !self.check_schema => {}
+                Ok(_) => self.check_record(&object, &member_column),
+                Err(err) => {
+                    let at = culprit(&object, &key).map_or(1, member_column);
+                    self.error(at, message(err));
+                }
+            }
+        } else if !self.missing_key_reported {
+            self.missing_key_reported = true;
+            self.error(1, "no key specifier: the file has no header declaring one");
+        }
+
+        if let Some(at) = canonical
+            .bytes()
+            .zip(text.bytes())
+            .position(|(a, b)| a != b)
+            .or_else(|| (canonical.len() != text.len()).then(|| canonical.len().min(text.len())))
+        {
+            self.warning(column(offset + at), "line is not in canonical form");
+        }
+    }
+
+    fn check_header(&mut self, object: &Map<String, Value>) {
+        if self.line != 1 {
+            self.error(1, "header must be the first line");
+            return;
+        }
+        let header = match Header::from_object(object) {
+            Ok(header) => header,
+            Err(err) => {
+                self.error(1, message(err));
+                return;
+            }
+        };
+        match (&self.key, &header.key) {
+            (Some(expected), Some(found)) if expected != found => {
+                let message = format!("header key {found} does not match expected key {expected}");
+                self.error(1, message);
+            }
+            (None, Some(found)) => self.key = Some(found.clone()),
+            _ => {}
+        }
+        if self.check_schema && self.schema.is_none() {
+            self.schema.clone_from(&header.schema);
+            if let (None, Some(url)) = (&header.schema, &header.schema_url) {
+                self.warning(
+                    1,
+                    format!("schema {url} is not fetched and was not checked"),
+                );
+            }
+        }
+    }
+
+    fn check_record(&mut self, object: &Map<String, Value>, member_column: &dyn Fn(&str) -> u64) {
+        let Some(schema) = &self.schema else {
+            return;
+        };
+        let record = Value::Object(object.clone());
+        let mut violations = Vec::new();
+        check_schema(schema, &record, &mut String::new(), &mut violations);
+        for (pointer, message) in violations {
+            let field = pointer.split('/').nth(1).unwrap_or_default();
+            let field = field.replace("~1", "/").replace("~0", "~");
+            let at = member_column(&field);
+            if pointer.is_empty() {
+                self.error(at, message);
+            } else {
+                self.error(at, format!("{pointer}: {message}"));
+            }
+        }
+    }
+}
+
+/// Converts a zero-based byte offset into a one-based column.
+fn column(offset: usize) -> u64 {
+    u64::try_from(offset).map_or(u64::MAX, |offset| offset + 1)
+}
+
+/// Returns the member most likely responsible for an invalid entry: a
+/// misused `$` field, then any field a tombstone must not carry, then the
+/// first key field present.
+fn culprit<'a>(object: &'a Map<String, Value>, key: &'a KeySpecifier) -> Option<&'a str> {
+    let deleted = object.get(DELETED_FIELD);
+    let reserved = object
+        .keys()
+        .find(|field| field.starts_with('$') && (deleted.is_none() || *field != DELETED_FIELD));
+    let misplaced = || {
+        object
+            .keys()
+            .find(|field| *field != DELETED_FIELD && !key.contains(field))
+    };
+    if deleted.is_some_and(|deleted| deleted != &Value::Bool(true)) {
+        return Some(DELETED_FIELD);
+    }
+    reserved
+        .or_else(|| deleted.and_then(|_| misplaced()))
+        .or_else(|| {
+            key.fields()
+                .iter()
+                .find(|field| object.contains_key(*field))
+        })
+        .map(String::as_str)
+}
+
+/// Returns the message of an error without its kind.
+fn message(err: Error) -> String {
+    match err {
+        Error::InvalidFormat { message, .. } | Error::InvalidKey { message, .. } => message,
+        other => other.to_string(),
+    }
+}
+
+#[derive(Default)]
+struct Members {
+    /// Byte offsets of the names of top-level members.
+    top_level: HashMap<String, usize>,
+
+    /// Byte offsets and names of members repeated within an object.
+    duplicates: Vec<(usize, String)>,
+}
+
+/// Finds the object members of a line already known to be valid JSON.
+fn scan_members(text: &str) -> Members {
+    struct Frame {
+        names: Option<HashSet<String>>,
+        expect_name: bool,
+    }
+
+    let mut members = Members::default();
+    let mut stack: Vec<Frame> = Vec::new();
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' => stack.push(Frame {
+                names: Some(HashSet::new()),
+                expect_name: true,
+            }),
+            b'[' => stack.push(Frame {
+                names: None,
+                expect_name: false,
+            }),
+            b'}' | b']' => {
+                stack.pop();
+            }
+            b',' => {
+                if let Some(frame) = stack.last_mut() {
+                    frame.expect_name = frame.names.is_some();
+                }
+            }
+            b'"' => {
+                let start = i;
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                let depth = stack.len();
+                if let Some(frame) = stack.last_mut().filter(|frame| frame.expect_name) {
+                    frame.expect_name = false;
+                    let name: String = serde_json::from_str(&text[start..=i]).unwrap_or_default();
+                    if let Some(names) = &mut frame.names {
+                        if !names.insert(name.clone()) {
+                            members.duplicates.push((start, name));
+                        } else if depth == 1 {
+                            members.top_level.insert(name, start);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    members
+}
+
+/// Checks `value` against the supported subset of JSON Schema, collecting
+/// the pointers and descriptions of violations.
+fn check_schema(
+    schema: &Value,
+    value: &Value,
+    pointer: &mut String,
+    violations: &mut Vec<(String, String)>,
+) {
+    let Value::Object(schema) = schema else {
+        if schema == &Value::Bool(false) {
+            violations.push((pointer.clone(), "no value is allowed here".into()));
+        }
+        return;
+    };
+    let mut fail = |message: String| violations.push((pointer.clone(), message));
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+            fail(format!("expected type {}", types.join(" or ")));
+        }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            fail("value is not one of the allowed values".into());
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            fail(format!("expected {}", to_canonical_string(expected)));
+        }
+    }
+    check_bounds(schema, value, &mut fail);
+
+    match value {
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    let len = pointer.len();
+                    push_segment(pointer, &i.to_string());
+                    check_schema(item_schema, item, pointer, violations);
+                    pointer.truncate(len);
+                }
+            }
+        }
+        Value::Object(object) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for field in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(field) {
+                        fail(format!("missing required field {field:?}"));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let additional = schema.get("additionalProperties");
+            for (field, item) in object {
+                let item_schema = properties
+                    .and_then(|properties| properties.get(field))
+                    .or(additional);
+                if let Some(item_schema) = item_schema {
+                    let len = pointer.len();
+                    push_segment(pointer, field);
+                    check_schema(item_schema, item, pointer, violations);
+                    pointer.truncate(len);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Checks the length and range keywords.
+fn check_bounds(schema: &Map<String, Value>, value: &Value, fail: &mut impl FnMut(String)) {
+    match value {
+        Value::String(s) => {
+            let len = s.chars().count() as u64;
+            if schema
+                .get("minLength")
+                .and_then(Value::as_u64)
+                .is_some_and(|min| len < min)
+            {
+                fail(format!("string is shorter than {}", schema["minLength"]));
+            }
+            if schema
+                .get("maxLength")
+                .and_then(Value::as_u64)
+                .is_some_and(|max| len > max)
+            {
+                fail(format!("string is longer than {}", schema["maxLength"]));
+            }
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or(f64::NAN);
+            if schema
+                .get("minimum")
+                .and_then(Value::as_f64)
+                .is_some_and(|min| n < min)
+            {
+                fail(format!("number is less than {}", schema["minimum"]));
+            }
+            if schema
+                .get("maximum")
+                .and_then(Value::as_f64)
+                .is_some_and(|max| n > max)
+            {
+                fail(format!("number is greater than {}", schema["maximum"]));
+            }
+        }
+        Value::Array(items) => {
+            let len = items.len() as u64;
+            if schema
+                .get("minItems")
+                .and_then(Value::as_u64)
+                .is_some_and(|min| len < min)
+            {
+                fail(format!("array has fewer than {} items", schema["minItems"]));
+            }
+            if schema
+                .get("maxItems")
+                .and_then(Value::as_u64)
+                .is_some_and(|max| len > max)
+            {
+                fail(format!("array has more than {} items", schema["maxItems"]));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
@@ -39,7 +39,7 @@ impl JsTable {
     #[wasm_bindgen(js_name = hasKey)]
     #[must_use]
     pub fn has_key(&self, key: &str) -> bool {
-        self.inner.contains_key(key)
+        self.inner.contains_key(&crate::Key::from(key))
     }
 
     /// Gets a record by key as a JSON string.
@@ -50,7 +50,7 @@ impl JsTable {
     #[must_use]
     pub fn get(&self, key: &str) -> Option<String> {
         self.inner
-            .get(key)
+            .get(&crate::Key::from(key))
             .map(|record| serde_json::to_string(record.value()).unwrap_or_default())
     }
 
@@ -58,14 +58,15 @@ impl JsTable {
     ///
     /// # Errors
     ///
-    /// Returns an error if the JSON is invalid.
+    /// Returns an error if the JSON is invalid or is not a valid record.
     #[wasm_bindgen]
     #[cfg(feature = "serde")]
     pub fn insert(&mut self, key: &str, json: &str) -> Result<(), JsValue> {
         let value: serde_json::Value =
             serde_json::from_str(json).map_err(|e| JsValue::from_str(&e.to_string()))?;
-        self.inner
-            .insert(key.to_string(), crate::Record::from_value(value));
+        let record =
+            crate::Record::try_from_value(value).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.inner.insert(key, record);
         Ok(())
     }
 
@@ -74,7 +75,7 @@ impl JsTable {
     /// Returns `true` if the record was removed, `false` if it didn't exist.
     #[wasm_bindgen]
     pub fn remove(&mut self, key: &str) -> bool {
-        self.inner.remove(key).is_some()
+        self.inner.remove(&crate::Key::from(key)).is_some()
     }
 
     /// Returns all keys as a JSON array string.
@@ -82,7 +83,7 @@ impl JsTable {
     #[cfg(feature = "serde")]
     #[must_use]
     pub fn keys(&self) -> String {
-        let keys: Vec<&String> = self.inner.keys().collect();
+        let keys: Vec<&crate::Key> = self.inner.keys().collect();
         serde_json::to_string(&keys).unwrap_or_else(|_| "[]".to_string())
     }
 }
@@ -18,17 +18,17 @@ pub fn create_sample_table() -> Table {
     let mut table = Table::new();
     table.insert(
         "user-1".to_string(),
-        Record::from_value(json!({
+        Record::try_from_value(json!({
             "name": "Alice",
             "email": "alice@example.com"
-        })),
+        })).unwrap(),
     );
     table.insert(
         "user-2".to_string(),
-        Record::from_value(json!({
+        Record::try_from_value(json!({
             "name": "Bob",
             "email": "bob@example.com"
-        })),
+        })).unwrap(),
     );
     table
 }
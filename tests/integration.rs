@@ -1,6 +1,13 @@
 //! Integration tests for JSONLT.
 
-use jsonlt::{Operations, Record, Table};
+use jsonlt::{ChangeKind, Key, Operations, Record, Table};
+use std::sync::{Arc, Mutex};
+
+/// Builds a record from a JSON value, which must be an object.
+#[cfg(feature = "serde")]
+fn record(value: serde_json::Value) -> Record {
+    Record::try_from_value(value).unwrap()
+}
 
 #[test]
 fn test_table_basic_operations() {
@@ -12,11 +19,11 @@ fn test_table_basic_operations() {
     table.insert("key-1".to_string(), Record::new());
     assert!(!table.is_empty());
     assert_eq!(table.len(), 1);
-    assert!(table.contains_key("key-1"));
-    assert!(!table.contains_key("key-2"));
+    assert!(table.contains_key(&Key::from("key-1")));
+    assert!(!table.contains_key(&Key::from("key-2")));
 
     // Remove a record
-    let removed = table.remove("key-1");
+    let removed = table.remove(&Key::from("key-1"));
     assert!(removed.is_some());
     assert!(table.is_empty());
 }
@@ -26,12 +33,12 @@ fn test_operations_insert() {
     let mut table = Table::new();
 
     let op = Operations::Insert {
-        key: "test-key".to_string(),
+        key: "test-key".into(),
         record: Record::new(),
     };
 
     assert!(op.apply(&mut table).is_ok());
-    assert!(table.contains_key("test-key"));
+    assert!(table.contains_key(&Key::from("test-key")));
 }
 
 #[test]
@@ -40,7 +47,7 @@ fn test_operations_insert_duplicate() {
     table.insert("test-key".to_string(), Record::new());
 
     let op = Operations::Insert {
-        key: "test-key".to_string(),
+        key: "test-key".into(),
         record: Record::new(),
     };
 
@@ -53,7 +60,7 @@ fn test_operations_update() {
     table.insert("test-key".to_string(), Record::new());
 
     let op = Operations::Update {
-        key: "test-key".to_string(),
+        key: "test-key".into(),
         record: Record::new(),
     };
 
@@ -65,7 +72,7 @@ fn test_operations_update_nonexistent() {
     let mut table = Table::new();
 
     let op = Operations::Update {
-        key: "test-key".to_string(),
+        key: "test-key".into(),
         record: Record::new(),
     };
 
@@ -78,11 +85,11 @@ fn test_operations_delete() {
     table.insert("test-key".to_string(), Record::new());
 
     let op = Operations::Delete {
-        key: "test-key".to_string(),
+        key: "test-key".into(),
     };
 
     assert!(op.apply(&mut table).is_ok());
-    assert!(!table.contains_key("test-key"));
+    assert!(!table.contains_key(&Key::from("test-key")));
 }
 
 #[test]
@@ -90,7 +97,7 @@ fn test_operations_delete_nonexistent() {
     let mut table = Table::new();
 
     let op = Operations::Delete {
-        key: "test-key".to_string(),
+        key: "test-key".into(),
     };
 
     assert!(op.apply(&mut table).is_err());
@@ -101,12 +108,12 @@ fn test_operations_upsert_insert() {
     let mut table = Table::new();
 
     let op = Operations::Upsert {
-        key: "test-key".to_string(),
+        key: "test-key".into(),
         record: Record::new(),
     };
 
     assert!(op.apply(&mut table).is_ok());
-    assert!(table.contains_key("test-key"));
+    assert!(table.contains_key(&Key::from("test-key")));
 }
 
 #[test]
@@ -115,17 +122,537 @@ fn test_operations_upsert_update() {
     table.insert("test-key".to_string(), Record::new());
 
     let op = Operations::Upsert {
-        key: "test-key".to_string(),
+        key: "test-key".into(),
         record: Record::new(),
     };
 
     assert!(op.apply(&mut table).is_ok());
-    assert!(table.contains_key("test-key"));
+    assert!(table.contains_key(&Key::from("test-key")));
+}
+
+#[test]
+fn test_subscribe_receives_changes() {
+    let mut table = Table::new();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&seen);
+    table.subscribe(move |event| {
+        sink.lock().unwrap().push((
+            event.kind,
+            event.key.clone(),
+            event.old.is_some(),
+            event.new.is_some(),
+        ));
+    });
+
+    table.insert("key-1".to_string(), Record::new());
+    table.insert("key-1".to_string(), Record::new());
+    table.remove(&Key::from("key-1"));
+    table.remove(&Key::from("key-1"));
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            (ChangeKind::Insert, "key-1".into(), false, true),
+            (ChangeKind::Update, "key-1".into(), true, true),
+            (ChangeKind::Delete, "key-1".into(), true, false),
+        ]
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_subscribe_channel_receives_operations() {
+    let mut table = Table::new();
+    let (_, events) = table.subscribe_channel();
+
+    let ops = [
+        Operations::Upsert {
+            key: "test-key".into(),
+            record: Record::new(),
+        },
+        Operations::Update {
+            key: "test-key".into(),
+            record: Record::new(),
+        },
+        Operations::Delete {
+            key: "test-key".into(),
+        },
+    ];
+    for op in ops {
+        op.apply(&mut table).unwrap();
+    }
+
+    let kinds: Vec<_> = events.try_iter().map(|event| event.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![ChangeKind::Insert, ChangeKind::Update, ChangeKind::Delete]
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_unsubscribe_stops_notifications() {
+    let mut table = Table::new();
+    let (id, events) = table.subscribe_channel();
+    assert!(table.unsubscribe(id));
+    assert!(!table.unsubscribe(id));
+
+    table.insert("key-1".to_string(), Record::new());
+    assert!(events.try_recv().is_err());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_clone_does_not_copy_subscribers() {
+    let mut table = Table::new();
+    let (_, events) = table.subscribe_channel();
+
+    let mut copy = table.clone();
+    copy.insert("key-1".to_string(), Record::new());
+    assert!(events.try_recv().is_err());
+}
+
+mod json_tests {
+    #[cfg(feature = "std")]
+    use jsonlt::io::{FileTable, StreamReader, StreamWriter};
+    use jsonlt::json::{self, Map, Value};
+    use jsonlt::{
+        to_canonical_string, Entry, Header, Key, KeySpecifier, LineParser, Record, Table,
+    };
+
+    fn object(text: &str) -> Record {
+        Record::try_from_value(json::from_str(text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_json_parse_and_serialize() {
+        let value = json::from_str(concat!(
+            " {\"s\":\"a\\\"\\\\\\/\\b\\f\\n\\r\\t\\u00e9\\ud83d\\ude00\",",
+            "\"n\":[0,-7,18446744073709551615,-9223372036854775808,2.5,-1e3],",
+            "\"z\":{},\"a\":[],\"t\":true,\"f\":false,\"x\":null,\"d\":1,\"d\":2}\r\n",
+        ))
+        .unwrap();
+        assert_eq!(value["s"].as_str(), Some("a\"\\/\u{8}\u{c}\n\r\té😀"));
+        assert_eq!(value["n"][1].as_i64(), Some(-7));
+        assert_eq!(value["n"][2].as_u64(), Some(u64::MAX));
+        assert_eq!(value["n"][3].as_i64(), Some(i64::MIN));
+        assert_eq!(value["n"][4].as_f64(), Some(2.5));
+        assert_eq!(value["n"][5].as_f64(), Some(-1000.0));
+        assert_eq!(value["d"], Value::from(2));
+        assert_eq!(value.pointer("/n/0"), Some(&Value::from(0)));
+        assert!(value["missing"].is_null());
+        assert_eq!(
+            to_canonical_string(&value),
+            concat!(
+                "{\"a\":[],\"d\":2,\"f\":false,",
+                "\"n\":[0,-7,18446744073709551615,-9223372036854775808,2.5,-1000.0],",
+                "\"s\":\"a\\\"\\\\/\\b\\f\\n\\r\\té😀\",\"t\":true,\"x\":null,\"z\":{}}",
+            )
+        );
+
+        let nested = "[".repeat(200) + &"]".repeat(200);
+        for invalid in [
+            "",
+            "{",
+            "{\"a\"}",
+            "{\"a\":1,}",
+            "[1,]",
+            "01",
+            "1.",
+            "-",
+            "1e",
+            "1e400",
+            "tru",
+            "\"\\x\"",
+            "\"\\ud800\"",
+            "\"a\nb\"",
+            "{} {}",
+            "{\"a\" 1}",
+            &nested,
+        ] {
+            assert!(json::from_str(invalid).is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn test_key_extraction_and_headers() {
+        let key = KeySpecifier::from(["org", "id"].as_slice());
+        let record = object("{\"org\":\"acme\",\"id\":7,\"name\":\"x\"}");
+        let extracted = key.extract(record.value().as_object().unwrap()).unwrap();
+        assert_eq!(extracted, Key::from(vec![Key::from("acme"), Key::from(7)]));
+        assert_eq!(
+            Value::Object(key.key_fields(&extracted).unwrap()),
+            json::from_str("{\"id\":7,\"org\":\"acme\"}").unwrap()
+        );
+        assert!(key
+            .extract(
+                object("{\"org\":\"acme\",\"id\":1.5}")
+                    .value()
+                    .as_object()
+                    .unwrap()
+            )
+            .is_err());
+        assert_eq!(KeySpecifier::from_value(&key.to_value()).unwrap(), key);
+
+        let header = Header::new(key);
+        assert_eq!(
+            to_canonical_string(&header.to_value()),
+            "{\"$jsonlt\":{\"key\":[\"org\",\"id\"],\"version\":1}}"
+        );
+        let mut fields = Map::new();
+        fields.insert(
+            "$jsonlt".to_string(),
+            json::from_str("{\"version\":1,\"key\":5}").unwrap(),
+        );
+        assert!(Header::from_object(&fields).is_err());
+    }
+
+    #[test]
+    fn test_line_parser_builds_table() {
+        let text = concat!(
+            "{\"$jsonlt\":{\"key\":\"name\",\"version\":1}}\n",
+            "{\"name\":\"baud\",\"value\":9600}\n",
+            "{\"name\":\"led\",\"value\":true}\n",
+            "{\"name\":\"baud\",\"value\":115200}\n",
+            "{\"$deleted\":true,\"name\":\"led\"}\n",
+        );
+        let mut parser = LineParser::new(None);
+        let mut table = Table::new();
+        for line in text.lines() {
+            match parser.parse(line).unwrap() {
+                Some(Entry::Record { key, record }) => table.insert(key, record),
+                Some(Entry::Tombstone { key }) => {
+                    table.remove(&key);
+                }
+                None => {}
+            }
+        }
+        assert_eq!(parser.key_specifier(), Some(&KeySpecifier::from("name")));
+        assert_eq!(table.len(), 1);
+        assert_eq!(
+            table.get(&Key::from("baud")).unwrap().value()["value"].as_u64(),
+            Some(115_200)
+        );
+
+        let err = parser.parse("{\"value\":1}").unwrap_err();
+        assert!(err.to_string().starts_with("invalid key: line 6:"), "{err}");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_stream_and_file_round_trip() {
+        let mut writer = StreamWriter::new(Vec::new(), "id");
+        writer.write_header(&Header::new("id".into())).unwrap();
+        writer
+            .write_record(&object("{\"id\":\"a\",\"v\":[1,{\"w\":null}]}"))
+            .unwrap();
+        writer.write_record(&object("{\"id\":\"b\"}")).unwrap();
+        writer.write_tombstone(&Key::from("a")).unwrap();
+        let data = writer.into_inner().unwrap();
+
+        let entries: Vec<_> = StreamReader::new(data.as_slice())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].line, 2);
+        assert_eq!(
+            entries[0].entry.record().unwrap().value()["v"][1],
+            json::from_str("{\"w\":null}").unwrap()
+        );
+        assert!(matches!(&entries[2].entry, Entry::Tombstone { key } if *key == Key::from("a")));
+
+        let err = StreamReader::with_key(&b"{\"id\":1}\n{\"id\":\n"[..], "id")
+            .nth(1)
+            .unwrap()
+            .unwrap_err();
+        assert!(err.to_string().contains("line 2:"), "{err}");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("table.jsonlt");
+        std::fs::write(&path, &data).unwrap();
+        let mut table = FileTable::open(&path).unwrap();
+        assert_eq!(table.table().len(), 1);
+        table.put(&object("{\"id\":\"c\",\"n\":3}")).unwrap();
+        let reopened = FileTable::open(&path).unwrap();
+        assert_eq!(
+            reopened.table().get(&Key::from("c")).unwrap().value()["n"].as_i64(),
+            Some(3)
+        );
+        assert!(reopened.table().get(&Key::from("a")).is_none());
+    }
+}
+
+mod record_tests {
+    use jsonlt::json::{Map, Value};
+    use jsonlt::{Key, KeySpecifier, LineParser, Record, MAX_RECORD_DEPTH, MAX_RECORD_SIZE};
+
+    #[test]
+    fn test_record_get_fields_and_pointers() {
+        let record = Record::try_from(r#"{"id":"a","tags":["x","y"],"a/b":{"c~d":1}}"#).unwrap();
+        assert_eq!(record.get("id").and_then(Value::as_str), Some("a"));
+        assert_eq!(record.get("/tags/1").and_then(Value::as_str), Some("y"));
+        assert_eq!(record.get("/a~1b/c~0d").and_then(Value::as_i64), Some(1));
+        assert!(record.get("a/b").is_some());
+        assert!(record.get("/tags/01").is_none());
+        assert!(record.get("/tags/2").is_none());
+        assert!(record.get("/id/x").is_none());
+        assert_eq!(record.get(""), Some(record.value()));
+
+        let names: Vec<&str> = record.fields().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["a/b", "id", "tags"]);
+        assert_eq!(Record::new().fields().count(), 0);
+    }
+
+    #[test]
+    fn test_record_set_and_remove() {
+        let mut record = Record::new();
+        assert_eq!(record.set("id", "a").unwrap(), None);
+        assert_eq!(record.set("/meta/owner", "bob").unwrap(), None);
+        assert_eq!(
+            record.set("/meta/owner", "eve").unwrap(),
+            Some(Value::from("bob"))
+        );
+        record.set("tags", Value::Array(Vec::new())).unwrap();
+        record.set("/tags/-", "x").unwrap();
+        record.set("/tags/1", "y").unwrap();
+        assert_eq!(record.set("/tags/0", "z").unwrap(), Some(Value::from("x")));
+        assert_eq!(
+            record.value().to_string(),
+            r#"{"id":"a","meta":{"owner":"eve"},"tags":["z","y"]}"#
+        );
+
+        assert!(record.set("/tags/5", 1).is_err());
+        assert!(record.set("/id/x", 1).is_err());
+        assert!(record.set("/tags/9/x", 1).is_err());
+        assert!(record.set("", 1).is_err());
+
+        assert_eq!(record.remove("/tags/0"), Some(Value::from("z")));
+        assert_eq!(record.remove("/meta/owner"), Some(Value::from("eve")));
+        assert_eq!(record.remove("missing"), None);
+        assert_eq!(record.remove(""), None);
+        assert_eq!(
+            record.value().to_string(),
+            r#"{"id":"a","meta":{},"tags":["y"]}"#
+        );
+    }
+
+    #[test]
+    fn test_record_rejects_invalid_values() {
+        assert_eq!(Record::new().value(), &Value::Object(Map::new()));
+        for value in [
+            Value::Null,
+            Value::from("a"),
+            Value::from(vec![Value::from(1)]),
+        ] {
+            assert!(Record::try_from_value(value).is_err());
+        }
+        assert!(Record::try_from("{\"$meta\":1}").is_err());
+        assert!(Record::try_from("{\"id\":1,\"$deleted\":false}").is_err());
+        assert!(Record::try_from("{\"id\":1,\"$deleted\":true}").is_err());
+
+        let mut nested = Value::from(1);
+        for _ in 0..MAX_RECORD_DEPTH - 1 {
+            nested = Value::from(vec![nested]);
+        }
+        let mut object = Map::new();
+        object.insert("a".to_string(), nested.clone());
+        assert!(Record::try_from(object.clone()).is_ok());
+        object.insert("a".to_string(), Value::from(vec![nested]));
+        assert!(Record::try_from(object).is_err());
+
+        let mut object = Map::new();
+        object.insert(
+            "a".to_string(),
+            Value::from("x".repeat(MAX_RECORD_SIZE - 8)),
+        );
+        assert!(Record::try_from(object.clone()).is_ok());
+        object.insert(
+            "a".to_string(),
+            Value::from("x".repeat(MAX_RECORD_SIZE - 7)),
+        );
+        assert!(Record::try_from(object).is_err());
+
+        // Records read from a file are held to the same limits.
+        let mut parser = LineParser::new(Some(KeySpecifier::from("id")));
+        let deep = format!(
+            "{{\"id\":1,\"a\":{}1{}}}",
+            "[".repeat(MAX_RECORD_DEPTH),
+            "]".repeat(MAX_RECORD_DEPTH)
+        );
+        let err = parser.parse(&deep).unwrap_err();
+        assert!(err.to_string().contains("deeper than"), "{err}");
+
+        let mut record = Record::from_value_unchecked(Value::Null);
+        assert!(record.validate().is_err());
+        *record.value_mut() = Value::Object(Map::new());
+        assert!(record.validate().is_ok());
+        assert!(record.set("$meta", 1).is_err());
+        assert!(record.set("", Value::Null).is_err());
+        assert_eq!(record.value(), &Value::Object(Map::new()));
+    }
+
+    #[test]
+    fn test_record_key_tombstone_and_conversions() {
+        let record = Record::try_from(r#"{"org":"acme","id":7}"#).unwrap();
+        assert_eq!(
+            record.key(&KeySpecifier::from("org")).unwrap(),
+            Key::from("acme")
+        );
+        assert!(record.key(&KeySpecifier::from("name")).is_err());
+        assert!(Record::from_value_unchecked(Value::Null)
+            .key(&KeySpecifier::from("id"))
+            .is_err());
+
+        assert!(!record.is_tombstone());
+        let tombstone = jsonlt::json::from_str(r#"{"id":7,"$deleted":true}"#).unwrap();
+        assert!(Record::from_value_unchecked(tombstone).is_tombstone());
+
+        assert!(Record::try_from("[1]").is_err());
+        assert!(Record::try_from("{").is_err());
+
+        let mut object = Map::new();
+        object.insert("id".to_string(), Value::from(7));
+        let record = Record::try_from(object).unwrap();
+        let value: Value = record.clone().into();
+        assert_eq!(value, *record.value());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_record_typed_access() {
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct User {
+            id: u32,
+            name: String,
+        }
+
+        let user = User {
+            id: 1,
+            name: "alice".into(),
+        };
+        let record = Record::from_serialize(&user).unwrap();
+        assert_eq!(record.get_as::<u32>("id").unwrap(), Some(1));
+        assert_eq!(record.get_as::<&str>("/name").unwrap(), Some("alice"));
+        assert_eq!(record.get_as::<u32>("missing").unwrap(), None);
+        assert!(record.get_as::<u32>("name").is_err());
+        assert_eq!(record.deserialize_as::<User>().unwrap(), user);
+
+        assert!(Record::from_serialize(&[1, 2]).is_err());
+    }
+}
+
+mod error_tests {
+    use jsonlt::{Error, ErrorKind, Key, KeySpecifier, LineParser, Operations, Record, Table};
+
+    #[test]
+    fn test_error_kinds_and_fields() {
+        let mut table = Table::new();
+        Operations::Insert {
+            key: Key::from(1),
+            record: Record::new(),
+        }
+        .apply(&mut table)
+        .unwrap();
+        let err = Operations::Insert {
+            key: Key::from(1),
+            record: Record::new(),
+        }
+        .apply(&mut table)
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert!(matches!(
+            err,
+            Error::AlreadyExists {
+                key: Key::Integer(1)
+            }
+        ));
+        assert_eq!(err.to_string(), "record already exists: 1");
+
+        let err = Operations::Delete { key: "a".into() }
+            .apply(&mut table)
+            .unwrap_err();
+        assert!(matches!(&err, Error::NotFound { key } if *key == Key::from("a")));
+
+        let mut parser = LineParser::new(Some(KeySpecifier::from("id")));
+        let err = parser
+            .parse("{\"$jsonlt\":{\"key\":\"name\",\"version\":1}}")
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::KeyMismatch);
+        assert!(matches!(
+            &err,
+            Error::KeyMismatch { expected, found }
+                if *expected == KeySpecifier::from("id") && *found == KeySpecifier::from("name")
+        ));
+
+        let mut parser = LineParser::new(Some(KeySpecifier::from("id")));
+        parser.parse("{\"id\":1}").unwrap();
+        let err = parser.parse("{\"name\":\"a\"}").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidKey);
+        assert_eq!(err.line(), Some(2));
+        assert_eq!(
+            err.to_string(),
+            "invalid key: line 2: missing key field \"id\""
+        );
+        let err = parser.parse("{").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidFormat);
+        assert_eq!(err.line(), Some(3));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_errors_name_file_line_and_offset() {
+        use jsonlt::io::{FileTable, StreamReader};
+        use std::error::Error as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.jsonlt");
+        let err = FileTable::open(&path).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(err.path(), Some(path.as_path()));
+        assert!(matches!(
+            &err,
+            Error::Context {
+                operation: "read",
+                ..
+            }
+        ));
+        assert!(err.to_string().starts_with("failed to read "), "{err}");
+        assert!(
+            matches!(err.source(), Some(source) if source.to_string().starts_with("I/O error"))
+        );
+
+        let path = dir.path().join("bad.jsonlt");
+        std::fs::write(&path, "{\"id\":1}\n{\"id\":2}\n[]\n").unwrap();
+        let err = FileTable::open_with_key(&path, "id").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidFormat);
+        assert_eq!(err.line(), Some(3));
+        assert_eq!(err.path(), Some(path.as_path()));
+        assert!(
+            err.full_message()
+                .ends_with("line 3: line must be a JSON object (at byte 18)"),
+            "{err}"
+        );
+
+        let err = StreamReader::with_key(&b"{\"id\":1}\n{\"id\":true}\n"[..], "id")
+            .nth(1)
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidKey {
+                line: Some(2),
+                offset: Some(9),
+                ..
+            }
+        ));
+    }
 }
 
 #[cfg(feature = "serde")]
 mod serde_tests {
-    use jsonlt::{Record, Table};
+    use jsonlt::{Key, Record, Table};
     use serde_json::json;
 
     #[test]
@@ -135,7 +662,7 @@ mod serde_tests {
             "count": 42
         });
 
-        let record = Record::from_value(value.clone());
+        let record = Record::try_from_value(value.clone()).unwrap();
         assert_eq!(record.value(), &value);
     }
 
@@ -145,17 +672,2688 @@ mod serde_tests {
 
         table.insert(
             "item-1".to_string(),
-            Record::from_value(json!({"name": "First"})),
+            Record::try_from_value(json!({"name": "First"})).unwrap(),
         );
         table.insert(
             "item-2".to_string(),
-            Record::from_value(json!({"name": "Second"})),
+            Record::try_from_value(json!({"name": "Second"})).unwrap(),
         );
 
         assert_eq!(table.len(), 2);
         assert_eq!(
-            table.get("item-1").unwrap().value(),
+            table.get(&Key::from("item-1")).unwrap().value(),
             &json!({"name": "First"})
         );
     }
+
+    #[test]
+    fn test_record_serializes_as_its_object() {
+        let record: Record = serde_json::from_str(r#"{"id":1,"tags":["a"]}"#).unwrap();
+        assert_eq!(record.value(), &json!({"id": 1, "tags": ["a"]}));
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"id":1,"tags":["a"]}"#
+        );
+
+        for text in ["[1,2]", r#""id""#, r#"{"$meta":1}"#] {
+            let err = serde_json::from_str::<Record>(text).unwrap_err();
+            assert!(err.to_string().contains("invalid format"), "{text}: {err}");
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod file_table_tests {
+    use jsonlt::io::{FileTable, PollOutcome};
+    use jsonlt::{ChangeKind, Key, Record};
+    use serde_json::json;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    fn append(path: &std::path::Path, text: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_open_applies_header_and_tombstones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.jsonlt");
+        fs::write(
+            &path,
+            concat!(
+                "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+                "{\"id\":\"alice\",\"role\":\"admin\"}\n",
+                "{\"id\":\"bob\",\"role\":\"user\"}\n",
+                "{\"$deleted\":true,\"id\":\"alice\"}\n",
+                "{\"id\":7,\"role\":\"bot\"}",
+            ),
+        )
+        .unwrap();
+
+        let file = FileTable::open(&path).unwrap();
+        assert_eq!(file.table().len(), 2);
+        assert!(!file.table().contains_key(&Key::from("alice")));
+        assert_eq!(
+            file.table().get(&Key::from(7)).unwrap().value(),
+            &json!({"id": 7, "role": "bot"})
+        );
+    }
+
+    #[test]
+    fn test_open_without_key_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain.jsonlt");
+        fs::write(&path, "{\"id\":\"alice\"}\n").unwrap();
+
+        assert!(FileTable::open(&path).is_err());
+        let file = FileTable::open_with_key(&path, "id").unwrap();
+        assert!(file.table().contains_key(&Key::from("alice")));
+    }
+
+    #[test]
+    fn test_invalid_line_reports_line_number() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.jsonlt");
+        fs::write(&path, "{\"id\":\"alice\"}\n{\"name\":\"bob\"}\n").unwrap();
+
+        let err = FileTable::open_with_key(&path, "id").unwrap_err();
+        assert!(err.full_message().contains("line 2"), "{err}");
+    }
+
+    #[test]
+    fn test_poll_applies_appended_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        fs::write(&path, "{\"id\":\"alice\"}\n").unwrap();
+
+        let mut file = FileTable::open_with_key(&path, "id").unwrap();
+        let (_, events) = file.subscribe_channel();
+        assert_eq!(file.poll().unwrap(), PollOutcome::Unchanged);
+
+        append(
+            &path,
+            "{\"id\":\"bob\"}\n{\"$deleted\":true,\"id\":\"alice\"}\n{\"id\":",
+        );
+        assert_eq!(file.poll().unwrap(), PollOutcome::Appended { lines: 2 });
+        assert!(file.table().contains_key(&Key::from("bob")));
+        assert!(!file.table().contains_key(&Key::from("alice")));
+
+        append(&path, "\"carol\"}\n");
+        assert_eq!(file.poll().unwrap(), PollOutcome::Appended { lines: 1 });
+        assert!(file.table().contains_key(&Key::from("carol")));
+
+        let changes: Vec<_> = events.try_iter().map(|e| (e.kind, e.key)).collect();
+        assert_eq!(
+            changes,
+            vec![
+                (ChangeKind::Insert, Key::from("bob")),
+                (ChangeKind::Delete, Key::from("alice")),
+                (ChangeKind::Insert, Key::from("carol")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_poll_reloads_after_compaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        fs::write(
+            &path,
+            "{\"id\":\"alice\",\"v\":1}\n{\"id\":\"alice\",\"v\":2}\n{\"id\":\"bob\"}\n",
+        )
+        .unwrap();
+
+        let mut file = FileTable::open_with_key(&path, "id").unwrap();
+        let (_, events) = file.subscribe_channel();
+
+        let compacted = dir.path().join("log.jsonlt.tmp");
+        fs::write(&compacted, "{\"id\":\"alice\",\"v\":3}\n").unwrap();
+        fs::rename(&compacted, &path).unwrap();
+
+        assert_eq!(file.poll().unwrap(), PollOutcome::Reloaded);
+        assert_eq!(file.table().len(), 1);
+        assert_eq!(
+            file.table().get(&Key::from("alice")).unwrap().value(),
+            &json!({"id": "alice", "v": 3})
+        );
+
+        let changes: Vec<_> = events.try_iter().map(|e| (e.kind, e.key)).collect();
+        assert_eq!(
+            changes,
+            vec![
+                (ChangeKind::Delete, Key::from("bob")),
+                (ChangeKind::Update, Key::from("alice")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_put_delete_and_compact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.jsonlt");
+
+        let mut file = FileTable::create(&path, "id").unwrap();
+        assert!(FileTable::create(&path, "id").is_err());
+        file.put(&Record::try_from_value(json!({"id": "bob", "v": 1})).unwrap())
+            .unwrap();
+        file.put(&Record::try_from_value(json!({"v": 2, "id": "alice"})).unwrap())
+            .unwrap();
+        file.put(&Record::try_from_value(json!({"id": "bob", "v": 3})).unwrap())
+            .unwrap();
+        assert!(file.delete("alice").unwrap());
+        assert!(!file.delete("carol").unwrap());
+        assert!(file
+            .put(&Record::try_from_value(json!({"v": 4})).unwrap())
+            .is_err());
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            concat!(
+                "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+                "{\"id\":\"bob\",\"v\":1}\n",
+                "{\"id\":\"alice\",\"v\":2}\n",
+                "{\"id\":\"bob\",\"v\":3}\n",
+                "{\"$deleted\":true,\"id\":\"alice\"}\n",
+            )
+        );
+
+        file.compact().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            concat!(
+                "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+                "{\"id\":\"bob\",\"v\":3}\n",
+            )
+        );
+        assert_eq!(file.table().len(), 1);
+    }
+
+    #[test]
+    fn test_put_after_unterminated_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        fs::write(&path, "{\"id\":\"alice\"}").unwrap();
+
+        let mut file = FileTable::open_with_key(&path, "id").unwrap();
+        file.put(&Record::try_from_value(json!({"id": "bob"})).unwrap())
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\"id\":\"alice\"}\n{\"id\":\"bob\"}\n"
+        );
+        assert_eq!(
+            FileTable::open_with_key(&path, "id").unwrap().table().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_put_truncates_torn_last_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        let header = "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n";
+        fs::write(
+            &path,
+            format!("{header}{{\"id\":\"a\"}}\n{{\"id\":\"x\",\"v"),
+        )
+        .unwrap();
+
+        let mut file = FileTable::open(&path).unwrap();
+        file.put(&Record::try_from_value(json!({"id": "b"})).unwrap())
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{header}{{\"id\":\"a\"}}\n{{\"id\":\"b\"}}\n")
+        );
+
+        // A line torn after the file was opened is cut off as well.
+        append(&path, "{\"id\":\"y\",");
+        assert!(file.delete("a").unwrap());
+        let reopened = FileTable::open(&path).unwrap();
+        assert_eq!(reopened.table().len(), 1);
+        assert!(reopened.table().contains_key(&Key::from("b")));
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .ends_with("{\"id\":\"b\"}\n{\"$deleted\":true,\"id\":\"a\"}\n"));
+    }
+
+    #[test]
+    fn test_open_defers_partially_written_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        fs::write(&path, "{\"id\":1}\n{\"id\":2,\"na").unwrap();
+
+        let mut file = FileTable::open_with_key(&path, "id").unwrap();
+        assert_eq!(file.table().len(), 1);
+        file.reload().unwrap();
+        assert_eq!(file.table().len(), 1);
+
+        append(&path, "me\":\"bob\"}\n");
+        assert_eq!(file.poll().unwrap(), PollOutcome::Appended { lines: 1 });
+        assert_eq!(
+            file.table().get(&Key::from(2)).unwrap().value(),
+            &json!({"id": 2, "name": "bob"})
+        );
+    }
+}
+
+#[cfg(feature = "serde")]
+mod stream_tests {
+    use jsonlt::io::{StreamReader, StreamWriter};
+    use jsonlt::{Entry, Header, Key, KeySpecifier, Record};
+    use serde_json::json;
+
+    #[test]
+    fn test_stream_round_trip() {
+        let mut writer = StreamWriter::new(Vec::new(), "id");
+        writer.write_header(&Header::new("id".into())).unwrap();
+        let key = writer
+            .write_record(&Record::try_from_value(json!({"name": "Alice", "id": "alice"})).unwrap())
+            .unwrap();
+        assert_eq!(key, Key::from("alice"));
+        writer.write_tombstone(&Key::from("alice")).unwrap();
+        let bytes = writer.into_inner().unwrap();
+
+        assert_eq!(
+            String::from_utf8(bytes.clone()).unwrap(),
+            concat!(
+                "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+                "{\"id\":\"alice\",\"name\":\"Alice\"}\n",
+                "{\"$deleted\":true,\"id\":\"alice\"}\n",
+            )
+        );
+
+        let mut reader = StreamReader::new(bytes.as_slice());
+        let entries: Vec<_> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+        assert_eq!(reader.key_specifier(), Some(&KeySpecifier::from("id")));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].line, 2);
+        assert!(!entries[0].entry.is_tombstone());
+        assert_eq!(entries[1].line, 3);
+        assert_eq!(
+            entries[1].entry,
+            Entry::Tombstone {
+                key: Key::from("alice")
+            }
+        );
+    }
+
+    #[test]
+    fn test_stream_writer_rejects_record_without_key() {
+        let mut writer = StreamWriter::new(Vec::new(), "id");
+        assert!(writer
+            .write_record(&Record::try_from_value(json!({"name": "Alice"})).unwrap())
+            .is_err());
+        assert!(writer
+            .write_record(&Record::from_value_unchecked(
+                json!({"id": 1, "$meta": true})
+            ))
+            .is_err());
+        assert_eq!(writer.into_inner().unwrap(), b"");
+    }
+
+    #[test]
+    fn test_stream_reader_compound_keys() {
+        let data = "{\"org\":\"acme\",\"id\":1}\n{\"org\":\"acme\",\"id\":2}\n";
+        let keys: Vec<_> = StreamReader::with_key(data.as_bytes(), ["org", "id"].as_slice())
+            .map(|entry| entry.unwrap().entry.key().clone())
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                Key::Tuple(vec!["acme".into(), 1.into()]),
+                Key::Tuple(vec!["acme".into(), 2.into()]),
+            ]
+        );
+    }
+}
+
+#[cfg(feature = "serde")]
+mod record_ref_tests {
+    use jsonlt::io::StreamReader;
+    use jsonlt::{Key, RecordRef};
+    use serde_json::json;
+
+    #[test]
+    fn test_record_ref_pointer_lookup() {
+        let line = concat!(
+            r#"{"id":1,"a/b":{"m~n":true},"esc\"aped":2,"list":[10,[20,21],{"x":null}],"#,
+            r#""dup":1,"dup":2,"text":"caf\u00e9"}"#,
+            "\r\n",
+        );
+        let record = RecordRef::parse(line).unwrap();
+        assert!(record.as_str().ends_with('}'));
+        assert_eq!(record.pointer("").unwrap().get(), record.as_str());
+        assert_eq!(record.get("id").unwrap().get(), "1");
+        assert_eq!(record.pointer("/a~1b/m~0n").unwrap().get(), "true");
+        assert_eq!(record.get("esc\"aped").unwrap().get(), "2");
+        assert_eq!(record.pointer("/list/1/0").unwrap().get(), "20");
+        assert_eq!(record.pointer("/list/2/x").unwrap().get(), "null");
+        assert_eq!(record.get("dup").unwrap().get(), "2");
+        assert_eq!(
+            record.pointer_as::<String>("/text").unwrap(),
+            Some("café".into())
+        );
+        for missing in ["/list/01", "/list/+1", "/list/3", "/id/0", "/nope", "id"] {
+            assert!(record.pointer(missing).is_none(), "{missing}");
+        }
+        assert!(record.pointer_as::<u64>("/text").is_err());
+        assert_eq!(
+            record.to_record().unwrap().value()["list"][1],
+            json!([20, 21])
+        );
+
+        assert!(RecordRef::parse("[1]").is_err());
+        assert!(RecordRef::parse("{\"id\":").is_err());
+    }
+
+    #[test]
+    fn test_stream_select_and_raw_entries() {
+        let data = concat!(
+            "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+            "{\"id\":\"alice\",\"role\":\"admin\"}\n",
+            "{\"id\":\"bob\",\"role\":\"admin\"}\n",
+            "{\"id\":\"carol\",\"role\":\"admin\"}\n",
+            "{\"id\":\"bob\",\"role\":\"user\"}\n",
+            "{\"$deleted\":true,\"id\":\"carol\"}\n",
+        );
+        let is_admin =
+            |record: RecordRef<'_>| record.pointer_as::<&str>("/role").unwrap() == Some("admin");
+        let admins = StreamReader::new(data.as_bytes()).select(is_admin).unwrap();
+        assert_eq!(admins.len(), 1);
+        assert_eq!(
+            admins.get(&Key::from("alice")).unwrap().value()["role"],
+            "admin"
+        );
+
+        let mut reader = StreamReader::new(data.as_bytes());
+        let entry = reader.read_raw_entry().unwrap().unwrap();
+        assert_eq!((entry.line, entry.key), (2, Key::from("alice")));
+        assert!(entry.record.is_some());
+        for _ in 0..3 {
+            reader.read_raw_entry().unwrap().unwrap();
+        }
+        let tombstone = reader.read_raw_entry().unwrap().unwrap();
+        assert_eq!(tombstone.line, 6);
+        assert!(tombstone.record.is_none());
+        assert!(reader.read_raw_entry().unwrap().is_none());
+
+        let invalid = "{\"id\":1}\n{\"id\":2,\"$x\":1}\n";
+        let err = StreamReader::with_key(invalid.as_bytes(), "id")
+            .select(|_| true)
+            .unwrap_err();
+        assert!(err.to_string().contains("line 2:"), "{err}");
+    }
+}
+
+#[cfg(all(feature = "serde", feature = "async"))]
+mod async_stream_tests {
+    use jsonlt::io::{
+        read_table, write_table, AsyncStreamReader, AsyncStreamWriter, StreamWriter, TableReader,
+    };
+    use jsonlt::{Key, Record};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_async_stream_round_trip() {
+        let mut buffer = Vec::new();
+        let mut writer = AsyncStreamWriter::new(&mut buffer, "id");
+        writer
+            .write_record(&Record::try_from_value(json!({"id": 1, "v": true})).unwrap())
+            .await
+            .unwrap();
+        writer.write_tombstone(&Key::from(1)).await.unwrap();
+        writer.flush().await.unwrap();
+
+        let mut reader = AsyncStreamReader::with_key(buffer.as_slice(), "id");
+        let first = reader.read_entry().await.unwrap().unwrap();
+        assert_eq!(first.line, 1);
+        assert_eq!(first.key(), &Key::from(1));
+        let second = reader.read_entry().await.unwrap().unwrap();
+        assert!(second.entry.is_tombstone());
+        assert!(reader.read_entry().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_table_reader_strips_line_terminator() {
+        let mut reader = TableReader::new(&b"{\"id\":1}\r\n{\"id\":2}"[..]);
+        assert_eq!(reader.read_line().await.unwrap().unwrap(), "{\"id\":1}");
+        assert_eq!(reader.read_line().await.unwrap().unwrap(), "{\"id\":2}");
+        assert!(reader.read_line().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_table_writer_sync_writes_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        let mut writer = jsonlt::io::create(&path).await.unwrap();
+        writer.write_line("{\"id\":1}").await.unwrap();
+        writer.sync().await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\"id\":1}\n");
+    }
+
+    #[tokio::test]
+    async fn test_async_read_and_write_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        std::fs::write(
+            &path,
+            concat!(
+                "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+                "{\"id\":2,\"v\":1}\n",
+                "{\"id\":1,\"v\":1}\n",
+                "{\"id\":2,\"v\":2}\n",
+                "{\"$deleted\":true,\"id\":1}\n",
+            ),
+        )
+        .unwrap();
+        let table = read_table(&path).await.unwrap();
+        assert_eq!(table.len(), 1);
+        assert_eq!(
+            table.get(&Key::from(2)).unwrap().value(),
+            &json!({"id": 2, "v": 2})
+        );
+
+        let copy = dir.path().join("copy.jsonlt");
+        write_table(&copy, &table, "id").await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&copy).unwrap(),
+            "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n{\"id\":2,\"v\":2}\n"
+        );
+        assert!(write_table(&copy, &table, "name").await.is_err());
+
+        // A record stored under a key other than its own would read back
+        // under a different key.
+        let mut miskeyed = table.clone();
+        miskeyed.insert(3, Record::try_from_value(json!({"id": 4})).unwrap());
+        let err = write_table(&copy, &miskeyed, "id").await.unwrap_err();
+        assert!(err.full_message().contains("stored under key 3"), "{err}");
+        let mut writer = StreamWriter::new(Vec::new(), "id");
+        assert!(writer.write_table(&miskeyed).is_err());
+
+        std::fs::write(&path, "{\"id\":1}\n").unwrap();
+        assert!(read_table(&path).await.is_err());
+    }
+}
+
+#[cfg(feature = "serde")]
+mod history_tests {
+    use jsonlt::io::FileTable;
+    use jsonlt::Key;
+    use serde_json::json;
+    use std::fs;
+
+    const LOG: &str = concat!(
+        "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+        "{\"id\":\"alice\",\"v\":1}\n",
+        "{\"id\":\"bob\",\"v\":1}\n",
+        "{\"id\":\"alice\",\"v\":2}\n",
+        "{\"$deleted\":true,\"id\":\"alice\"}\n",
+        "{\"id\":\"alice\",\"v\":3}\n",
+    );
+
+    #[test]
+    fn test_history_lists_every_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        fs::write(&path, LOG).unwrap();
+        let file = FileTable::open(&path).unwrap();
+
+        let history = file.history("alice").unwrap();
+        let lines: Vec<_> = history.iter().map(|version| version.line).collect();
+        assert_eq!(lines, vec![2, 4, 5, 6]);
+        assert!(history[2].entry.is_tombstone());
+        assert_eq!(
+            history[1].entry.record().unwrap().value(),
+            &json!({"id": "alice", "v": 2})
+        );
+        assert_eq!(file.history(Key::from("carol")).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_as_of_reconstructs_earlier_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        fs::write(&path, LOG).unwrap();
+        let file = FileTable::open(&path).unwrap();
+
+        assert!(file.as_of(1).unwrap().is_empty());
+
+        let state = file.as_of(4).unwrap();
+        assert_eq!(state.len(), 2);
+        assert_eq!(
+            state.get(&Key::from("alice")).unwrap().value()["v"],
+            json!(2)
+        );
+
+        let state = file.as_of(5).unwrap();
+        assert!(!state.contains_key(&Key::from("alice")));
+        assert!(state.contains_key(&Key::from("bob")));
+
+        let state = file.as_of(u64::MAX).unwrap();
+        assert_eq!(
+            state.get(&Key::from("alice")).unwrap().value()["v"],
+            json!(3)
+        );
+    }
+}
+
+#[cfg(feature = "serde")]
+mod lazy_tests {
+    use jsonlt::io::{LazyOptions, LazyTable};
+    use jsonlt::Key;
+    use serde_json::json;
+    use std::fs;
+    use std::io::Write;
+
+    const LOG: &str = concat!(
+        "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+        "{\"id\":\"alice\",\"v\":1,\"tags\":[\"a\",{\"b\":null}]}\n",
+        "{\"id\":\"bob\",\"v\":1}\r\n",
+        "{\"id\":\"carol\",\"v\":1}\n",
+        "{\"$deleted\":true,\"id\":\"bob\"}\n",
+        "{\"id\":\"alice\",\"v\":2}",
+    );
+
+    #[test]
+    fn test_lazy_table_reads_latest_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        fs::write(&path, LOG).unwrap();
+
+        let table = LazyTable::open(&path).unwrap();
+        assert_eq!(table.len(), 2);
+        assert!(table.header().is_some());
+        assert!(table.contains_key("carol"));
+        assert!(!table.contains_key("bob"));
+        assert!(table.get("bob").unwrap().is_none());
+        assert_eq!(
+            table.get("alice").unwrap().unwrap().value(),
+            &json!({"id": "alice", "v": 2})
+        );
+        assert_eq!(
+            table.get("carol").unwrap().unwrap().value(),
+            &json!({"id": "carol", "v": 1})
+        );
+        let mut keys: Vec<_> = table.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec![Key::from("alice"), Key::from("carol")]);
+    }
+
+    #[test]
+    fn test_lazy_table_cache_and_rewrites() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        fs::write(&path, LOG).unwrap();
+
+        let table = LazyTable::open(&path).unwrap().with_cache_capacity(1);
+        table.get("carol").unwrap();
+        table.get("alice").unwrap();
+        fs::write(&path, "{\"id\":\"x\"}\n").unwrap();
+
+        // The most recent record is still cached; the other was evicted.
+        assert!(table.get("alice").unwrap().is_some());
+        let err = table.get("carol").unwrap_err();
+        assert!(
+            err.full_message().contains("changed since it was indexed"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_lazy_table_reports_invalid_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        for (contents, message) in [
+            (
+                "{\"id\":1}\n{\"id\":2,\"$x\":1}\n",
+                "line 2: field \"$x\" uses the reserved",
+            ),
+            ("{\"id\":1}\n[1]\n", "line 2: line must be a JSON object"),
+            ("{\"id\":1}\n{\"id\":2,\"v\":[}\n", "line 2:"),
+            (
+                "{\"id\":1}\n{\"$deleted\":true,\"id\":1,\"v\":2}\n",
+                "line 2: tombstone",
+            ),
+        ] {
+            fs::write(&path, contents).unwrap();
+            let err = LazyTable::open_with_key(&path, "id").unwrap_err();
+            assert!(err.full_message().contains(message), "{err}");
+        }
+
+        let gz = dir.path().join("log.jsonlt.gz");
+        fs::write(&gz, "").unwrap();
+        assert!(LazyTable::open(&gz).is_err());
+    }
+
+    #[test]
+    fn test_lazy_table_resumes_from_saved_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        fs::write(&path, LOG).unwrap();
+        LazyTable::open(&path).unwrap().save_index().unwrap();
+        assert!(dir.path().join("log.jsonlt.idx").exists());
+
+        // Complete the unterminated last line and append more.
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"\n{\"id\":\"dave\"}\n{\"$deleted\":true,\"id\":\"carol\"}\n")
+            .unwrap();
+
+        let table = LazyTable::open(&path).unwrap();
+        let last = LOG.rfind('\n').unwrap() + 1;
+        assert_eq!(table.index_coverage(), last as u64);
+        let mut keys: Vec<_> = table.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec![Key::from("alice"), Key::from("dave")]);
+        assert_eq!(table.get("alice").unwrap().unwrap().value()["v"], 2);
+
+        let options = LazyOptions {
+            use_index: false,
+            ..LazyOptions::default()
+        };
+        assert_eq!(
+            LazyTable::open_with(&path, &options)
+                .unwrap()
+                .index_coverage(),
+            0
+        );
+
+        // A line past the covered prefix is still reported with its number.
+        file.write_all(b"[1]\n").unwrap();
+        let err = LazyTable::open(&path).unwrap_err();
+        assert!(err.full_message().contains("line 9:"), "{err}");
+    }
+
+    #[test]
+    fn test_lazy_table_ignores_stale_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        fs::write(&path, LOG).unwrap();
+        LazyTable::open(&path).unwrap().save_index().unwrap();
+
+        // Rewrite a covered line without changing the length of the file.
+        fs::write(&path, LOG.replace("\"carol\"", "\"erica\"")).unwrap();
+        let table = LazyTable::open(&path).unwrap();
+        assert_eq!(table.index_coverage(), 0);
+        assert!(table.contains_key("erica"));
+        assert!(!table.contains_key("carol"));
+        assert_eq!(table.get("erica").unwrap().unwrap().value()["v"], 1);
+
+        // A truncated file is shorter than the covered prefix.
+        fs::write(&path, &LOG[..20]).unwrap();
+        assert!(LazyTable::open(&path).is_err());
+
+        // An index built with another key specifier is not used.
+        let headerless = dir.path().join("headerless.jsonlt");
+        fs::write(
+            &headerless,
+            "{\"id\":1,\"n\":\"a\"}\n{\"id\":2,\"n\":\"b\"}\n",
+        )
+        .unwrap();
+        LazyTable::open_with_key(&headerless, "id")
+            .unwrap()
+            .save_index()
+            .unwrap();
+        let table = LazyTable::open_with_key(&headerless, "n").unwrap();
+        assert_eq!(table.index_coverage(), 0);
+        assert!(table.contains_key("b"));
+
+        // A corrupt index is not used either.
+        fs::write(dir.path().join("headerless.jsonlt.idx"), "{").unwrap();
+        let table = LazyTable::open_with_key(&headerless, "id").unwrap();
+        assert_eq!(table.index_coverage(), 0);
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn test_lazy_table_verifies_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        let mut contents = String::from("{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n");
+        contents
+            .extend((0..10_000).map(|id| format!("{{\"id\":{id},\"name\":\"user {id:05}\"}}\n")));
+        fs::write(&path, &contents).unwrap();
+        LazyTable::open(&path).unwrap().save_index().unwrap();
+        let fast = LazyOptions {
+            verify_index: false,
+            ..LazyOptions::default()
+        };
+        let covered = contents.len() as u64;
+        assert_eq!(LazyTable::open(&path).unwrap().index_coverage(), covered);
+        assert_eq!(
+            LazyTable::open_with(&path, &fast).unwrap().index_coverage(),
+            covered
+        );
+
+        // An edit in the middle that keeps the length is outside the windows
+        // checked by the fast path.
+        let edited = contents.replace("\"user 05000\"", "\"user 0500x\"");
+        let mut file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.write_all(edited.as_bytes()).unwrap();
+        drop(file);
+        assert_eq!(
+            LazyTable::open_with(&path, &fast).unwrap().index_coverage(),
+            covered
+        );
+        let table = LazyTable::open(&path).unwrap();
+        assert_eq!(table.index_coverage(), 0);
+        assert_eq!(
+            table.get(5000).unwrap().unwrap().value()["name"],
+            "user 0500x"
+        );
+
+        // A file replaced by another is not trusted, even if it is the same.
+        let replacement = dir.path().join("replacement.jsonlt");
+        fs::write(&replacement, &contents).unwrap();
+        fs::rename(&replacement, &path).unwrap();
+        let table = LazyTable::open(&path).unwrap();
+        if cfg!(unix) {
+            assert_eq!(table.index_coverage(), 0);
+        }
+        assert_eq!(table.len(), 10_000);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod merge_tests {
+    use jsonlt::io::FileTable;
+    use jsonlt::{merge, merge_files, merge_with, Key, MergeOptions, Record, Table};
+    use serde_json::{json, Value};
+    #[cfg(feature = "cli")]
+    use std::fs;
+    #[cfg(feature = "cli")]
+    use std::process::Command;
+
+    fn table(records: &[Value]) -> Table {
+        let mut table = Table::new();
+        for record in records {
+            table.insert(
+                record["id"].as_str().unwrap(),
+                Record::try_from_value(record.clone()).unwrap(),
+            );
+        }
+        table
+    }
+
+    #[test]
+    fn test_merge_takes_one_sided_changes() {
+        let base = table(&[json!({"id": "a", "v": 1}), json!({"id": "b", "v": 1})]);
+        let ours = table(&[json!({"id": "a", "v": 2}), json!({"id": "b", "v": 1})]);
+        let theirs = table(&[json!({"id": "a", "v": 1}), json!({"id": "c", "v": 1})]);
+
+        let result = merge(&base, &ours, &theirs);
+        assert!(result.is_clean());
+        assert_eq!(result.table.len(), 2);
+        assert_eq!(result.table.get(&Key::from("a")).unwrap().value()["v"], 2);
+        assert!(!result.table.contains_key(&Key::from("b")));
+        assert!(result.table.contains_key(&Key::from("c")));
+    }
+
+    #[test]
+    fn test_merge_reports_conflicts() {
+        let base = table(&[json!({"id": "a", "x": 1, "y": 1}), json!({"id": "b"})]);
+        let ours = table(&[
+            json!({"id": "a", "x": 2, "y": 1}),
+            json!({"id": "b", "v": 1}),
+        ]);
+        let theirs = table(&[json!({"id": "a", "x": 1, "y": 2})]);
+
+        let result = merge(&base, &ours, &theirs);
+        let keys: Vec<_> = result.conflicts.iter().map(|c| c.key.to_string()).collect();
+        assert_eq!(keys, vec!["a", "b"]);
+        assert!(result.conflicts[1].theirs.is_none());
+
+        let result = merge_with(&base, &ours, &theirs, MergeOptions { field_level: true });
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(
+            result.table.get(&Key::from("a")).unwrap().value(),
+            &json!({"id": "a", "x": 2, "y": 2})
+        );
+
+        let theirs = table(&[json!({"id": "a", "x": 3, "y": 1}), json!({"id": "b"})]);
+        let result = merge_with(&base, &ours, &theirs, MergeOptions { field_level: true });
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(result.conflicts[0].fields, vec!["/x".to_string()]);
+    }
+
+    #[test]
+    fn test_merge_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let header = "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n";
+        std::fs::write(path("base.jsonlt"), "{\"id\":\"a\"}\n").unwrap();
+        std::fs::write(path("ours.jsonlt"), format!("{header}{{\"id\":\"a\"}}\n")).unwrap();
+        std::fs::write(path("theirs.jsonlt"), "{\"id\":\"a\"}\n{\"id\":\"b\"}\n").unwrap();
+        let open = |name| FileTable::open_with_key(path(name), "id").unwrap();
+        let (base, ours, theirs) = (
+            open("base.jsonlt"),
+            open("ours.jsonlt"),
+            open("theirs.jsonlt"),
+        );
+
+        let merged = merge_files(&base, &ours, &theirs, MergeOptions::default()).unwrap();
+        assert_eq!(merged.key, "id".into());
+        assert!(merged.header.is_some());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let permissions = std::fs::Permissions::from_mode(0o640);
+            std::fs::set_permissions(path("ours.jsonlt"), permissions).unwrap();
+        }
+        merged.write_to_path(path("ours.jsonlt")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path("ours.jsonlt")).unwrap(),
+            format!("{header}{{\"id\":\"a\"}}\n{{\"id\":\"b\"}}\n")
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = std::fs::metadata(path("ours.jsonlt")).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+        }
+        assert!(!path("ours.jsonlt.merge").exists());
+
+        std::fs::write(path("empty.jsonlt"), "").unwrap();
+        let headerless = FileTable::open(path("empty.jsonlt")).unwrap();
+        let err = merge_files(
+            &headerless,
+            &headerless,
+            &headerless,
+            MergeOptions::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("no key specifier"), "{err}");
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_merge_driver() {
+        let dir = tempfile::tempdir().unwrap();
+        let header = "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n";
+        let base = dir.path().join("base.jsonlt");
+        let ours = dir.path().join("ours.jsonlt");
+        let theirs = dir.path().join("theirs.jsonlt");
+        fs::write(&base, format!("{header}{{\"id\":\"a\",\"v\":1}}\n")).unwrap();
+        fs::write(
+            &ours,
+            format!("{header}{{\"id\":\"a\",\"v\":1}}\n{{\"id\":\"b\"}}\n"),
+        )
+        .unwrap();
+        fs::write(
+            &theirs,
+            format!("{header}{{\"id\":\"a\",\"v\":1}}\n{{\"id\":\"a\",\"v\":2}}\n"),
+        )
+        .unwrap();
+
+        let status = Command::new(env!("CARGO_BIN_EXE_jsonlt-merge"))
+            .args([&base, &ours, &theirs])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(
+            fs::read_to_string(&ours).unwrap(),
+            format!("{header}{{\"id\":\"a\",\"v\":2}}\n{{\"id\":\"b\"}}\n")
+        );
+
+        fs::write(&theirs, format!("{header}{{\"id\":\"b\",\"v\":3}}\n")).unwrap();
+        let status = Command::new(env!("CARGO_BIN_EXE_jsonlt-merge"))
+            .args([&base, &ours, &theirs])
+            .status()
+            .unwrap();
+        assert_eq!(status.code(), Some(1));
+        assert_eq!(
+            fs::read_to_string(&ours).unwrap(),
+            format!(
+                "{header}{}",
+                concat!(
+                    "<<<<<<< ours\n{\"id\":\"a\",\"v\":2}\n",
+                    "||||||| base\n{\"id\":\"a\",\"v\":1}\n",
+                    "=======\n{\"$deleted\":true,\"id\":\"a\"}\n>>>>>>> theirs\n",
+                    "<<<<<<< ours\n{\"id\":\"b\"}\n",
+                    "||||||| base\n",
+                    "=======\n{\"id\":\"b\",\"v\":3}\n>>>>>>> theirs\n",
+                )
+            )
+        );
+
+        // Without a key specifier nothing can be merged or written.
+        for path in [&base, &ours, &theirs] {
+            fs::write(path, "{\"id\":\"a\"}\n").unwrap();
+        }
+        let output = Command::new(env!("CARGO_BIN_EXE_jsonlt-merge"))
+            .args([&base, &ours, &theirs])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("no key specifier"));
+        assert_eq!(fs::read_to_string(&ours).unwrap(), "{\"id\":\"a\"}\n");
+    }
+}
+
+#[cfg(feature = "serde")]
+mod diff_tests {
+    use jsonlt::{FieldChange, Key, Operations, Record, Table};
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_lists_changes() {
+        let mut old = Table::new();
+        old.insert(
+            "a",
+            Record::try_from_value(json!({"id": "a", "n": 1, "tags": ["x"]})).unwrap(),
+        );
+        old.insert("b", Record::try_from_value(json!({"id": "b"})).unwrap());
+        old.insert("c", Record::try_from_value(json!({"id": "c"})).unwrap());
+
+        let mut new = Table::new();
+        new.insert(
+            "a",
+            Record::try_from_value(json!({"id": "a", "tags": ["x", "y"], "x/y": true})).unwrap(),
+        );
+        new.insert("c", Record::try_from_value(json!({"id": "c"})).unwrap());
+        new.insert("d", Record::try_from_value(json!({"id": "d"})).unwrap());
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added.keys().collect::<Vec<_>>(), vec![&Key::from("d")]);
+        assert_eq!(
+            diff.removed.keys().collect::<Vec<_>>(),
+            vec![&Key::from("b")]
+        );
+        assert_eq!(
+            diff.changed[&Key::from("a")].changes,
+            vec![
+                FieldChange {
+                    pointer: "/n".into(),
+                    old: Some(json!(1)),
+                    new: None,
+                },
+                FieldChange {
+                    pointer: "/tags".into(),
+                    old: Some(json!(["x"])),
+                    new: Some(json!(["x", "y"])),
+                },
+                FieldChange {
+                    pointer: "/x~1y".into(),
+                    old: None,
+                    new: Some(json!(true)),
+                },
+            ]
+        );
+        assert!(new.diff(&new.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_diff_operations_transform_table() {
+        let mut old = Table::new();
+        old.insert(
+            1,
+            Record::try_from_value(json!({"id": 1, "v": "old"})).unwrap(),
+        );
+        old.insert(2, Record::try_from_value(json!({"id": 2})).unwrap());
+
+        let mut new = Table::new();
+        new.insert(
+            1,
+            Record::try_from_value(json!({"id": 1, "v": "new"})).unwrap(),
+        );
+        new.insert(3, Record::try_from_value(json!({"id": 3})).unwrap());
+
+        let ops = old.diff(&new).into_operations();
+        assert!(matches!(ops[0], Operations::Delete { .. }));
+        assert!(matches!(ops[1], Operations::Insert { .. }));
+        assert!(matches!(ops[2], Operations::Update { .. }));
+        for op in ops {
+            op.apply(&mut old).unwrap();
+        }
+        assert!(old.diff(&new).is_empty());
+    }
+}
+
+#[cfg(feature = "serde")]
+mod validate_tests {
+    use jsonlt::{validate, validate_reader, Severity, ValidateOptions};
+    use serde_json::json;
+
+    fn issues(data: &str, options: &ValidateOptions) -> Vec<(u64, u64, Severity, String)> {
+        validate_reader(data.as_bytes(), options)
+            .unwrap()
+            .issues
+            .into_iter()
+            .map(|issue| (issue.line, issue.column, issue.severity, issue.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_file_has_no_issues() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.jsonlt");
+        std::fs::write(
+            &path,
+            concat!(
+                "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+                "{\"id\":\"alice\",\"role\":\"admin\"}\n",
+                "{\"$deleted\":true,\"id\":\"alice\"}\n",
+            ),
+        )
+        .unwrap();
+
+        let report = validate(&path).unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.issues, vec![]);
+    }
+
+    #[test]
+    fn test_reports_every_issue() {
+        let data = concat!(
+            "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+            "{\"role\":\"user\", \"id\":\"bob\"}\r\n",
+            "{\"id\":true}\n",
+            "{\"id\":\"carol\",\"a\":{\"b\":1,\"b\":2}}\n",
+            "{\"$deleted\":false,\"id\":\"bob\"}\n",
+            "{\"$deleted\":true,\"id\":\"bob\",\"role\":\"user\"}\n",
+            "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+            "\n",
+            "[1]\n",
+            "{\"id\":\"dave\"",
+        );
+        let warning =
+            |line, column, message: &str| (line, column, Severity::Warning, message.into());
+        let error = |line, column, message: &str| (line, column, Severity::Error, message.into());
+        assert_eq!(
+            issues(data, &ValidateOptions::default()),
+            vec![
+                warning(2, 28, "line ends with a carriage return"),
+                warning(2, 3, "line is not in canonical form"),
+                error(3, 2, "key must be a string or an integer, found true"),
+                error(4, 26, "duplicate member \"b\""),
+                warning(4, 3, "line is not in canonical form"),
+                error(5, 2, "$deleted must be true"),
+                error(
+                    6,
+                    29,
+                    "tombstone must contain only key fields, found \"role\""
+                ),
+                error(7, 1, "header must be the first line"),
+                error(8, 1, "empty line"),
+                error(9, 1, "line must be a JSON object"),
+                error(10, 12, "EOF while parsing an object"),
+                warning(10, 1, "missing newline at end of file"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_from_options() {
+        let data = "{\"id\":1}\n{\"id\":2}\n";
+        assert_eq!(
+            issues(data, &ValidateOptions::default()),
+            vec![(
+                1,
+                1,
+                Severity::Error,
+                "no key specifier: the file has no header declaring one".into()
+            )]
+        );
+        let options = ValidateOptions {
+            key: Some("id".into()),
+            ..ValidateOptions::default()
+        };
+        assert_eq!(issues(data, &options), vec![]);
+    }
+
+    #[test]
+    fn test_schema_conformance() {
+        let data = concat!(
+            "{\"$jsonlt\":{\"key\":\"id\",\"schema\":{\"properties\":{\"age\":{\"minimum\":0,\"type\":\"integer\"}},",
+            "\"required\":[\"name\"]},\"version\":1}}\n",
+            "{\"age\":30,\"id\":1,\"name\":\"alice\"}\n",
+            "{\"age\":-1.5,\"id\":2}\n",
+            "{\"$deleted\":true,\"id\":1}\n",
+        );
+        assert_eq!(issues(data, &ValidateOptions::default()), vec![]);
+
+        let options = ValidateOptions {
+            check_schema: true,
+            ..ValidateOptions::default()
+        };
+        assert_eq!(
+            issues(data, &options),
+            vec![
+                (
+                    3,
+                    1,
+                    Severity::Error,
+                    "missing required field \"name\"".into()
+                ),
+                (3, 2, Severity::Error, "/age: expected type integer".into()),
+                (3, 2, Severity::Error, "/age: number is less than 0".into()),
+            ]
+        );
+
+        let options = ValidateOptions {
+            schema: Some(json!({"additionalProperties": false, "properties": {"id": {}}})),
+            ..ValidateOptions::default()
+        };
+        let report = validate_reader(data.as_bytes(), &options).unwrap();
+        assert_eq!(report.errors().count(), 3);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod format_tests {
+    use jsonlt::{check_format, format};
+    use std::fs;
+
+    #[test]
+    fn test_format_rewrites_lines_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.jsonlt");
+        fs::write(
+            &path,
+            concat!(
+                "\u{feff}{\"$jsonlt\":{\"version\":1,\"key\":\"id\"}}\r\n",
+                "{\"id\":\"alice\",\"role\":\"admin\"}\n",
+                "{ \"role\": \"user\", \"id\": \"alice\" }\n",
+                "{\"id\":\"alice\",\"$deleted\":true}",
+            ),
+        )
+        .unwrap();
+
+        let report = check_format(&path).unwrap();
+        assert_eq!(report.lines, 4);
+        assert_eq!(report.changed, vec![1, 3, 4]);
+        assert!(fs::read_to_string(&path).unwrap().starts_with('\u{feff}'));
+
+        assert_eq!(format(&path).unwrap(), report);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            concat!(
+                "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+                "{\"id\":\"alice\",\"role\":\"admin\"}\n",
+                "{\"id\":\"alice\",\"role\":\"user\"}\n",
+                "{\"$deleted\":true,\"id\":\"alice\"}\n",
+            )
+        );
+        assert!(check_format(&path).unwrap().is_canonical());
+        assert!(format(&path).unwrap().is_canonical());
+    }
+
+    #[test]
+    fn test_format_leaves_invalid_file_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.jsonlt");
+        fs::write(&path, "{ \"id\": 1 }\n[2]\n").unwrap();
+
+        let err = format(&path).unwrap_err();
+        assert!(err.full_message().contains("line 2"), "{err}");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{ \"id\": 1 }\n[2]\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rewrites_keep_permissions() {
+        use jsonlt::io::{Durability, FileTable};
+        use jsonlt::{format_with, Record};
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.jsonlt");
+        let mode = |path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        fs::write(&path, "{ \"id\": 1 }\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        for durability in [Durability::None, Durability::Commit] {
+            fs::write(&path, "{ \"id\": 1 }\n").unwrap();
+            assert_eq!(format_with(&path, durability).unwrap().changed, [1]);
+            assert_eq!(fs::read_to_string(&path).unwrap(), "{\"id\":1}\n");
+            assert_eq!(mode(&path), 0o600);
+        }
+
+        let mut table = FileTable::open_with_key(&path, "id").unwrap();
+        table
+            .put(&Record::try_from(r#"{"id":1,"v":2}"#).unwrap())
+            .unwrap();
+        table.compact().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"id\":1,\"v\":2}\n");
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod convert_tests {
+    use jsonlt::io::{
+        read_json_array, read_jsonl, write_json_array, write_json_object, write_jsonlt, FileTable,
+    };
+    use jsonlt::{ErrorKind, Key, KeySpecifier, Record, Table};
+    use serde_json::json;
+
+    #[test]
+    fn test_json_array_to_jsonlt() {
+        let data = r#"[
+            {"id": "b", "n": 1},
+            {"id": "a", "n": 2},
+            {"id": "b", "n": 3}
+        ]"#;
+        let key = KeySpecifier::from("id");
+        let table = read_json_array(data.as_bytes(), &key).unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.get(&Key::from("b")).unwrap().value(),
+            &json!({"id": "b", "n": 3})
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.jsonlt");
+        write_jsonlt(std::fs::File::create(&path).unwrap(), &table, &key).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            concat!(
+                "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+                "{\"id\":\"a\",\"n\":2}\n",
+                "{\"id\":\"b\",\"n\":3}\n",
+            )
+        );
+        assert!(FileTable::open(&path)
+            .unwrap()
+            .table()
+            .diff(&table)
+            .is_empty());
+
+        let mut array = Vec::new();
+        write_json_array(&mut array, &table).unwrap();
+        assert_eq!(
+            String::from_utf8(array.clone()).unwrap(),
+            "[\n{\"id\":\"a\",\"n\":2},\n{\"id\":\"b\",\"n\":3}\n]\n"
+        );
+        assert!(read_json_array(array.as_slice(), &key)
+            .unwrap()
+            .diff(&table)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_jsonl_import_lists_offending_records() {
+        let key = KeySpecifier::from(vec!["org".to_string(), "id".to_string()]);
+        let data =
+            "{\"org\":\"x\",\"id\":1}\n\n{\"org\":\"x\"}\n[1]\n{\"org\":\"y\",\"id\":1,\"$v\":2}\n";
+        let err = read_jsonl(data.as_bytes(), &key).unwrap_err().to_string();
+        assert!(err.contains("3 records have no valid key"), "{err}");
+        assert!(err.contains("line 3: missing key field \"id\""), "{err}");
+        assert!(
+            err.contains("line 4: record must be a JSON object"),
+            "{err}"
+        );
+        assert!(
+            err.contains("line 5: field \"$v\" uses the reserved"),
+            "{err}"
+        );
+
+        let table = read_jsonl(
+            "{\"org\":\"x\",\"id\":1}\n{\"org\":\"y\",\"id\":1}\n".as_bytes(),
+            &key,
+        )
+        .unwrap();
+        let mut object = Vec::new();
+        write_json_object(&mut object, &table).unwrap();
+        assert_eq!(
+            String::from_utf8(object).unwrap(),
+            concat!(
+                "{\n\"[\\\"x\\\",1]\":{\"id\":1,\"org\":\"x\"},\n",
+                "\"[\\\"y\\\",1]\":{\"id\":1,\"org\":\"y\"}\n}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_write_json_object_rejects_colliding_names() {
+        let mut table = Table::new();
+        table.insert(7, Record::try_from_value(json!({"id": 7})).unwrap());
+        table.insert("7", Record::try_from_value(json!({"id": "7"})).unwrap());
+
+        let mut object = Vec::new();
+        let err = write_json_object(&mut object, &table).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidKey);
+        assert!(err.to_string().contains("7 and \"7\""), "{err}");
+        assert_eq!(object, b"");
+    }
+}
+
+#[cfg(feature = "serde")]
+mod database_tests {
+    use super::record;
+    use jsonlt::io::{Database, FileTable};
+    use jsonlt::{ErrorKind, Key};
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn test_database_discovers_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut users = FileTable::create(dir.path().join("users.jsonlt"), "id").unwrap();
+        users.put(&record(json!({"id": "alice"}))).unwrap();
+        fs::write(dir.path().join("notes.txt"), "not a table").unwrap();
+
+        let mut db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.names().collect::<Vec<_>>(), ["users"]);
+        assert!(db
+            .table("users")
+            .unwrap()
+            .table()
+            .contains_key(&Key::from("alice")));
+        assert!(db.table("notes").is_none());
+
+        db.create_table("orders", "id").unwrap();
+        assert_eq!(db.names().collect::<Vec<_>>(), ["orders", "users"]);
+        assert!(db.create_table("orders", "id").is_err());
+        assert!(db.create_table("../escape", "id").is_err());
+    }
+
+    #[test]
+    fn test_database_rejects_compressed_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        FileTable::create(dir.path().join("users.jsonlt"), "id").unwrap();
+        fs::write(dir.path().join("orders.jsonlt.gz"), b"").unwrap();
+        fs::write(dir.path().join("backup.tar.gz"), b"").unwrap();
+
+        let err = Database::open(dir.path()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidFormat);
+        assert!(err.to_string().contains("orders.jsonlt.gz"));
+
+        fs::remove_file(dir.path().join("orders.jsonlt.gz")).unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.names().collect::<Vec<_>>(), ["users"]);
+    }
+
+    #[test]
+    fn test_transaction_commits_across_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.create_table("users", "id").unwrap();
+        db.create_table("orders", "id").unwrap();
+        db.table_mut("users")
+            .unwrap()
+            .put(&record(json!({"id": "bob"})))
+            .unwrap();
+
+        let mut tx = db.transaction();
+        tx.put("users", record(json!({"id": "alice"}))).unwrap();
+        tx.put("orders", record(json!({"id": 1, "user": "alice"})))
+            .unwrap();
+        assert!(tx.delete("users", "bob").unwrap());
+        assert!(tx.get("users", "alice").unwrap().is_some());
+        assert!(tx.get("users", "bob").unwrap().is_none());
+        assert!(!tx.delete("users", "carol").unwrap());
+        tx.commit().unwrap();
+
+        assert!(!dir.path().join(".jsonlt-journal").exists());
+        let db = Database::open(dir.path()).unwrap();
+        let users = db.table("users").unwrap().table();
+        assert!(users.contains_key(&Key::from("alice")));
+        assert!(!users.contains_key(&Key::from("bob")));
+        assert_eq!(db.table("orders").unwrap().table().len(), 1);
+    }
+
+    #[test]
+    fn test_transaction_rollback_discards_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.create_table("users", "id").unwrap();
+        let before = fs::read(dir.path().join("users.jsonlt")).unwrap();
+
+        let mut tx = db.transaction();
+        tx.put("users", record(json!({"id": "alice"}))).unwrap();
+        tx.rollback();
+
+        let mut tx = db.transaction();
+        tx.put("users", record(json!({"id": "bob"}))).unwrap();
+        drop(tx);
+
+        assert!(db.table("users").unwrap().table().is_empty());
+        assert_eq!(fs::read(dir.path().join("users.jsonlt")).unwrap(), before);
+    }
+
+    #[test]
+    fn test_transaction_rejects_unknown_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+
+        let mut tx = db.transaction();
+        let err = tx.put("users", record(json!({"id": "alice"}))).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TableNotFound);
+        assert!(err.to_string().contains("users"), "{err}");
+        assert_eq!(
+            tx.get("users", "alice").unwrap_err().kind(),
+            ErrorKind::TableNotFound
+        );
+    }
+
+    #[test]
+    fn test_open_rolls_back_interrupted_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.create_table("users", "id").unwrap();
+        db.create_table("orders", "id").unwrap();
+        let users = dir.path().join("users.jsonlt");
+        let orders = dir.path().join("orders.jsonlt");
+        let users_len = fs::metadata(&users).unwrap().len();
+        let orders_len = fs::metadata(&orders).unwrap().len();
+        drop(db);
+
+        // Simulate a crash after the journal was written and the tables were
+        // partly appended to.
+        fs::write(
+            dir.path().join(".jsonlt-journal"),
+            format!(
+                "{{\"$jsonlt-journal\":{{\"version\":1}}}}\n\
+                 {{\"length\":{orders_len},\"table\":\"orders\"}}\n\
+                 {{\"length\":{users_len},\"table\":\"users\"}}\n"
+            ),
+        )
+        .unwrap();
+        let mut file = fs::OpenOptions::new().append(true).open(&users).unwrap();
+        std::io::Write::write_all(&mut file, b"{\"id\":\"alice\"}\n{\"id\":\"bo").unwrap();
+        drop(file);
+
+        let db = Database::open(dir.path()).unwrap();
+        assert!(!dir.path().join(".jsonlt-journal").exists());
+        assert_eq!(fs::metadata(&users).unwrap().len(), users_len);
+        assert_eq!(fs::metadata(&orders).unwrap().len(), orders_len);
+        assert!(db.table("users").unwrap().table().is_empty());
+    }
+
+    #[test]
+    fn test_open_rejects_corrupt_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".jsonlt-journal"), "{\"length\":1}\n").unwrap();
+
+        let err = Database::open(dir.path()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidFormat);
+        assert!(dir.path().join(".jsonlt-journal").exists());
+    }
+}
+
+#[cfg(feature = "serde")]
+mod durability_tests {
+    use super::record;
+    #[cfg(feature = "test-util")]
+    use jsonlt::io::{simulate_crash, CrashPoint};
+    use jsonlt::io::{Database, Durability, FileTable};
+    use jsonlt::Key;
+    use serde_json::json;
+    #[cfg(feature = "test-util")]
+    use std::fs;
+    #[cfg(feature = "test-util")]
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::time::Duration;
+
+    #[test]
+    fn test_file_table_durability_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        let mut file = FileTable::create(&path, "id").unwrap();
+        assert_eq!(file.durability(), Durability::Commit);
+
+        let modes = [
+            Durability::None,
+            Durability::Always,
+            Durability::Group {
+                operations: 2,
+                interval: Duration::from_secs(60),
+            },
+            Durability::Commit,
+        ];
+        for (id, durability) in modes.into_iter().enumerate() {
+            file.set_durability(durability);
+            for n in 0..3 {
+                file.put(&record(json!({"id": id, "n": n}))).unwrap();
+            }
+            file.sync().unwrap();
+        }
+        file.compact().unwrap();
+
+        let reopened = FileTable::open(&path).unwrap();
+        assert_eq!(reopened.table().len(), modes.len());
+        assert_eq!(
+            reopened.table().get(&Key::from(3)).unwrap().value(),
+            &json!({"id": 3, "n": 2})
+        );
+    }
+
+    #[test]
+    fn test_group_durability_waits_for_next_append() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = FileTable::create(dir.path().join("log.jsonlt"), "id").unwrap();
+        file.set_durability(Durability::Group {
+            operations: 3,
+            interval: Duration::from_millis(200),
+        });
+        assert!(!file.sync_if_due().unwrap());
+
+        file.put(&record(json!({"id": 1}))).unwrap();
+        file.put(&record(json!({"id": 2}))).unwrap();
+        assert_eq!(file.unsynced(), 2);
+        assert!(!file.sync_if_due().unwrap());
+
+        // Nothing syncs the group while the file is idle.
+        std::thread::sleep(Duration::from_millis(250));
+        assert_eq!(file.unsynced(), 2);
+        assert!(file.sync_if_due().unwrap());
+        assert_eq!(file.unsynced(), 0);
+
+        file.put(&record(json!({"id": 3}))).unwrap();
+        std::thread::sleep(Duration::from_millis(250));
+        assert_eq!(file.unsynced(), 1);
+        file.put(&record(json!({"id": 4}))).unwrap();
+        assert_eq!(file.unsynced(), 0);
+
+        file.set_durability(Durability::None);
+        file.put(&record(json!({"id": 5}))).unwrap();
+        std::thread::sleep(Duration::from_millis(250));
+        assert!(!file.sync_if_due().unwrap());
+        assert_eq!(file.unsynced(), 1);
+    }
+
+    #[test]
+    fn test_database_sets_durability_of_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.create_table("users", "id").unwrap();
+        db.set_durability(Durability::Always);
+        db.create_table("orders", "id").unwrap();
+
+        assert_eq!(db.durability(), Durability::Always);
+        for name in ["users", "orders"] {
+            assert_eq!(db.table(name).unwrap().durability(), Durability::Always);
+        }
+
+        db.set_durability(Durability::None);
+        let mut tx = db.transaction();
+        tx.put("users", record(json!({"id": "alice"}))).unwrap();
+        tx.commit().unwrap();
+        assert!(!dir.path().join(".jsonlt-journal").exists());
+        assert_eq!(db.table("users").unwrap().table().len(), 1);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_interrupted_commits_are_rolled_back() {
+        for point in [
+            CrashPoint::JournalWritten,
+            CrashPoint::TableAppended,
+            CrashPoint::TablesAppended,
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let mut db = Database::open(dir.path()).unwrap();
+            db.create_table("users", "id").unwrap();
+            db.create_table("orders", "id").unwrap();
+            let mut tx = db.transaction();
+            tx.put("users", record(json!({"id": "alice"}))).unwrap();
+            tx.commit().unwrap();
+            let before = [
+                fs::read(dir.path().join("users.jsonlt")).unwrap(),
+                fs::read(dir.path().join("orders.jsonlt")).unwrap(),
+            ];
+
+            simulate_crash(point);
+            let crashed = catch_unwind(AssertUnwindSafe(|| {
+                let mut tx = db.transaction();
+                tx.put("users", record(json!({"id": "bob"}))).unwrap();
+                tx.put("orders", record(json!({"id": 1, "user": "bob"})))
+                    .unwrap();
+                tx.commit()
+            }));
+            assert!(crashed.is_err(), "{point:?}");
+            assert!(dir.path().join(".jsonlt-journal").exists(), "{point:?}");
+            drop(db);
+
+            let mut db = Database::open(dir.path()).unwrap();
+            let after = [
+                fs::read(dir.path().join("users.jsonlt")).unwrap(),
+                fs::read(dir.path().join("orders.jsonlt")).unwrap(),
+            ];
+            assert_eq!(after, before, "{point:?}");
+            assert!(!dir.path().join(".jsonlt-journal").exists(), "{point:?}");
+
+            let mut tx = db.transaction();
+            tx.put("orders", record(json!({"id": 1, "user": "alice"})))
+                .unwrap();
+            tx.commit().unwrap();
+            assert_eq!(db.table("orders").unwrap().table().len(), 1);
+        }
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_interrupted_compaction_keeps_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.jsonlt");
+        let mut file = FileTable::create(&path, "id").unwrap();
+        file.put(&record(json!({"id": 1}))).unwrap();
+        file.put(&record(json!({"id": 2}))).unwrap();
+        file.delete(1).unwrap();
+        let before = fs::read(&path).unwrap();
+
+        simulate_crash(CrashPoint::CompactWritten);
+        let crashed = catch_unwind(AssertUnwindSafe(|| file.compact()));
+        assert!(crashed.is_err());
+        assert_eq!(fs::read(&path).unwrap(), before);
+
+        let mut file = FileTable::open(&path).unwrap();
+        assert_eq!(file.table().len(), 1);
+        file.compact().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n{\"id\":2}\n"
+        );
+    }
+}
+
+#[cfg(feature = "serde")]
+mod reference_tests {
+    use super::record;
+    use jsonlt::io::{Database, FileTable};
+    use jsonlt::{check_references, ErrorKind, Key, OnDelete, Operations, Reference, Table};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn users_and_orders() -> BTreeMap<String, Table> {
+        let mut users = Table::new();
+        users.insert("alice", record(json!({"id": "alice"})));
+        users.insert("bob", record(json!({"id": "bob", "manager": "alice"})));
+        let mut orders = Table::new();
+        orders.insert(1, record(json!({"id": 1, "user_id": "alice"})));
+        orders.insert(2, record(json!({"id": 2, "user_id": "bob"})));
+        orders.insert(3, record(json!({"id": 3, "user_id": null})));
+        BTreeMap::from([("users".to_string(), users), ("orders".to_string(), orders)])
+    }
+
+    #[test]
+    fn test_parse_reference() {
+        let reference: Reference = " orders.customer.id->users ".parse().unwrap();
+        assert_eq!(reference.table, "orders");
+        assert_eq!(reference.field, "customer.id");
+        assert_eq!(reference.target, "users");
+        assert_eq!(reference.on_delete, OnDelete::Restrict);
+
+        for text in [
+            "orders.user_id",
+            "orders -> users",
+            ".user_id -> users",
+            "a.b ->",
+        ] {
+            let err = text.parse::<Reference>().unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidFormat, "{text}");
+        }
+    }
+
+    #[test]
+    fn test_check_references_reports_dangling() {
+        let mut tables = users_and_orders();
+        let orders = tables.get_mut("orders").unwrap();
+        orders.insert(4, record(json!({"id": 4, "user_id": "carol"})));
+        orders.insert(5, record(json!({"id": 5, "user_id": {"id": "alice"}})));
+        orders.insert(6, record(json!({"id": 6})));
+        let references = [
+            Reference::new("orders", "user_id", "users"),
+            Reference::new("users", "manager", "users"),
+        ];
+
+        let dangling = check_references(&references, |name| tables.get(name)).unwrap();
+        let found: Vec<_> = dangling
+            .iter()
+            .map(|dangling| (dangling.key.clone(), dangling.value.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (4.into(), json!("carol")),
+                (5.into(), json!({"id": "alice"}))
+            ]
+        );
+        assert_eq!(dangling[0].reference, references[0]);
+
+        let references = [Reference::new("orders", "user_id", "customers")];
+        let err = check_references(&references, |name| tables.get(name)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TableNotFound);
+    }
+
+    #[test]
+    fn test_apply_rejects_dangling_writes() {
+        let mut tables = users_and_orders();
+        let references = [
+            Reference::new("orders", "user_id", "users"),
+            Reference::new("users", "manager", "users"),
+        ];
+
+        let insert = Operations::Insert {
+            key: 4.into(),
+            record: record(json!({"id": 4, "user_id": "carol"})),
+        };
+        let err = insert
+            .apply_with_references("orders", &mut tables, &references)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DanglingReference);
+        assert!(err.to_string().contains("\"carol\""), "{err}");
+        assert_eq!(tables["orders"].len(), 3);
+
+        let update = Operations::Update {
+            key: 1.into(),
+            record: record(json!({"id": 1, "user_id": "bob"})),
+        };
+        update
+            .apply_with_references("orders", &mut tables, &references)
+            .unwrap();
+
+        let upsert = Operations::Upsert {
+            key: "carol".into(),
+            record: record(json!({"id": "carol", "manager": "carol"})),
+        };
+        upsert
+            .apply_with_references("users", &mut tables, &references)
+            .unwrap();
+
+        let err = Operations::Delete { key: 1.into() }
+            .apply_with_references("customers", &mut tables, &references)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TableNotFound);
+    }
+
+    #[test]
+    fn test_apply_restricts_deletes() {
+        let mut tables = users_and_orders();
+        let references = [
+            Reference::new("orders", "user_id", "users"),
+            Reference::new("users", "manager", "users"),
+        ];
+
+        let err = Operations::Delete { key: "bob".into() }
+            .apply_with_references("users", &mut tables, &references)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DanglingReference);
+        assert!(tables["users"].contains_key(&Key::from("bob")));
+
+        let err = Operations::Delete {
+            key: "carol".into(),
+        }
+        .apply_with_references("users", &mut tables, &references)
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        Operations::Delete { key: 2.into() }
+            .apply_with_references("orders", &mut tables, &references)
+            .unwrap();
+        Operations::Delete { key: "bob".into() }
+            .apply_with_references("users", &mut tables, &references)
+            .unwrap();
+        assert!(!tables["users"].contains_key(&Key::from("bob")));
+    }
+
+    #[test]
+    fn test_apply_cascades_deletes() {
+        let mut tables = users_and_orders();
+        let references = [
+            Reference::new("orders", "user_id", "users").on_delete(OnDelete::Cascade),
+            Reference::new("users", "manager", "users").on_delete(OnDelete::Cascade),
+        ];
+
+        Operations::Delete {
+            key: "alice".into(),
+        }
+        .apply_with_references("users", &mut tables, &references)
+        .unwrap();
+        assert!(tables["users"].is_empty());
+        assert_eq!(tables["orders"].keys().collect::<Vec<_>>(), [&3.into()]);
+
+        // A cascade that reaches a restricted reference changes nothing.
+        let mut tables = users_and_orders();
+        let references = [
+            Reference::new("orders", "user_id", "users"),
+            Reference::new("users", "manager", "users").on_delete(OnDelete::Cascade),
+        ];
+        let err = Operations::Delete {
+            key: "alice".into(),
+        }
+        .apply_with_references("users", &mut tables, &references)
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DanglingReference);
+        assert_eq!(tables["users"].len(), 2);
+        assert_eq!(tables["orders"].len(), 3);
+    }
+
+    #[test]
+    fn test_references_to_compound_keys() {
+        let mut users = Table::new();
+        users.insert(
+            Key::Tuple(vec!["eu".into(), 1.into()]),
+            record(json!({"region": "eu", "id": 1})),
+        );
+        let mut tables = BTreeMap::from([
+            ("users".to_string(), users),
+            ("orders".to_string(), Table::new()),
+        ]);
+        let references = [Reference::new("orders", "user", "users")];
+
+        Operations::Insert {
+            key: 1.into(),
+            record: record(json!({"id": 1, "user": ["eu", 1]})),
+        }
+        .apply_with_references("orders", &mut tables, &references)
+        .unwrap();
+        let err = Operations::Insert {
+            key: 2.into(),
+            record: record(json!({"id": 2, "user": ["us", 1]})),
+        }
+        .apply_with_references("orders", &mut tables, &references)
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DanglingReference);
+        let dangling = check_references(&references, |name| tables.get(name)).unwrap();
+        assert_eq!(dangling, []);
+
+        let err = Operations::Delete {
+            key: Key::Tuple(vec!["eu".into(), 1.into()]),
+        }
+        .apply_with_references("users", &mut tables, &references)
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DanglingReference);
+
+        let references = [references[0].clone().on_delete(OnDelete::Cascade)];
+        Operations::Delete {
+            key: Key::Tuple(vec!["eu".into(), 1.into()]),
+        }
+        .apply_with_references("users", &mut tables, &references)
+        .unwrap();
+        assert!(tables["orders"].is_empty());
+    }
+
+    #[test]
+    fn test_database_checks_references() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.create_table("users", "id").unwrap();
+        db.create_table("orders", "id").unwrap();
+        let mut tx = db.transaction();
+        tx.put("users", record(json!({"id": "alice"}))).unwrap();
+        tx.put("orders", record(json!({"id": 1, "user_id": "alice"})))
+            .unwrap();
+        tx.put("orders", record(json!({"id": 2, "user_id": "bob"})))
+            .unwrap();
+        tx.commit().unwrap();
+
+        let references = ["orders.user_id -> users".parse().unwrap()];
+        let dangling = db.check_references(&references).unwrap();
+        assert_eq!(dangling.len(), 1);
+        assert_eq!(
+            dangling[0].to_string(),
+            "orders record 2: user_id references missing users record \"bob\""
+        );
+
+        let err = db.add_reference(references[0].clone()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DanglingReference);
+        assert_eq!(db.references(), []);
+        let err = db
+            .add_reference("orders.user_id -> accounts".parse().unwrap())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TableNotFound);
+    }
+
+    #[test]
+    fn test_transaction_enforces_references() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.create_table("users", "id").unwrap();
+        db.create_table("orders", "id").unwrap();
+        db.add_reference("orders.user_id -> users".parse().unwrap())
+            .unwrap();
+
+        let mut tx = db.transaction();
+        let err = tx
+            .put("orders", record(json!({"id": 1, "user_id": "alice"})))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DanglingReference);
+        // Records staged in the same transaction can be referenced.
+        tx.put("users", record(json!({"id": "alice"}))).unwrap();
+        tx.put("orders", record(json!({"id": 1, "user_id": "alice"})))
+            .unwrap();
+        tx.commit().unwrap();
+
+        let mut tx = db.transaction();
+        let err = tx.delete("users", "alice").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DanglingReference);
+        assert!(tx.delete("orders", 1).unwrap());
+        assert!(tx.delete("users", "alice").unwrap());
+        tx.rollback();
+        assert_eq!(db.table("users").unwrap().table().len(), 1);
+    }
+
+    #[test]
+    fn test_transaction_cascades_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.create_table("users", "id").unwrap();
+        db.create_table("orders", "id").unwrap();
+        let reference = Reference::new("orders", "user_id", "users").on_delete(OnDelete::Cascade);
+        db.add_reference(reference.clone()).unwrap();
+
+        let mut tx = db.transaction();
+        tx.put("users", record(json!({"id": "alice"}))).unwrap();
+        tx.put("users", record(json!({"id": "bob"}))).unwrap();
+        tx.put("orders", record(json!({"id": 1, "user_id": "alice"})))
+            .unwrap();
+        tx.put("orders", record(json!({"id": 2, "user_id": "bob"})))
+            .unwrap();
+        tx.commit().unwrap();
+
+        let mut tx = db.transaction();
+        assert!(tx.delete("users", "alice").unwrap());
+        assert_eq!(tx.get("orders", 1).unwrap(), None);
+        tx.commit().unwrap();
+
+        let mut db = Database::open(dir.path()).unwrap();
+        db.add_reference(reference).unwrap();
+        let users = db.table("users").unwrap().table();
+        assert_eq!(users.keys().collect::<Vec<_>>(), [&"bob".into()]);
+        let orders = db.table("orders").unwrap().table();
+        assert_eq!(orders.keys().collect::<Vec<_>>(), [&2.into()]);
+    }
+
+    #[test]
+    fn test_commit_rechecks_references() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.create_table("users", "id").unwrap();
+        db.create_table("orders", "id").unwrap();
+        db.add_reference("orders.user_id -> users".parse().unwrap())
+            .unwrap();
+        let mut tx = db.transaction();
+        tx.put("users", record(json!({"id": "alice"}))).unwrap();
+        tx.commit().unwrap();
+
+        let mut tx = db.transaction();
+        assert!(tx.delete("users", "alice").unwrap());
+        // Another writer references the record before the delete commits.
+        let mut orders = FileTable::open(dir.path().join("orders.jsonlt")).unwrap();
+        orders
+            .put(&record(json!({"id": 1, "user_id": "alice"})))
+            .unwrap();
+        let err = tx.commit().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DanglingReference);
+        assert!(db
+            .table("users")
+            .unwrap()
+            .table()
+            .contains_key(&Key::from("alice")));
+    }
+}
+
+#[cfg(feature = "csv")]
+mod csv_tests {
+    use jsonlt::{ColumnType, CsvOptions, Key, Record, Table};
+    use serde_json::json;
+
+    #[test]
+    fn test_csv_round_trip() {
+        let records = [
+            json!({"id": 1, "name": "alice", "address": {"city": "Oslo", "zip": "0150"}}),
+            json!({"id": 2, "name": "true", "tags": ["a", "b"], "meta": {}, "note": "say \"hi\", ok"}),
+            json!({"id": 3, "score": 1.5, "active": false, "extra": null, "odd": {"x.y": 1}}),
+        ];
+        let mut table = Table::new();
+        for record in &records {
+            table.insert(
+                record["id"].as_i64().unwrap(),
+                Record::try_from_value(record.clone()).unwrap(),
+            );
+        }
+
+        let options = CsvOptions {
+            key: Some("id".into()),
+            ..CsvOptions::default()
+        };
+        let mut csv = Vec::new();
+        table.to_csv(&mut csv, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(csv.clone()).unwrap(),
+            concat!(
+                "id,active,address.city,address.zip,extra,meta,name,note,odd,score,tags\n",
+                "1,,Oslo,0150,,,alice,,,,\n",
+                "2,,,,,{},\"\"\"true\"\"\",\"say \"\"hi\"\", ok\",,,\"[\"\"a\"\",\"\"b\"\"]\"\n",
+                "3,false,,,null,,,,\"{\"\"x.y\"\":1}\",1.5,\n",
+            )
+        );
+
+        let read = Table::from_csv(csv.as_slice(), &CsvOptions::default()).unwrap();
+        assert!(read.diff(&table).is_empty());
+    }
+
+    #[test]
+    fn test_csv_import_with_types() {
+        let csv = "sku;qty;price;ok;dims.w\n007;3;2;true;{\"cm\":4}\n";
+        let mut options = CsvOptions {
+            key: Some("sku".into()),
+            delimiter: b';',
+            ..CsvOptions::default()
+        };
+        options.types.insert("sku".into(), ColumnType::String);
+        options.types.insert("price".into(), ColumnType::Number);
+        options.types.insert("dims.w".into(), ColumnType::Json);
+
+        let table = Table::from_csv(csv.as_bytes(), &options).unwrap();
+        assert_eq!(
+            table.get(&Key::from("007")).unwrap().value(),
+            &json!({"sku": "007", "qty": 3, "price": 2, "ok": true, "dims": {"w": {"cm": 4}}})
+        );
+
+        options.types.insert("qty".into(), ColumnType::Boolean);
+        let err = Table::from_csv(csv.as_bytes(), &options).unwrap_err();
+        assert!(err.to_string().contains("row 2: column \"qty\""), "{err}");
+    }
+
+    #[test]
+    fn test_csv_key_column() {
+        let csv = "code,name\nx,alpha\n7,beta\n";
+        let table = Table::from_csv(csv.as_bytes(), &CsvOptions::default()).unwrap();
+        let mut keys: Vec<_> = table.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec![Key::from(7), Key::from("x")]);
+
+        let options = CsvOptions {
+            key: Some("id".into()),
+            ..CsvOptions::default()
+        };
+        let err = Table::from_csv(csv.as_bytes(), &options).unwrap_err();
+        assert!(err.to_string().contains("missing key field"), "{err}");
+    }
+}
+
+#[cfg(feature = "arrow")]
+mod arrow_tests {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, Int64Type};
+    use arrow_array::RecordBatch;
+    use arrow_schema::{DataType, Field, Schema};
+    use jsonlt::io::StreamReader;
+    use jsonlt::{infer_schema, ArrowOptions, Record, Table};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn table() -> Table {
+        let mut table = Table::new();
+        for i in 0..5 {
+            table.insert(
+                i,
+                Record::try_from_value(json!({"id": i, "name": format!("user{i}"), "tags": ["a"]}))
+                    .unwrap(),
+            );
+        }
+        table
+    }
+
+    #[test]
+    fn test_table_to_record_batches() {
+        let table = table();
+        let options = ArrowOptions {
+            batch_size: 2,
+            ..ArrowOptions::default()
+        };
+        let batches = table.to_record_batches(&options).unwrap();
+        assert_eq!(
+            batches
+                .iter()
+                .map(arrow_array::RecordBatch::num_rows)
+                .collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        let schema = batches[0].schema();
+        assert_eq!(
+            schema.field_with_name("id").unwrap().data_type(),
+            &DataType::Int64
+        );
+        assert!(matches!(
+            schema.field_with_name("tags").unwrap().data_type(),
+            DataType::List(_)
+        ));
+        let ids = batches[2].column(0).as_primitive::<Int64Type>();
+        assert_eq!(ids.value(0), 4);
+
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Utf8, false)]));
+        let options = ArrowOptions {
+            schema: Some(schema),
+            ..ArrowOptions::default()
+        };
+        assert!(table.to_record_batches(&options).is_err());
+    }
+
+    #[test]
+    fn test_write_parquet() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.parquet");
+        let table = table();
+        table
+            .write_parquet(
+                std::fs::File::create(&path).unwrap(),
+                &ArrowOptions::default(),
+            )
+            .unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(rows, 5);
+    }
+
+    #[test]
+    fn test_stream_to_parquet() {
+        let data = concat!(
+            "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n",
+            "{\"id\":1,\"v\":1.5}\n",
+            "{\"$deleted\":true,\"id\":1}\n",
+            "{\"id\":2,\"v\":2.5}\n",
+            "{\"id\":3}\n",
+            "{\"id\":4}\n",
+            "{\"id\":2,\"v\":3.5}\n",
+        );
+        let records: Vec<_> = StreamReader::new(data.as_bytes())
+            .filter_map(|entry| entry.unwrap().entry.record().cloned())
+            .collect();
+        let schema = Arc::new(infer_schema(&records).unwrap());
+
+        let batches = StreamReader::new(data.as_bytes())
+            .record_batches(Arc::clone(&schema), 2)
+            .unwrap();
+        let batches: Vec<_> = batches.map(Result::unwrap).collect();
+        let rows: Vec<_> = batches.iter().map(RecordBatch::num_rows).collect();
+        assert_eq!(rows, vec![2, 1]);
+        let ids = batches[0].column(0).as_primitive::<Int64Type>();
+        assert_eq!(ids.values(), &[2, 3]);
+        let values = batches[0].column(1).as_primitive::<Float64Type>();
+        assert!((values.value(0) - 3.5).abs() < f64::EPSILON);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.parquet");
+        StreamReader::new(data.as_bytes())
+            .record_batches(schema, 2)
+            .unwrap()
+            .write_parquet(std::fs::File::create(&path).unwrap())
+            .unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(rows, 3);
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite_tests {
+    use jsonlt::io::write_jsonlt;
+    use jsonlt::{Key, KeySpecifier, Record, SqliteOptions, Table};
+    use rusqlite::Connection;
+    use serde_json::json;
+
+    #[test]
+    fn test_sqlite_round_trip() {
+        let mut table = Table::new();
+        table.insert(
+            "alice",
+            Record::try_from_value(json!({"id": "alice", "age": 30})).unwrap(),
+        );
+        table.insert(
+            "bob",
+            Record::try_from_value(json!({"id": "bob", "tags": ["x"]})).unwrap(),
+        );
+
+        let conn = Connection::open_in_memory().unwrap();
+        let options = SqliteOptions {
+            columns: vec!["age".into()],
+            ..SqliteOptions::default()
+        };
+        table.to_sqlite(&conn, &options).unwrap();
+        table.to_sqlite(&conn, &options).unwrap();
+
+        let ages: Vec<Option<i64>> = conn
+            .prepare("SELECT age FROM records ORDER BY key")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(ages, vec![Some(30), None]);
+
+        let read = Table::from_sqlite(&conn, "SELECT record FROM records", &"id".into(), &options)
+            .unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(
+            read.get(&Key::from("alice")),
+            table.get(&Key::from("alice"))
+        );
+        assert_eq!(read.get(&Key::from("bob")), table.get(&Key::from("bob")));
+    }
+
+    #[test]
+    fn test_sqlite_tuple_keys() {
+        let key = KeySpecifier::Fields(vec!["org".into(), "n".into()]);
+        let tuple = Key::Tuple(vec![Key::from("acme"), Key::from(1)]);
+        let mut table = Table::new();
+        table.insert(
+            tuple.clone(),
+            Record::try_from_value(json!({"org": "acme", "n": 1})).unwrap(),
+        );
+
+        let conn = Connection::open_in_memory().unwrap();
+        table.to_sqlite(&conn, &SqliteOptions::default()).unwrap();
+        let stored: String = conn
+            .query_row("SELECT key FROM records", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, r#"["acme",1]"#);
+
+        let read = Table::from_sqlite(
+            &conn,
+            "SELECT record FROM records",
+            &key,
+            &SqliteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(read.get(&tuple), table.get(&tuple));
+        assert_eq!(read.len(), 1);
+    }
+
+    #[test]
+    fn test_sqlite_import_query() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE people (id INTEGER, name TEXT, score REAL, note TEXT);
+             INSERT INTO people VALUES (1, 'alice', 1.5, NULL), (2, 'bob', 2.0, 'hi');",
+        )
+        .unwrap();
+
+        let table = Table::from_sqlite(
+            &conn,
+            "SELECT id, upper(name) AS name, score, note FROM people",
+            &"id".into(),
+            &SqliteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            table.get(&Key::from(1)).unwrap().value(),
+            &json!({"id": 1, "name": "ALICE", "score": 1.5, "note": null})
+        );
+
+        let mut out = Vec::new();
+        write_jsonlt(&mut out, &table, &"id".into()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), 3);
+
+        let err = Table::from_sqlite(
+            &conn,
+            "SELECT name FROM people",
+            &"id".into(),
+            &SqliteOptions::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("row 1"), "{err}");
+
+        let err = Table::from_sqlite(
+            &conn,
+            "SELECT id, x'00' AS data FROM people",
+            &"id".into(),
+            &SqliteOptions::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("blob"), "{err}");
+    }
+}
+
+#[cfg(all(feature = "serde", feature = "compression"))]
+mod compression_tests {
+    use jsonlt::io::{Compression, Decoder, Encoder, FileTable, PollOutcome};
+    use jsonlt::io::{StreamReader, StreamWriter};
+    use jsonlt::{check_format, format, validate, Key, Record};
+    use serde_json::json;
+    use std::fs;
+    use std::io::{BufReader, Read, Write};
+
+    #[test]
+    fn test_compressed_file_table() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, magic) in [
+            ("users.jsonlt.gz", &[0x1f, 0x8b][..]),
+            ("users.jsonlt.zst", &[0x28, 0xb5, 0x2f, 0xfd][..]),
+        ] {
+            let path = dir.path().join(name);
+            let mut file = FileTable::create(&path, "id").unwrap();
+            assert!(file.compression().is_compressed());
+            file.put(&Record::try_from_value(json!({"id": "alice", "v": 1})).unwrap())
+                .unwrap();
+            file.put(&Record::try_from_value(json!({"id": "bob", "v": 1})).unwrap())
+                .unwrap();
+            file.put(&Record::try_from_value(json!({"id": "alice", "v": 2})).unwrap())
+                .unwrap();
+            assert!(file.delete("bob").unwrap());
+            assert!(fs::read(&path).unwrap().starts_with(magic));
+
+            let reopened = FileTable::open(&path).unwrap();
+            assert_eq!(reopened.table().len(), 1);
+            assert_eq!(file.history("alice").unwrap().len(), 2);
+
+            file.compact().unwrap();
+            assert!(fs::read(&path).unwrap().starts_with(magic));
+            let lines: Vec<_> = StreamReader::open(&path)
+                .unwrap()
+                .map(|entry| entry.unwrap().line)
+                .collect();
+            assert_eq!(lines, vec![2]);
+            assert_eq!(
+                file.table().get(&Key::from("alice")).unwrap().value(),
+                &json!({"id": "alice", "v": 2})
+            );
+        }
+    }
+
+    #[test]
+    fn test_compact_to_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.jsonlt");
+        let mut file = FileTable::create(&path, "id").unwrap();
+        file.put(&Record::try_from_value(json!({"id": 2})).unwrap())
+            .unwrap();
+        file.put(&Record::try_from_value(json!({"id": 1})).unwrap())
+            .unwrap();
+
+        let archive = dir.path().join("users.jsonlt.zst");
+        file.compact_to(&archive).unwrap();
+        assert_eq!(file.compression(), Compression::None);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+
+        let archived = FileTable::open(&archive).unwrap();
+        assert_eq!(archived.compression(), Compression::Zstd);
+        assert_eq!(archived.table().len(), 2);
+        assert!(archived.table().contains_key(&Key::from(1)));
+    }
+
+    #[test]
+    fn test_poll_compressed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.jsonlt.gz");
+        let mut writer = FileTable::create(&path, "id").unwrap();
+        let mut follower = FileTable::open(&path).unwrap();
+        assert_eq!(follower.poll().unwrap(), PollOutcome::Unchanged);
+
+        writer
+            .put(&Record::try_from_value(json!({"id": "alice"})).unwrap())
+            .unwrap();
+        assert_eq!(follower.poll().unwrap(), PollOutcome::Reloaded);
+        assert!(follower.table().contains_key(&Key::from("alice")));
+        assert_eq!(follower.poll().unwrap(), PollOutcome::Unchanged);
+    }
+
+    #[test]
+    fn test_explicit_compression() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.data");
+        let mut writer =
+            StreamWriter::create_with_compression(&path, "id", Compression::Gzip).unwrap();
+        writer
+            .write_record(&Record::try_from_value(json!({"id": 1})).unwrap())
+            .unwrap();
+        writer.finish().unwrap();
+        assert!(fs::read(&path).unwrap().starts_with(&[0x1f, 0x8b]));
+
+        let decoder = Decoder::new(fs::File::open(&path).unwrap(), Compression::Gzip).unwrap();
+        let mut reader = StreamReader::with_key(BufReader::new(decoder), "id");
+        assert_eq!(reader.next().unwrap().unwrap().key(), &Key::from(1));
+        assert!(reader.next().is_none());
+
+        let mut plain = StreamReader::open(&path).unwrap();
+        assert!(plain.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_format_and_validate_compressed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["users.jsonlt.gz", "users.jsonlt.zst"] {
+            let path = dir.path().join(name);
+            let mut writer = Encoder::new(
+                fs::File::create(&path).unwrap(),
+                Compression::from_path(&path),
+            )
+            .unwrap();
+            writer
+                .write_all(b"{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n{ \"name\": \"a\", \"id\": 1 }\n")
+                .unwrap();
+            writer.finish().unwrap();
+
+            let report = validate(&path).unwrap();
+            assert!(report.is_valid(), "{name}: {:?}", report.issues);
+            assert_eq!(check_format(&path).unwrap().changed, [2]);
+
+            assert_eq!(format(&path).unwrap().changed, [2]);
+            assert!(check_format(&path).unwrap().is_canonical());
+            let mut contents = String::new();
+            Decoder::new(
+                fs::File::open(&path).unwrap(),
+                Compression::from_path(&path),
+            )
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+            assert_eq!(
+                contents,
+                "{\"$jsonlt\":{\"key\":\"id\",\"version\":1}}\n{\"id\":1,\"name\":\"a\"}\n"
+            );
+        }
+    }
+}
+
+#[cfg(all(feature = "serde", feature = "async", feature = "compression"))]
+mod async_compression_tests {
+    use jsonlt::io::{create, open, read_table, write_table, AsyncStreamReader, AsyncStreamWriter};
+    use jsonlt::io::{Compression, Decoder, StreamReader, TableReader, TableWriter};
+    use jsonlt::{Header, Key, Record, Table};
+    use std::fs::File;
+    use std::io::BufReader;
+
+    #[tokio::test]
+    async fn test_async_compressed_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["users.jsonlt.gz", "users.jsonlt.zst"] {
+            let path = dir.path().join(name);
+            let mut writer = create(&path).await.unwrap();
+            writer.write_line("{\"id\":1}").await.unwrap();
+            writer.write_line("{\"id\":2}").await.unwrap();
+            writer.shutdown().await.unwrap();
+
+            let decoder = Decoder::new(File::open(&path).unwrap(), Compression::from_path(&path));
+            let reader = StreamReader::with_key(BufReader::new(decoder.unwrap()), "id");
+            assert_eq!(reader.count(), 2);
+
+            let mut reader = open(&path).await.unwrap();
+            assert_eq!(reader.read_line().await.unwrap().unwrap(), "{\"id\":1}");
+            assert_eq!(reader.read_line().await.unwrap().unwrap(), "{\"id\":2}");
+            assert!(reader.read_line().await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_multi_member_gzip() {
+        let mut buffer = Vec::new();
+        for line in ["{\"id\":1}", "{\"id\":2}"] {
+            let mut writer = TableWriter::with_compression(&mut buffer, Compression::Gzip).unwrap();
+            writer.write_line(line).await.unwrap();
+            writer.shutdown().await.unwrap();
+        }
+        let mut reader =
+            TableReader::with_compression(buffer.as_slice(), Compression::Gzip).unwrap();
+        assert_eq!(reader.read_line().await.unwrap().unwrap(), "{\"id\":1}");
+        assert_eq!(reader.read_line().await.unwrap().unwrap(), "{\"id\":2}");
+        assert!(reader.read_line().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_async_compressed_streams_and_tables() {
+        let mut buffer = Vec::new();
+        let mut writer =
+            AsyncStreamWriter::with_compression(&mut buffer, "id", Compression::Zstd).unwrap();
+        writer
+            .write_header(&Header::new("id".into()))
+            .await
+            .unwrap();
+        writer
+            .write_record(&Record::try_from(r#"{"id":1}"#).unwrap())
+            .await
+            .unwrap();
+        writer.write_tombstone(&Key::from(1)).await.unwrap();
+        writer.shutdown().await.unwrap();
+
+        let mut reader =
+            AsyncStreamReader::with_compression(buffer.as_slice(), Compression::Zstd).unwrap();
+        assert_eq!(reader.read_entry().await.unwrap().unwrap().line, 2);
+        assert_eq!(reader.header().unwrap().key, Some("id".into()));
+        assert!(reader
+            .read_entry()
+            .await
+            .unwrap()
+            .unwrap()
+            .entry
+            .is_tombstone());
+        assert!(reader.read_entry().await.unwrap().is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.jsonlt.gz");
+        let mut table = Table::new();
+        table.insert(1, Record::try_from(r#"{"id":1,"name":"alice"}"#).unwrap());
+        write_table(&path, &table, "id").await.unwrap();
+        let decoder = Decoder::new(File::open(&path).unwrap(), Compression::Gzip).unwrap();
+        assert_eq!(StreamReader::new(BufReader::new(decoder)).count(), 1);
+        assert_eq!(
+            read_table(&path).await.unwrap().get(&Key::from(1)),
+            table.get(&Key::from(1))
+        );
+    }
+}
+
+#[cfg(feature = "cli")]
+mod cli_tests {
+    use std::fs;
+    use std::process::{Command, Output};
+
+    fn jsonlt(args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_jsonlt"))
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    fn stdout(output: &Output) -> String {
+        String::from_utf8(output.stdout.clone()).unwrap()
+    }
+
+    #[test]
+    fn test_cli_edits_and_queries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.jsonlt");
+        let file = path.to_str().unwrap();
+
+        let put = jsonlt(&[
+            "--key",
+            "id",
+            "put",
+            file,
+            r#"{"id":"alice","role":"admin"}"#,
+            r#"{"id":"bob","role":"user"}"#,
+        ]);
+        assert!(put.status.success(), "{put:?}");
+        assert!(jsonlt(&["delete", file, "bob"]).status.success());
+        assert_eq!(jsonlt(&["delete", file, "bob"]).status.code(), Some(1));
+
+        assert_eq!(stdout(&jsonlt(&["count", file])), "1\n");
+        assert_eq!(
+            stdout(&jsonlt(&["--format", "json", "get", file, "alice"])),
+            "{\"id\":\"alice\",\"role\":\"admin\"}\n"
+        );
+        assert_eq!(jsonlt(&["get", file, "bob"]).status.code(), Some(2));
+        assert_eq!(
+            stdout(&jsonlt(&["list", file])),
+            "alice  {\"id\":\"alice\",\"role\":\"admin\"}\n"
+        );
+        assert_eq!(
+            stdout(&jsonlt(&["history", file, "bob"])),
+            "     3  {\"id\":\"bob\",\"role\":\"user\"}\n     4  (deleted)\n"
+        );
+
+        assert!(jsonlt(&["compact", file]).status.success());
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+        assert!(jsonlt(&["validate", file]).status.success());
+    }
+
+    #[test]
+    fn test_cli_validate_and_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("old.jsonlt");
+        let new = dir.path().join("new.jsonlt");
+        let bad = dir.path().join("bad.jsonlt");
+        fs::write(&old, "{\"id\":1,\"n\":1}\n{\"id\":2}\n").unwrap();
+        fs::write(&new, "{\"id\":1,\"n\":2}\n{\"id\":3}\n").unwrap();
+        fs::write(&bad, "{\"id\":1}\nnot json\n").unwrap();
+        let [old, new, bad] = [&old, &new, &bad].map(|path| path.to_str().unwrap());
+
+        let diff = jsonlt(&["--key", "id", "diff", old, new]);
+        assert_eq!(diff.status.code(), Some(1));
+        assert_eq!(stdout(&diff), "- 2\n+ 3\n~ 1\n    /n: 1 -> 2\n");
+        assert!(jsonlt(&["--key", "id", "diff", old, old]).status.success());
+
+        fs::write(bad, "{ \"id\": 4 }\n").unwrap();
+        assert_eq!(jsonlt(&["format", "--check", bad]).status.code(), Some(1));
+        assert!(jsonlt(&["format", bad]).status.success());
+        assert!(jsonlt(&["format", "--check", bad]).status.success());
+        assert_eq!(fs::read_to_string(bad).unwrap(), "{\"id\":4}\n");
+        fs::write(bad, "{\"id\":1}\nnot json\n").unwrap();
+
+        let validate = jsonlt(&["--key", "id", "validate", old, bad]);
+        assert_eq!(validate.status.code(), Some(1));
+        let report = stdout(&validate);
+        assert!(report.contains("old.jsonlt: ok"), "{report}");
+        assert!(
+            report.contains("bad.jsonlt:2:2: error: expected ident"),
+            "{report}"
+        );
+    }
+
+    #[test]
+    fn test_cli_validate_references() {
+        let dir = tempfile::tempdir().unwrap();
+        let users = dir.path().join("users.jsonlt");
+        let orders = dir.path().join("orders.jsonlt");
+        fs::write(&users, "{\"id\":\"alice\"}\n").unwrap();
+        fs::write(&orders, "{\"id\":1,\"user_id\":\"alice\"}\n").unwrap();
+        let [users, orders] = [&users, &orders].map(|path| path.to_str().unwrap());
+        let args = |extra: &[&'static str]| {
+            let mut args = vec!["--key", "id", "validate", "--reference"];
+            args.extend_from_slice(extra);
+            args
+        };
+
+        let validate = jsonlt(&[args(&["orders.user_id -> users"]), vec![users, orders]].concat());
+        assert!(validate.status.success(), "{validate:?}");
+
+        fs::write(orders, "{\"id\":1,\"user_id\":\"bob\"}\n").unwrap();
+        let validate = jsonlt(&[args(&["orders.user_id -> users"]), vec![users, orders]].concat());
+        assert_eq!(validate.status.code(), Some(1));
+        let report = stdout(&validate);
+        assert!(
+            report.contains(
+                "orders.jsonlt: error: orders record 1: user_id references missing users record \"bob\""
+            ),
+            "{report}"
+        );
+
+        let validate = jsonlt(&[args(&["orders.user_id"]), vec![users, orders]].concat());
+        assert_eq!(validate.status.code(), Some(2));
+    }
 }
@@ -137,7 +137,7 @@ mod serde_tests {
             "count": 42
         });
 
-        let record = Record::from_value(value.clone());
+        let record = Record::try_from_value(value.clone()).unwrap();
         assert_eq!(record.value(), &value);
     }
 
@@ -147,11 +147,11 @@ mod serde_tests {
 
         table.insert(
             "item-1".to_string(),
-            Record::from_value(json!({"name": "First"})),
+            Record::try_from_value(json!({"name": "First"})).unwrap(),
         );
         table.insert(
             "item-2".to_string(),
-            Record::from_value(json!({"name": "Second"})),
+            Record::try_from_value(json!({"name": "Second"})).unwrap(),
         );
 
         assert_eq!(table.len(), 2);